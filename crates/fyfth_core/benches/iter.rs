@@ -0,0 +1,84 @@
+use bevy::prelude::*;
+use criterion::{criterion_group, criterion_main, Criterion};
+use fyfth_core::interpreter::FyfthInterpreter;
+
+/// Creates an interpreter that has already run `setup` in a world with `entity_count` named
+/// entities.
+fn setup(setup: &str, entity_count: usize) -> (World, FyfthInterpreter) {
+    let mut world = World::new();
+    for i in 0..entity_count {
+        world.spawn(Name::new(format!("entity {i}")));
+    }
+
+    let mut interpreter = FyfthInterpreter::new();
+    interpreter.parse_code(setup).unwrap();
+    interpreter.run(&mut world).1.unwrap();
+
+    (world, interpreter)
+}
+
+fn bench_code(
+    c: &mut Criterion,
+    name: &str,
+    (mut world, mut interpreter): (World, FyfthInterpreter),
+    code: &str,
+) {
+    c.bench_function(name, |b| {
+        b.iter(|| {
+            interpreter.parse_code(code).unwrap();
+            interpreter.run(&mut world).1.unwrap();
+        })
+    });
+}
+
+fn iter_benchmarks(c: &mut Criterion) {
+    // 1000 lists of 10 numbers each
+    let nested = "[] 1000 [10 enum to_iter append] times nested store";
+    let flat = "10000 enum to_iter flat store";
+
+    bench_code(
+        c,
+        "dup nested iter",
+        setup(nested, 0),
+        "*nested dup pop pop",
+    );
+    bench_code(
+        c,
+        "append to nested iter",
+        setup(nested, 0),
+        "*nested [] append pop",
+    );
+    bench_code(
+        c,
+        "broadcast get over nested iter",
+        setup(nested, 0),
+        "*nested 0 get pop",
+    );
+    bench_code(
+        c,
+        "broadcast set over nested iter",
+        setup(nested, 0),
+        "*nested 0 5 set pop",
+    );
+    bench_code(
+        c,
+        "broadcast add over flat iter",
+        setup(flat, 0),
+        "*flat 1 add pop",
+    );
+    bench_code(
+        c,
+        "broadcast name over entities",
+        setup("", 10_000),
+        "entities name pop",
+    );
+    bench_code(
+        c,
+        "push list literal in loop",
+        setup("", 0),
+        "1000 [[0 1 2 3 4 5 6 7 8 9] pop] times",
+    );
+}
+
+criterion_group!(benches, iter_benchmarks);
+criterion_main!(benches);
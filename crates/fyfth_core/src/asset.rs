@@ -0,0 +1,222 @@
+use bevy::{
+    asset::{
+        io::Reader, AssetLoader, AssetPath, AsyncReadExt, LoadContext, LoadState,
+        RecursiveDependencyLoadState,
+    },
+    prelude::*,
+    utils::HashSet,
+};
+
+use crate::{
+    error::{FyfthError, FyfthErrorKind},
+    interpreter::{find_imports, format_diagnostics, FyfthInterpreter},
+    FyfthPreludeErrorBehavior,
+};
+
+/// The source code of a fyfth module, loaded from a `.fy` file.
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct FyfthScript {
+    pub source: String,
+    /// The modules this script imports, which are loaded along with it.
+    ///
+    /// Only imports whose path is written directly in front of `import` or `import_as` can be
+    /// found without running the script.
+    #[dependency]
+    pub imports: Vec<Handle<FyfthScript>>,
+}
+
+#[derive(Debug, Default)]
+pub struct FyfthScriptLoader;
+
+impl AssetLoader for FyfthScriptLoader {
+    type Asset = FyfthScript;
+    type Settings = ();
+    type Error = FyfthError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<FyfthScript, FyfthError> {
+        let mut source = String::new();
+        reader.read_to_string(&mut source).await.map_err(|err| {
+            FyfthError::new(FyfthErrorKind::Io {
+                path: load_context.path().display().to_string(),
+                message: err.to_string(),
+            })
+        })?;
+
+        let mut imports = vec![];
+        for path in find_imports(&source) {
+            // imports are relative to the directory of the importing script
+            if let Ok(path) = load_context.asset_path().resolve_embed(&path) {
+                imports.push(load_context.load(path));
+            }
+        }
+
+        Ok(FyfthScript { source, imports })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["fy"]
+    }
+}
+
+/// The preludes of the [`FyfthPlugin`](crate::FyfthPlugin) and the state of loading them.
+#[derive(Debug, Resource)]
+pub(crate) struct FyfthPreludes {
+    paths: Vec<String>,
+    error_behavior: FyfthPreludeErrorBehavior,
+    handles: Vec<Handle<FyfthScript>>,
+    /// Whether all preludes have been loaded and run.
+    loaded: bool,
+    /// Scripts that have changed and are reloaded once all of their imports are loaded.
+    modified: Vec<AssetId<FyfthScript>>,
+}
+
+impl FyfthPreludes {
+    pub(crate) fn new(paths: Vec<String>, error_behavior: FyfthPreludeErrorBehavior) -> Self {
+        Self {
+            paths,
+            error_behavior,
+            handles: vec![],
+            loaded: false,
+            modified: vec![],
+        }
+    }
+}
+
+pub(crate) fn load_preludes(asset_server: Res<AssetServer>, mut preludes: ResMut<FyfthPreludes>) {
+    preludes.handles = preludes
+        .paths
+        .iter()
+        .map(|path| asset_server.load(path))
+        .collect();
+}
+
+/// Runs the preludes once they are loaded and reloads scripts whenever they change.
+pub(crate) fn update_scripts(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<FyfthScript>>,
+    asset_server: Res<AssetServer>,
+    scripts: Res<Assets<FyfthScript>>,
+    preludes: ResMut<FyfthPreludes>,
+) {
+    let preludes = preludes.into_inner();
+
+    for event in events.read() {
+        if let AssetEvent::Modified { id } = *event {
+            if preludes.loaded {
+                preludes.modified.push(id);
+            }
+        }
+    }
+
+    let is_loading = |id: AssetId<FyfthScript>| {
+        matches!(
+            asset_server.get_recursive_dependency_load_state(id),
+            Some(RecursiveDependencyLoadState::NotLoaded | RecursiveDependencyLoadState::Loading)
+        )
+    };
+
+    let mut to_run = vec![];
+    if !preludes.loaded
+        && !preludes
+            .handles
+            .iter()
+            .any(|handle| is_loading(handle.id()))
+    {
+        preludes.loaded = true;
+        for (path, handle) in preludes.paths.iter().zip(&preludes.handles) {
+            let result = match asset_server.load_state(handle.id()) {
+                LoadState::Failed(err) => Err(FyfthError::new(FyfthErrorKind::Io {
+                    path: path.clone(),
+                    message: err.to_string(),
+                })),
+                _ => Ok(AssetPath::parse(path).path().display().to_string()),
+            };
+            to_run.push((path.clone(), result));
+        }
+    }
+
+    let mut to_reload = vec![];
+    preludes.modified.retain(|&id| {
+        if is_loading(id) {
+            return true;
+        }
+        to_reload.extend(
+            asset_server
+                .get_path(id)
+                .map(|path| path.path().display().to_string()),
+        );
+        false
+    });
+
+    if to_run.is_empty() && to_reload.is_empty() {
+        return;
+    }
+
+    // the interpreter reads all modules from the loaded scripts instead of the file system
+    let sources: Vec<_> = scripts
+        .iter()
+        .filter_map(|(id, script)| {
+            let path = asset_server.get_path(id)?;
+            Some((path.path().display().to_string(), script.source.clone()))
+        })
+        .collect();
+
+    let error_behavior = preludes.error_behavior;
+    commands.add(move |world: &mut World| {
+        world.resource_scope(|world, mut interpreter: Mut<FyfthInterpreter>| {
+            // imports that were not found by the loader would be read relative to the working
+            // directory rather than the asset folder
+            interpreter.set_module_sources_only(true);
+
+            let mut changed = HashSet::new();
+            for (path, source) in sources {
+                if interpreter.add_module_source(&path, &source) {
+                    changed.insert(path);
+                }
+            }
+
+            for (path, module) in to_run {
+                let res = module
+                    .map_err(|err| vec![err])
+                    .and_then(|module| interpreter.load_prelude(&module, world));
+                let Err(errors) = res else {
+                    continue;
+                };
+
+                match error_behavior {
+                    FyfthPreludeErrorBehavior::Panic => panic!(
+                        "Failed to load the prelude `{path}`:\n{}",
+                        format_diagnostics(&errors)
+                    ),
+                    FyfthPreludeErrorBehavior::Log => {
+                        for err in errors {
+                            error!("Failed to load the prelude `{path}`: {err}");
+                        }
+                    }
+                }
+            }
+
+            for path in to_reload {
+                // modules are only reloaded if their source actually changed
+                if !changed.contains(&path) {
+                    continue;
+                }
+
+                match interpreter.reload_module(&path, world) {
+                    Ok(true) => info!("Reloaded `{path}`"),
+                    Ok(false) => {}
+                    Err(errors) => {
+                        for err in errors {
+                            error!("Failed to reload `{path}`: {err}");
+                        }
+                    }
+                }
+            }
+        });
+    });
+}
@@ -0,0 +1,209 @@
+use crate::{
+    error::FyfthError,
+    interpreter::{FyfthMacro, FyfthVariant, QueueEntry},
+    language::FyfthLanguageExtension,
+    lexer::FyfthSpan,
+};
+
+/// Values compiled for running them: branches are turned into jumps and macro definitions are
+/// collected up front, so running code never needs to copy or search it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct FyfthCode {
+    pub(crate) ops: Vec<FyfthOp>,
+    /// The source location of every op.
+    pub(crate) spans: Vec<Option<FyfthSpan>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum FyfthOp {
+    /// Runs a value, or pushes it onto the stack if it cannot be run.
+    Value(FyfthVariant),
+    DefineMacro(String, FyfthMacro),
+    /// Continues with the op at the given index.
+    Jump(usize),
+    /// Pops a bool off the stack and continues with the op at `target` if it is `false`.
+    JumpUnless {
+        target: usize,
+        /// The `if` or `while` this jump was compiled from.
+        source: FyfthVariant,
+    },
+}
+
+impl FyfthCode {
+    /// Compiles parsed values, failing on unbalanced `if`/`else`/`then`s and nameless macros.
+    pub(crate) fn compile(
+        entries: impl IntoIterator<Item = QueueEntry>,
+    ) -> Result<Self, FyfthError> {
+        let mut entries = entries.into_iter();
+        let mut code = Self::default();
+        // the jumps of all `if`s and `else`s whose branch has not ended yet, innermost last
+        let mut branches: Vec<usize> = vec![];
+
+        while let Some((val, span)) = entries.next() {
+            let error = |message: &str, keyword: &str| {
+                let err = FyfthError::syntax(message).with_keyword(keyword);
+                match &span {
+                    Some(span) => err.with_span(span),
+                    None => err,
+                }
+            };
+
+            match val {
+                FyfthVariant::Macro => {
+                    let Some((FyfthVariant::Literal(name), _)) = entries.next() else {
+                        return Err(error(
+                            "`macro` needs to be followed by a name for the macro",
+                            "macro",
+                        ));
+                    };
+
+                    // the body ends at the `;` matching this `macro`
+                    let mut depth = 1;
+                    let body: Vec<_> = entries
+                        .by_ref()
+                        .take_while(|(val, _)| {
+                            match val {
+                                FyfthVariant::Macro => depth += 1,
+                                FyfthVariant::LineEnd => depth -= 1,
+                                _ => {}
+                            }
+                            depth > 0
+                        })
+                        .collect();
+
+                    code.push(FyfthOp::DefineMacro(name, FyfthMacro::new(body)?), span);
+                }
+                FyfthVariant::If => {
+                    branches.push(code.ops.len());
+                    code.push(
+                        FyfthOp::JumpUnless {
+                            target: 0,
+                            source: FyfthVariant::If,
+                        },
+                        span,
+                    );
+                }
+                FyfthVariant::Else => {
+                    let Some(branch) = branches.pop() else {
+                        return Err(error("`else` needs to follow an `if`", "else"));
+                    };
+                    if matches!(code.ops[branch], FyfthOp::Jump(_)) {
+                        return Err(error("an `if` can only have one `else`", "else"));
+                    }
+
+                    // the end of the taken branch jumps over the other one
+                    branches.push(code.ops.len());
+                    code.push(FyfthOp::Jump(0), span);
+                    code.set_jump_target(branch, code.ops.len());
+                }
+                FyfthVariant::Then => {
+                    let Some(branch) = branches.pop() else {
+                        return Err(error("`then` needs to follow an `if`", "then"));
+                    };
+                    code.set_jump_target(branch, code.ops.len());
+                }
+                FyfthVariant::LineEnd => {}
+                val => code.push(FyfthOp::Value(val), span),
+            }
+        }
+
+        if let Some(&branch) = branches.last() {
+            let keyword = match code.ops[branch] {
+                FyfthOp::Jump(_) => "else",
+                _ => "if",
+            };
+            let err =
+                FyfthError::syntax("missing `then` at the end of a branch").with_keyword(keyword);
+            return Err(match &code.spans[branch] {
+                Some(span) => err.with_span(span),
+                None => err,
+            });
+        }
+
+        Ok(code)
+    }
+
+    /// Compiles a list of values that are run as code, e.g. by `queue` or `times`, blaming `span`
+    /// for all of them.
+    pub(crate) fn compile_list(
+        vals: &[FyfthVariant],
+        span: Option<&FyfthSpan>,
+    ) -> Result<Self, FyfthError> {
+        Self::compile(vals.iter().map(|val| (val.clone(), span.cloned())))
+    }
+
+    /// Compiles `cond.. if body.. <jump to the start> then` for the `while` keyword.
+    pub(crate) fn compile_while(
+        cond: &[FyfthVariant],
+        body: &[FyfthVariant],
+        span: Option<&FyfthSpan>,
+    ) -> Result<Self, FyfthError> {
+        let mut code = Self::compile_list(cond, span)?;
+        let branch = code.ops.len();
+        code.push(
+            FyfthOp::JumpUnless {
+                target: 0,
+                source: FyfthVariant::FnWhile,
+            },
+            span.cloned(),
+        );
+        code.append(Self::compile_list(body, span)?);
+        code.push(FyfthOp::Jump(0), span.cloned());
+        code.set_jump_target(branch, code.ops.len());
+
+        Ok(code)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    fn push(&mut self, op: FyfthOp, span: Option<FyfthSpan>) {
+        self.ops.push(op);
+        self.spans.push(span);
+    }
+
+    /// Appends `other`, moving its jumps along with it.
+    fn append(&mut self, other: Self) {
+        let offset = self.ops.len();
+        self.ops.extend(other.ops.into_iter().map(|op| match op {
+            FyfthOp::Jump(target) => FyfthOp::Jump(target + offset),
+            FyfthOp::JumpUnless { target, source } => FyfthOp::JumpUnless {
+                target: target + offset,
+                source,
+            },
+            op => op,
+        }));
+        self.spans.extend(other.spans);
+    }
+
+    fn set_jump_target(&mut self, index: usize, new_target: usize) {
+        match &mut self.ops[index] {
+            FyfthOp::Jump(target) | FyfthOp::JumpUnless { target, .. } => *target = new_target,
+            _ => unreachable!("only jumps have a target"),
+        }
+    }
+}
+
+impl FyfthOp {
+    /// The value this op was compiled from, which is shown in the queue and recorded by traces.
+    ///
+    /// Plain jumps only exist in compiled code and have no value.
+    pub(crate) fn value(&self) -> Option<&FyfthVariant> {
+        match self {
+            FyfthOp::Value(val) | FyfthOp::JumpUnless { source: val, .. } => Some(val),
+            FyfthOp::DefineMacro(..) => Some(&FyfthVariant::Macro),
+            FyfthOp::Jump(_) => None,
+        }
+    }
+
+    /// The keyword or macro name of this op if it runs a command, which is also the name that a
+    /// breakpoint needs to be set on to pause before it runs.
+    pub(crate) fn command_name<'a>(&'a self, lang: &'a FyfthLanguageExtension) -> Option<&'a str> {
+        self.value()?.command_name(lang)
+    }
+}
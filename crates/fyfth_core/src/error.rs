@@ -0,0 +1,251 @@
+use std::fmt;
+
+use bevy::prelude::*;
+
+use crate::{interpreter::FyfthVariant, lexer::FyfthSpan};
+
+/// An error raised while lexing, parsing or running fyfth code.
+///
+/// Besides the [`FyfthErrorKind`] it carries the keyword of the command that failed (if any),
+/// the location of that command in the source code and a snapshot of the data stack at the time
+/// the command was called, so tooling can react to errors without having to parse the error
+/// message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FyfthError {
+    pub kind: FyfthErrorKind,
+    pub keyword: Option<String>,
+    pub span: Option<Box<FyfthSpan>>,
+    pub stack: Vec<FyfthVariant>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FyfthErrorKind {
+    /// A command needed more values than there were on the stack.
+    StackUnderflow { expected: usize, found: usize },
+    /// A command was called with values of the wrong types.
+    TypeMismatch {
+        expected: String,
+        found: Vec<String>,
+    },
+    /// No variable or macro of this name exists.
+    UnknownVariable(String),
+    /// No command of this name exists in the language.
+    UnknownKeyword(String),
+    /// A value has no field or component of this name.
+    UnknownField { type_name: String, field: String },
+    /// An index was out of bounds for an iterator.
+    IndexOutOfRange { index: i64, len: usize },
+    /// Looking up or accessing a bevy component failed.
+    ///
+    /// Boxed to keep the error small, since every command returns it.
+    Component(Box<FyfthComponentError>),
+    /// The interpreter executed more than the given number of steps.
+    IterationLimit(usize),
+    /// The code could not be lexed or parsed.
+    Syntax(String),
+    /// A file could not be read.
+    Io { path: String, message: String },
+    /// A module could not be imported because it failed to parse.
+    Import {
+        path: String,
+        errors: Vec<FyfthError>,
+    },
+    /// A language extension defines a keyword that is already in use.
+    KeywordCollision(String),
+    /// A language extension defines a prefix that is already in use.
+    PrefixCollision(char),
+    /// Any other error raised by a command.
+    Runtime(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FyfthComponentError {
+    NoMatchingComponent(String),
+    MultipleMatchingComponents {
+        query: String,
+        candidates: Vec<String>,
+    },
+    MissingComponent {
+        entity: Entity,
+        component: String,
+    },
+    NoSuchEntity(Entity),
+    NotAStruct(String),
+    UnsupportedFieldType {
+        component: String,
+        field: String,
+    },
+    /// A value cannot be stored in a field, e.g. because its type differs or it does not fit.
+    SetFieldFailed {
+        component: String,
+        field: String,
+        field_type: String,
+        value: String,
+    },
+}
+
+impl FyfthError {
+    pub fn new(kind: FyfthErrorKind) -> Self {
+        Self {
+            kind,
+            keyword: None,
+            span: None,
+            stack: vec![],
+        }
+    }
+
+    pub fn type_mismatch(expected: &str, found: &[FyfthVariant]) -> Self {
+        Self::new(FyfthErrorKind::TypeMismatch {
+            expected: expected.to_string(),
+            found: found.iter().map(FyfthVariant::type_name).collect(),
+        })
+    }
+
+    pub fn stack_underflow(expected: usize, found: usize) -> Self {
+        Self::new(FyfthErrorKind::StackUnderflow { expected, found })
+    }
+
+    pub fn syntax(message: impl Into<String>) -> Self {
+        Self::new(FyfthErrorKind::Syntax(message.into()))
+    }
+
+    pub fn runtime(message: impl Into<String>) -> Self {
+        Self::new(FyfthErrorKind::Runtime(message.into()))
+    }
+
+    /// Sets the keyword of the failing command unless one has already been set.
+    pub fn with_keyword(mut self, keyword: &str) -> Self {
+        self.keyword.get_or_insert_with(|| keyword.to_string());
+        self
+    }
+
+    /// Sets the source location of the failing command unless one has already been set.
+    pub fn with_span(mut self, span: &FyfthSpan) -> Self {
+        self.span.get_or_insert_with(|| Box::new(span.clone()));
+        self
+    }
+
+    pub fn with_stack(mut self, stack: &[FyfthVariant]) -> Self {
+        self.stack = stack.to_vec();
+        self
+    }
+}
+
+impl From<FyfthErrorKind> for FyfthError {
+    fn from(kind: FyfthErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl From<FyfthComponentError> for FyfthError {
+    fn from(error: FyfthComponentError) -> Self {
+        Self::new(FyfthErrorKind::Component(Box::new(error)))
+    }
+}
+
+impl fmt::Display for FyfthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(keyword) = &self.keyword {
+            write!(f, "`{keyword}`: ")?;
+        }
+        write!(f, "{}", self.kind)?;
+        if let Some(span) = &self.span {
+            writeln!(f)?;
+            span.write_excerpt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for FyfthError {}
+
+impl fmt::Display for FyfthErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FyfthErrorKind::StackUnderflow { expected, found } => write!(
+                f,
+                "expected {expected} items on the stack but found only {found}"
+            ),
+            FyfthErrorKind::TypeMismatch { expected, found } => write!(
+                f,
+                "expected types `{expected}` but got `{}`",
+                found.join(" ")
+            ),
+            FyfthErrorKind::UnknownVariable(name) => {
+                write!(f, "no variable of the name `{name}` found")
+            }
+            FyfthErrorKind::UnknownKeyword(keyword) => {
+                write!(f, "no command of the name `{keyword}` exists")
+            }
+            FyfthErrorKind::UnknownField { type_name, field } => {
+                write!(f, "{type_name} has no `{field}` component")
+            }
+            FyfthErrorKind::IndexOutOfRange { index, len } => write!(
+                f,
+                "index `{index}` out of range for an iterator of length {len}"
+            ),
+            FyfthErrorKind::Component(error) => write!(f, "{error}"),
+            FyfthErrorKind::IterationLimit(limit) => {
+                write!(f, "reached iteration limit of {limit}")
+            }
+            FyfthErrorKind::Syntax(message) => write!(f, "syntax error: {message}"),
+            FyfthErrorKind::Io { path, message } => {
+                write!(f, "failed to read `{path}`: {message}")
+            }
+            FyfthErrorKind::Import { path, errors } => {
+                write!(f, "failed to import `{path}`:")?;
+                for error in errors {
+                    write!(f, "\n{error}")?;
+                }
+                Ok(())
+            }
+            FyfthErrorKind::KeywordCollision(keyword) => {
+                write!(f, "keyword `{keyword}` is already in use")
+            }
+            FyfthErrorKind::PrefixCollision(prefix) => {
+                write!(f, "prefix `{prefix}` is already in use")
+            }
+            FyfthErrorKind::Runtime(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl fmt::Display for FyfthComponentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FyfthComponentError::NoMatchingComponent(query) => write!(
+                f,
+                "no component type path matches '{query}'. Make sure the component is registered using `app.fyfth_register_bevy_component::<T>()`."
+            ),
+            FyfthComponentError::MultipleMatchingComponents { query, candidates } => {
+                write!(f, "{} component type paths match '{query}':", candidates.len())?;
+                for candidate in candidates {
+                    write!(f, "\n    {candidate}")?;
+                }
+                Ok(())
+            }
+            FyfthComponentError::MissingComponent { entity, component } => {
+                write!(f, "entity ({entity}) does not contain component `{component}`")
+            }
+            FyfthComponentError::NoSuchEntity(entity) => {
+                write!(f, "entity ({entity}) no longer exists")
+            }
+            FyfthComponentError::NotAStruct(component) => {
+                write!(f, "component `{component}` is not a struct")
+            }
+            FyfthComponentError::UnsupportedFieldType { component, field } => write!(
+                f,
+                "field `{field}` of component `{component}` has an unsupported type"
+            ),
+            FyfthComponentError::SetFieldFailed {
+                component,
+                field,
+                field_type,
+                value,
+            } => write!(
+                f,
+                "cannot set field `{field}` of type `{field_type}` of component `{component}` to {value}"
+            ),
+        }
+    }
+}
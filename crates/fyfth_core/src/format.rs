@@ -0,0 +1,165 @@
+use std::fmt::Write;
+
+use bevy::prelude::*;
+
+use crate::{error::FyfthError, interpreter::FyfthVariant, language::FyfthLanguageExtension};
+
+/// A template of the `format` command, e.g. `"{} is at {:.2}"`.
+///
+/// `{}` is replaced by a value in its printed form, except that literals are inserted without
+/// quotes. `{:.N}` prints nums, and the nums in vectors, quats, iters and maps, with `N` decimal
+/// places. `{{` and `}}` are a literal `{` and `}`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FyfthTemplate {
+    parts: Vec<FyfthTemplatePart>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FyfthTemplatePart {
+    Text(String),
+    Value { precision: Option<usize> },
+}
+
+impl FyfthTemplate {
+    pub(crate) fn parse(template: &str) -> Result<Self, FyfthError> {
+        let mut parts = vec![];
+        let mut text = String::new();
+
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '{' => {
+                    let Some((spec, rest)) = chars.as_str().split_once('}') else {
+                        return Err(FyfthError::runtime(format!(
+                            "unclosed `{{` in the format template \"{template}\""
+                        )));
+                    };
+                    let precision = match spec {
+                        "" => None,
+                        _ => match spec.strip_prefix(":.").map(str::parse) {
+                            Some(Ok(precision)) => Some(precision),
+                            _ => {
+                                return Err(FyfthError::runtime(format!(
+                                    "invalid placeholder `{{{spec}}}` in the format template \
+                                     \"{template}\", expected `{{}}` or `{{:.N}}`"
+                                )))
+                            }
+                        },
+                    };
+                    chars = rest.chars();
+
+                    if !text.is_empty() {
+                        parts.push(FyfthTemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(FyfthTemplatePart::Value { precision });
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '}' => {
+                    return Err(FyfthError::runtime(format!(
+                        "unmatched `}}` in the format template \"{template}\", use `}}}}` for a \
+                         literal `}}`"
+                    )))
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(FyfthTemplatePart::Text(text));
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// The number of values the template needs.
+    pub(crate) fn placeholder_count(&self) -> usize {
+        self.parts
+            .iter()
+            .filter(|part| matches!(part, FyfthTemplatePart::Value { .. }))
+            .count()
+    }
+
+    /// Fills the placeholders with `vals` in order.
+    pub(crate) fn render(
+        &self,
+        vals: &[FyfthVariant],
+        world: &World,
+        lang: &FyfthLanguageExtension,
+    ) -> String {
+        let mut output = String::new();
+        let mut vals = vals.iter();
+        for part in self.parts.iter() {
+            match part {
+                FyfthTemplatePart::Text(text) => output.push_str(text),
+                FyfthTemplatePart::Value { precision } => {
+                    let val = vals.next().expect("a value for every placeholder");
+                    match val {
+                        FyfthVariant::Literal(val) => output.push_str(val),
+                        val => write_value(&mut output, val, *precision, world, lang),
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+/// Pretty prints `val`, with `precision` decimal places for all nums in it if there is one.
+fn write_value(
+    output: &mut String,
+    val: &FyfthVariant,
+    precision: Option<usize>,
+    world: &World,
+    lang: &FyfthLanguageExtension,
+) {
+    let Some(prec) = precision else {
+        return val.pretty_print(output, world, lang);
+    };
+
+    match val {
+        FyfthVariant::Num(val) => write!(output, "{val:.prec$}").unwrap(),
+        FyfthVariant::Vec2(val) => {
+            write!(output, "vec2({:.prec$} {:.prec$})", val.x, val.y).unwrap()
+        }
+        FyfthVariant::Vec3(val) => write!(
+            output,
+            "vec3({:.prec$} {:.prec$} {:.prec$})",
+            val.x, val.y, val.z
+        )
+        .unwrap(),
+        FyfthVariant::Quat(val) => write!(
+            output,
+            "quat({:.prec$} {:.prec$} {:.prec$} {:.prec$})",
+            val.x, val.y, val.z, val.w
+        )
+        .unwrap(),
+        FyfthVariant::Iter(vals) => {
+            write!(output, "[{} items; ", vals.len()).unwrap();
+            for (i, val) in vals.iter().enumerate() {
+                if i > 0 {
+                    output.push_str(", ");
+                }
+                write_value(output, val, precision, world, lang);
+            }
+            output.push(']');
+        }
+        FyfthVariant::Map(map) => {
+            write!(output, "{{{} entries; ", map.len()).unwrap();
+            for (i, (key, val)) in map.iter().enumerate() {
+                if i > 0 {
+                    output.push_str(", ");
+                }
+                write!(output, "\"{key}\": ").unwrap();
+                write_value(output, val, precision, world, lang);
+            }
+            output.push('}');
+        }
+        val => val.pretty_print(output, world, lang),
+    }
+}
@@ -0,0 +1,197 @@
+use std::sync::Arc;
+
+use crate::{
+    error::FyfthError,
+    interpreter::FyfthVariant,
+    language::{sort_by_keys, values_eq},
+    lexer::FyfthSpan,
+};
+
+/// A built-in word that runs a code block once for every value of an iter.
+///
+/// The code runs on the call stack like the body of `times`, so it may contain macros and
+/// control flow and every step it takes counts towards the step limit of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FyfthEachWord {
+    /// `iter code map` replaces every value with the value `code` leaves for it.
+    Map,
+    /// `iter code filter_by` keeps the values for which `code` leaves `true`.
+    FilterBy,
+    /// `iter init code fold` runs `code` on `acc val` for every value, starting with `init` as
+    /// `acc`, and leaves the last `acc`.
+    Fold,
+    /// `iter code reduce` is `fold` with the first value as `init`.
+    Reduce,
+    /// `iter code sort_by` sorts the values by the key `code` leaves for each of them.
+    SortBy,
+    /// `iter code any` is `true` if `code` leaves `true` for any value.
+    Any,
+    /// `iter code all` is `true` if `code` leaves `true` for all values.
+    All,
+    /// `iter code group_by` groups the values by the key `code` leaves for them into `[key vals]`
+    /// pairs, in the order the keys first appear.
+    GroupBy,
+}
+
+impl FyfthEachWord {
+    pub fn keyword(self) -> &'static str {
+        match self {
+            FyfthEachWord::Map => "map",
+            FyfthEachWord::FilterBy => "filter_by",
+            FyfthEachWord::Fold => "fold",
+            FyfthEachWord::Reduce => "reduce",
+            FyfthEachWord::SortBy => "sort_by",
+            FyfthEachWord::Any => "any",
+            FyfthEachWord::All => "all",
+            FyfthEachWord::GroupBy => "group_by",
+        }
+    }
+
+    /// The number of values the word takes off the stack.
+    pub(crate) fn arity(self) -> usize {
+        match self {
+            FyfthEachWord::Fold => 3,
+            _ => 2,
+        }
+    }
+
+    /// The types of the values the word takes off the stack, as shown in type mismatches.
+    pub(crate) fn expected(self) -> &'static str {
+        match self {
+            FyfthEachWord::Fold => "iter X iter",
+            _ => "iter iter",
+        }
+    }
+}
+
+/// A running [`FyfthEachWord`], which pushes the values for each run of its code and takes the
+/// result of the run off the stack again once the code has finished.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FyfthEach {
+    word: FyfthEachWord,
+    vals: Arc<Vec<FyfthVariant>>,
+    /// The index of the value the code runs on next.
+    index: usize,
+    /// The size of the stack before the values of the current run were pushed.
+    depth: usize,
+    /// The values left by the code so far, the values kept by `filter_by`, or the accumulator of
+    /// `fold` and `reduce`.
+    results: Vec<FyfthVariant>,
+    /// Set once `any` or `all` know their result, so that the remaining values are skipped.
+    decided: bool,
+    span: Option<FyfthSpan>,
+}
+
+impl FyfthEach {
+    pub(crate) fn new(
+        word: FyfthEachWord,
+        vals: Arc<Vec<FyfthVariant>>,
+        init: Option<FyfthVariant>,
+        span: Option<&FyfthSpan>,
+    ) -> Result<Self, FyfthError> {
+        let mut each = Self {
+            word,
+            vals,
+            index: 0,
+            depth: 0,
+            results: init.into_iter().collect(),
+            decided: false,
+            span: span.cloned(),
+        };
+
+        if word == FyfthEachWord::Reduce {
+            let Some(first) = each.vals.first() else {
+                return Err(each.error(FyfthError::runtime("cannot `reduce` an empty iter")));
+            };
+            each.results.push(first.clone());
+            each.index = 1;
+        }
+
+        Ok(each)
+    }
+
+    /// Pushes the values for the next run of the code, or returns `false` if there is none.
+    pub(crate) fn start_run(&mut self, stack: &mut Vec<FyfthVariant>) -> bool {
+        let Some(val) = self.vals.get(self.index).filter(|_| !self.decided) else {
+            return false;
+        };
+        self.index += 1;
+        self.depth = stack.len();
+
+        if matches!(self.word, FyfthEachWord::Fold | FyfthEachWord::Reduce) {
+            stack.push(self.results.pop().expect("an accumulator"));
+        }
+        stack.push(val.clone());
+        true
+    }
+
+    /// Takes the value left by the run of the code that just finished off the stack.
+    pub(crate) fn finish_run(&mut self, stack: &mut Vec<FyfthVariant>) -> Result<(), FyfthError> {
+        if stack.len() != self.depth + 1 {
+            return Err(self.error(FyfthError::runtime(format!(
+                "the code of `{}` needs to leave exactly one value each time it runs",
+                self.word.keyword()
+            ))));
+        }
+        let res = stack.pop().unwrap();
+
+        let cond = match (self.word, res) {
+            (
+                FyfthEachWord::FilterBy | FyfthEachWord::Any | FyfthEachWord::All,
+                FyfthVariant::Bool(cond),
+            ) => cond,
+            (FyfthEachWord::FilterBy | FyfthEachWord::Any | FyfthEachWord::All, res) => {
+                return Err(self.error(FyfthError::type_mismatch("bool", &[res])))
+            }
+            (_, res) => {
+                self.results.push(res);
+                return Ok(());
+            }
+        };
+
+        match self.word {
+            FyfthEachWord::FilterBy if cond => self.results.push(self.vals[self.index - 1].clone()),
+            FyfthEachWord::Any => self.decided = cond,
+            FyfthEachWord::All => self.decided = !cond,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The value the word leaves once the code has run for all values.
+    pub(crate) fn finish(&mut self) -> Result<FyfthVariant, FyfthError> {
+        let results = std::mem::take(&mut self.results);
+        Ok(match self.word {
+            FyfthEachWord::Map | FyfthEachWord::FilterBy => FyfthVariant::Iter(Arc::new(results)),
+            FyfthEachWord::Fold | FyfthEachWord::Reduce => {
+                results.into_iter().next().expect("an accumulator")
+            }
+            FyfthEachWord::SortBy => FyfthVariant::Iter(Arc::new(
+                sort_by_keys(&self.vals, &results).map_err(|err| self.error(err))?,
+            )),
+            FyfthEachWord::Any => FyfthVariant::Bool(self.decided),
+            FyfthEachWord::All => FyfthVariant::Bool(!self.decided),
+            FyfthEachWord::GroupBy => {
+                let mut groups: Vec<(FyfthVariant, Vec<FyfthVariant>)> = vec![];
+                for (key, val) in results.into_iter().zip(self.vals.iter()) {
+                    match groups.iter_mut().find(|(group, _)| values_eq(group, &key)) {
+                        Some((_, vals)) => vals.push(val.clone()),
+                        None => groups.push((key, vec![val.clone()])),
+                    }
+                }
+                let groups = groups.into_iter().map(|(key, vals)| {
+                    FyfthVariant::Iter(Arc::new(vec![key, FyfthVariant::Iter(Arc::new(vals))]))
+                });
+                FyfthVariant::Iter(Arc::new(groups.collect()))
+            }
+        })
+    }
+
+    fn error(&self, err: FyfthError) -> FyfthError {
+        let err = err.with_keyword(self.word.keyword());
+        match &self.span {
+            Some(span) => err.with_span(span),
+            None => err,
+        }
+    }
+}
@@ -1,48 +1,614 @@
 use core::any::TypeId;
-use std::{collections::VecDeque, fmt::Write, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
 
-use bevy::{prelude::*, utils::HashMap};
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 
 use crate::{
     bevy_component::{BevyComponentRegistry, DynBevyComponent},
+    code::{FyfthCode, FyfthOp},
+    error::{FyfthComponentError, FyfthError, FyfthErrorKind},
+    format::FyfthTemplate,
+    higher_order::{FyfthEach, FyfthEachWord},
     language::{FnInfo, FyfthBroadcastBehavior, FyfthLanguageExtension},
-    lexer::{FyfthLexer, FyfthWord},
+    lazy::{FyfthLazyCursor, FyfthLazyIter, LAZY_STEP_LIMIT, RANGE_LIMIT},
+    lexer::{FyfthLexer, FyfthSpan, FyfthWord, FYFTH_INPUT_FILE_NAME},
+    stack_words::{FyfthSideStacks, FyfthStackWord},
+    trace::{FyfthPendingStep, FyfthTrace},
 };
 
+/// The maximum number of steps a single call to [`FyfthInterpreter::run`] may execute.
+pub const ITERATION_LIMIT: usize = 100_000;
+
+/// A parsed value, together with its location in the source code.
+pub(crate) type QueueEntry = (FyfthVariant, Option<FyfthSpan>);
+
 #[derive(Clone, Resource)]
 pub struct FyfthInterpreter {
     stack: Vec<FyfthVariant>,
-    queue: VecDeque<FyfthVariant>,
+    /// The return stack and the saved stacks.
+    side_stacks: FyfthSideStacks,
+    /// Parsed code waiting to be run once the code that is currently running has finished.
+    queue: VecDeque<FyfthFrame>,
+    /// The code that is currently running.
+    calls: FyfthCallStack,
     vars: HashMap<String, FyfthVariant>,
+    macros: HashMap<String, FyfthMacro>,
+    modules: FyfthModules,
+    /// The keywords and macro names to pause before, see [`FyfthInterpreter::add_breakpoint`].
+    breakpoints: HashSet<String>,
+    /// Set when pausing at a breakpoint so that resuming does not pause at it again right away.
+    skip_breakpoint: bool,
+    /// The trace recorded since [`FyfthInterpreter::start_tracing`], if tracing is enabled.
+    trace: Option<Box<FyfthTrace>>,
     lang: Arc<FyfthLanguageExtension>,
 }
 
+/// Keeps track of the modules imported using `import` and `import_as`.
+#[derive(Debug, Clone, Default)]
+struct FyfthModules {
+    /// All modules that have been imported, including the ones that are still running.
+    imported: HashMap<PathBuf, FyfthModule>,
+    /// Sources added using [`FyfthInterpreter::add_module_source`], used instead of reading files.
+    sources: HashMap<PathBuf, Arc<str>>,
+    /// Whether modules without an added source fail to load instead of being read from files.
+    sources_only: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct FyfthModule {
+    namespace: Option<String>,
+    /// The (qualified) names of all macros defined by the module.
+    macros: HashSet<String>,
+}
+
+impl FyfthModules {
+    /// Returns the id of the module at `path`, which is the path itself for modules with an added
+    /// source and the canonical path otherwise.
+    fn resolve(&self, path: &Path) -> Result<PathBuf, FyfthError> {
+        let normalized = normalize_path(path);
+        if self.sources.contains_key(&normalized) {
+            return Ok(normalized);
+        }
+        if self.sources_only {
+            return Err(FyfthError::new(FyfthErrorKind::Io {
+                path: normalized.display().to_string(),
+                message: "the module has not been loaded as an asset, only modules whose path is \
+                    written directly in front of `import` or `import_as` are loaded along with \
+                    the script importing them"
+                    .to_string(),
+            }));
+        }
+
+        path.canonicalize().map_err(|err| io_error(path, err))
+    }
+
+    fn read(&self, id: &Path) -> Result<Arc<str>, FyfthError> {
+        match self.sources.get(id) {
+            Some(source) => Ok(source.clone()),
+            None => std::fs::read_to_string(id)
+                .map(Arc::from)
+                .map_err(|err| io_error(id, err)),
+        }
+    }
+}
+
+/// Compiled code that is running or waiting to be run, and how far it has run.
+#[derive(Debug, Clone)]
+struct FyfthFrame {
+    code: Arc<FyfthCode>,
+    /// The index of the next op to run.
+    pc: usize,
+    kind: FyfthFrameKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FyfthFrameKind {
+    /// Code passed to [`FyfthInterpreter::parse_code`].
+    Input,
+    /// The code of the module with the given id.
+    Module(PathBuf),
+    /// The body of a macro, which has its own local variables.
+    Macro,
+    /// A list run as code, e.g. by `queue` or `while`.
+    Block,
+    /// The body of `times`, which runs again for the given number of times once it has finished.
+    Repeat(u64),
+    /// The code of a higher-order word like `map`, which runs again for every value of its iter.
+    Each(Box<FyfthEach>),
+}
+
+impl FyfthFrame {
+    fn new(code: Arc<FyfthCode>, kind: FyfthFrameKind) -> Self {
+        Self { code, pc: 0, kind }
+    }
+
+    /// The values this frame has left to run, together with their source locations.
+    fn remaining(&self) -> impl Iterator<Item = (&FyfthVariant, Option<&FyfthSpan>)> {
+        self.code.ops[self.pc..]
+            .iter()
+            .zip(&self.code.spans[self.pc..])
+            .filter_map(|(op, span)| Some((op.value()?, span.as_ref())))
+    }
+}
+
+/// The frames of all code that is currently running, innermost last.
+#[derive(Debug, Clone, Default)]
+struct FyfthCallStack {
+    frames: Vec<FyfthFrame>,
+    /// The local variables of all currently running macro invocations, innermost last.
+    locals: Vec<HashMap<String, FyfthVariant>>,
+}
+
+impl FyfthCallStack {
+    fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.frames.clear();
+        self.locals.clear();
+    }
+
+    fn push(&mut self, frame: FyfthFrame) {
+        if frame.kind == FyfthFrameKind::Macro {
+            self.locals.push(default());
+        }
+        self.frames.push(frame);
+    }
+
+    /// Pops all frames that have run to their end, restarting the ones that repeat instead.
+    ///
+    /// Higher-order words take the result of their code off the stack here, which fails if the
+    /// code did not leave the right value.
+    fn pop_finished(
+        &mut self,
+        stack: &mut Vec<FyfthVariant>,
+        trace: &mut Option<Box<FyfthTrace>>,
+    ) -> Result<(), FyfthError> {
+        while let Some(frame) = self.frames.last_mut() {
+            if frame.pc < frame.code.len() {
+                break;
+            }
+
+            match &mut frame.kind {
+                FyfthFrameKind::Repeat(count) if *count > 0 && !frame.code.is_empty() => {
+                    *count -= 1;
+                    frame.pc = 0;
+                    break;
+                }
+                FyfthFrameKind::Each(each) => {
+                    let next = each.finish_run(stack).and_then(|()| {
+                        if each.start_run(stack) {
+                            Ok(None)
+                        } else {
+                            each.finish().map(Some)
+                        }
+                    });
+                    match next {
+                        // empty code is finished again right away
+                        Ok(None) if frame.code.is_empty() => continue,
+                        Ok(None) => {
+                            frame.pc = 0;
+                            break;
+                        }
+                        Ok(Some(val)) => stack.push(val),
+                        Err(err) => {
+                            self.frames.pop();
+                            return Err(err.with_stack(stack));
+                        }
+                    }
+                }
+                FyfthFrameKind::Macro => {
+                    self.locals.pop();
+                    if let Some(trace) = trace {
+                        trace.exit_macro();
+                    }
+                }
+                _ => {}
+            }
+            self.frames.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Continues the innermost frame with the op at `target`.
+    fn jump(&mut self, target: usize) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.pc = target;
+        }
+    }
+
+    /// The id of the innermost running module, if any.
+    fn module(&self) -> Option<&PathBuf> {
+        self.frames
+            .iter()
+            .rev()
+            .find_map(|frame| match &frame.kind {
+                FyfthFrameKind::Module(id) => Some(id),
+                _ => None,
+            })
+    }
+
+    fn runs_module(&self, id: &Path) -> bool {
+        self.frames
+            .iter()
+            .any(|frame| matches!(&frame.kind, FyfthFrameKind::Module(running) if running == id))
+    }
+
+    /// Calls the macro `name`, or runs the list in the variable `name`, which shadows the macro.
+    ///
+    /// Only variables holding an iter shadow macros, so that storing an unrelated value under
+    /// the name of a macro does not break calling it.
+    fn call_macro(
+        &mut self,
+        name: &str,
+        span: Option<&FyfthSpan>,
+        vars: &HashMap<String, FyfthVariant>,
+        macros: &HashMap<String, FyfthMacro>,
+        trace: &mut Option<Box<FyfthTrace>>,
+    ) -> Result<(), FyfthError> {
+        // variables shadow macros, just like `load` does
+        let var = self
+            .locals
+            .last()
+            .and_then(|locals| locals.get(name))
+            .or_else(|| vars.get(name))
+            .filter(|val| matches!(val, FyfthVariant::Iter(_)) || !macros.contains_key(name));
+
+        if let Some(val) = var {
+            let FyfthVariant::Iter(vals) = val else {
+                return Err(FyfthError::type_mismatch("iter", std::slice::from_ref(val))
+                    .with_keyword(&format!("${name}")));
+            };
+            let code = FyfthCode::compile_list(vals, span)?;
+            self.push(FyfthFrame::new(Arc::new(code), FyfthFrameKind::Block));
+        } else if let Some(mac) = macros.get(name) {
+            // every invocation gets its own frame for local variables
+            self.push(FyfthFrame::new(mac.code.clone(), FyfthFrameKind::Macro));
+            if let Some(trace) = trace {
+                trace.enter_macro(name);
+            }
+        } else {
+            return Err(
+                FyfthError::new(FyfthErrorKind::UnknownVariable(name.to_string()))
+                    .with_keyword(&format!("${name}")),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a call to [`FyfthInterpreter::run_with_fuel`] ran all of the queued code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FyfthRunStatus {
+    /// There is no code left to run.
+    Finished,
+    /// The run ran out of fuel and can be resumed.
+    Suspended,
+    /// The run stopped right before a breakpoint and can be resumed.
+    Paused,
+}
+
+/// A macro defined using `macro <name> ... ;`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FyfthMacro {
+    pub body: Vec<FyfthVariant>,
+    /// The source location of every value in `body`.
+    pub spans: Vec<Option<FyfthSpan>>,
+    code: Arc<FyfthCode>,
+}
+
+impl FyfthMacro {
+    /// Creates a macro from its parsed body, compiling it once so that calls do not copy it.
+    pub(crate) fn new(body: Vec<QueueEntry>) -> Result<Self, FyfthError> {
+        let code = FyfthCode::compile(body.iter().cloned())?;
+        let (body, spans) = body.into_iter().unzip();
+
+        Ok(Self {
+            body,
+            spans,
+            code: Arc::new(code),
+        })
+    }
+}
+
 impl FyfthInterpreter {
     pub fn new() -> Self {
         Self {
             stack: default(),
+            side_stacks: default(),
             queue: default(),
+            calls: default(),
             vars: default(),
+            macros: default(),
+            modules: default(),
+            breakpoints: default(),
+            skip_breakpoint: false,
+            trace: None,
             lang: Arc::new(FyfthLanguageExtension::base_fyfth()),
         }
     }
 
+    /// Creates an interpreter and loads the prelude at `path` into it.
+    ///
+    /// Panics if the prelude cannot be loaded, see [`FyfthInterpreter::try_new_with_prelude`] for
+    /// a fallible version.
     pub fn new_with_prelude(path: &str, world: &mut World) -> Self {
-        let mut output = Self {
-            stack: default(),
-            queue: default(),
-            vars: default(),
-            lang: Arc::new(FyfthLanguageExtension::base_fyfth()),
+        Self::try_new_with_prelude(path, world).unwrap_or_else(|errors| {
+            panic!(
+                "Failed to load the prelude `{path}`:\n{}",
+                format_diagnostics(&errors)
+            )
+        })
+    }
+
+    pub fn try_new_with_prelude(path: &str, world: &mut World) -> Result<Self, Vec<FyfthError>> {
+        let mut output = Self::new();
+        output.load_prelude(path, world)?;
+        Ok(output)
+    }
+
+    /// Reads, parses and runs the prelude at `path`.
+    ///
+    /// If running the prelude fails, the rest of it is discarded so that it does not get executed
+    /// by the next call to [`FyfthInterpreter::run`].
+    pub fn load_prelude(&mut self, path: &str, world: &mut World) -> Result<(), Vec<FyfthError>> {
+        let id = self
+            .modules
+            .resolve(Path::new(path))
+            .map_err(|err| vec![err])?;
+        self.run_module(path, id, None, world)
+    }
+
+    /// Runs the module at `path` again if it has been loaded or imported before, e.g. after its
+    /// source has changed. Returns whether the module was loaded before.
+    ///
+    /// Macros that the module no longer defines are removed, all other macros are kept. If the
+    /// module fails to parse, nothing changes at all.
+    pub fn reload_module(
+        &mut self,
+        path: &str,
+        world: &mut World,
+    ) -> Result<bool, Vec<FyfthError>> {
+        let Ok(id) = self.modules.resolve(Path::new(path)) else {
+            return Ok(false);
+        };
+        let Some(module) = self.modules.imported.get(&id) else {
+            return Ok(false);
         };
+        let namespace = module.namespace.clone();
+        let old_macros = module.macros.clone();
 
-        let prelude = std::fs::read_to_string(path).unwrap();
-        output.parse_code(&prelude);
+        let res = self.run_module(path, id.clone(), namespace, world);
 
-        let (_, res) = output.run(world);
+        let module = self.modules.imported.entry(id).or_default();
+        match res {
+            Ok(()) => {
+                for name in old_macros.difference(&module.macros) {
+                    self.macros.remove(name);
+                }
+                Ok(true)
+            }
+            Err(errors) => {
+                module.macros.extend(old_macros);
+                Err(errors)
+            }
+        }
+    }
 
-        res.unwrap();
+    /// Sets the source code of the module at `path`. Loading or importing the module then uses
+    /// this source instead of reading the file at `path`.
+    ///
+    /// Returns whether the source differs from the one added before.
+    pub fn add_module_source(&mut self, path: &str, source: &str) -> bool {
+        let id = normalize_path(Path::new(path));
+        if self
+            .modules
+            .sources
+            .get(&id)
+            .is_some_and(|old| **old == *source)
+        {
+            return false;
+        }
+
+        self.modules.sources.insert(id, source.into());
+        true
+    }
+
+    /// Sets whether loading or importing a module without a source added by
+    /// [`FyfthInterpreter::add_module_source`] fails instead of reading the file at its path.
+    ///
+    /// [`FyfthPlugin`](crate::FyfthPlugin) enables this for the scripts it loads as assets, whose
+    /// paths are relative to the asset folder rather than the working directory.
+    pub fn set_module_sources_only(&mut self, sources_only: bool) {
+        self.modules.sources_only = sources_only;
+    }
+
+    fn run_module(
+        &mut self,
+        path: &str,
+        id: PathBuf,
+        namespace: Option<String>,
+        world: &mut World,
+    ) -> Result<(), Vec<FyfthError>> {
+        let code = self.modules.read(&id).map_err(|err| vec![err])?;
+        let lexer = FyfthLexer::iter_file(&code, path, self.lang.clone());
+        let mut resolver = MacroResolver::new(&self.macros, namespace.as_deref());
+        let parsed = parse_lexed(&self.lang, &mut resolver, lexer)?;
+        let code = FyfthCode::compile(parsed).map_err(|err| vec![err])?;
+
+        // the module's own imports should neither run it again nor import it circularly
+        self.modules.imported.insert(
+            id.clone(),
+            FyfthModule {
+                namespace,
+                macros: default(),
+            },
+        );
+        let kind = FyfthFrameKind::Module(id);
+        self.queue
+            .push_back(FyfthFrame::new(Arc::new(code), kind.clone()));
+
+        let (_, res) = self.run(world);
+
+        res.map_err(|err| {
+            // the module may not have started if code queued before it failed
+            self.queue.retain(|frame| frame.kind != kind);
+            // like Forth's `ABORT`, so that the values the failed code parked are not picked up
+            self.side_stacks.ret.clear();
+            vec![err]
+        })
+    }
+
+    /// Takes over the results of `run`, a clone of `base` that ran code on its own, e.g. over
+    /// several frames while `base` stayed usable.
+    ///
+    /// Only what `run` changed is taken over, so macros and variables that were changed here in
+    /// the meantime, e.g. by [`FyfthInterpreter::reload_module`], are kept unless `run` changed
+    /// them as well.
+    pub fn apply_run(&mut self, base: &FyfthInterpreter, run: FyfthInterpreter) {
+        let FyfthInterpreter {
+            stack,
+            side_stacks,
+            queue,
+            calls,
+            vars,
+            macros,
+            modules,
+            breakpoints: _,
+            skip_breakpoint: _,
+            trace,
+            lang: _,
+        } = run;
+
+        self.stack = stack;
+        self.side_stacks = side_stacks;
+        self.queue = queue;
+        self.calls = calls;
+        self.trace = trace;
+        apply_changes(&mut self.vars, &base.vars, vars);
+        apply_changes(&mut self.macros, &base.macros, macros);
+        for (id, module) in modules.imported {
+            self.modules.imported.entry(id).or_insert(module);
+        }
+    }
+
+    /// Whether there is no code left to run, i.e. the last run was not suspended.
+    pub fn is_finished(&self) -> bool {
+        self.calls.is_empty() && self.queue.is_empty()
+    }
+
+    /// Starts recording a [`FyfthTrace`] of everything that runs, discarding any previous trace.
+    pub fn start_tracing(&mut self) {
+        self.trace = Some(default());
+    }
+
+    /// Stops recording and returns the trace recorded since [`FyfthInterpreter::start_tracing`].
+    pub fn stop_tracing(&mut self) -> Option<FyfthTrace> {
+        self.trace.take().map(|trace| *trace)
+    }
+
+    /// Continues recording into `trace`, e.g. one returned by [`FyfthInterpreter::stop_tracing`]
+    /// of another interpreter.
+    pub fn continue_tracing(&mut self, trace: FyfthTrace) {
+        self.trace = Some(Box::new(trace));
+    }
 
-        output
+    pub fn trace(&self) -> Option<&FyfthTrace> {
+        self.trace.as_deref()
+    }
+
+    /// Pauses [`FyfthInterpreter::run_with_fuel`] right before the keyword or macro `name` runs.
+    pub fn add_breakpoint(&mut self, name: &str) {
+        self.breakpoints.insert(name.to_string());
+    }
+
+    /// Removes a breakpoint, returning whether it existed.
+    pub fn remove_breakpoint(&mut self, name: &str) -> bool {
+        self.breakpoints.remove(name)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &str> {
+        self.breakpoints.iter().map(String::as_str)
+    }
+
+    pub fn stack(&self) -> &[FyfthVariant] {
+        &self.stack
+    }
+
+    /// The values moved off the stack with `>r`, the last one on top.
+    pub fn return_stack(&self) -> &[FyfthVariant] {
+        &self.side_stacks.ret
+    }
+
+    /// The stacks saved with `save_stack` by their names.
+    pub fn saved_stacks(&self) -> &HashMap<String, Vec<FyfthVariant>> {
+        &self.side_stacks.saved
+    }
+
+    /// The values waiting to be run, next one first, together with their source locations.
+    pub fn queue(&self) -> impl Iterator<Item = (&FyfthVariant, Option<&FyfthSpan>)> {
+        self.calls
+            .frames
+            .iter()
+            .rev()
+            .chain(&self.queue)
+            .flat_map(FyfthFrame::remaining)
+    }
+
+    pub fn vars(&self) -> &HashMap<String, FyfthVariant> {
+        &self.vars
+    }
+
+    /// The local variables of the innermost running macro, if any.
+    pub fn locals(&self) -> Option<&HashMap<String, FyfthVariant>> {
+        self.calls.locals.last()
+    }
+
+    /// Pretty prints the next `count` values waiting to be run.
+    pub fn pretty_print_queue(&self, world: &World, count: usize, delimiter: &str) -> String {
+        let mut buffer = String::new();
+
+        let mut first = true;
+        for (val, _) in self.queue().take(count) {
+            if !first {
+                write!(&mut buffer, "{delimiter}").unwrap();
+            }
+            val.pretty_print(&mut buffer, world, &self.lang);
+            first = false;
+        }
+
+        buffer
+    }
+
+    /// Pretty prints the local variables of the innermost running macro and all variables, one
+    /// per line.
+    pub fn pretty_print_vars(&self, world: &World) -> String {
+        let mut buffer = String::new();
+
+        let locals = self.locals().into_iter().flatten();
+        let vars = self.vars.iter();
+        for (scope, (ident, val)) in locals
+            .map(|var| ("local ", var))
+            .chain(vars.map(|var| ("", var)))
+        {
+            write!(&mut buffer, "{scope}\"{ident}\" : ").unwrap();
+            val.pretty_print(&mut buffer, world, &self.lang);
+            buffer.push('\n');
+        }
+
+        buffer
     }
 
     pub fn pretty_print_stack(&self, world: &World, delimiter: &str) -> String {
@@ -60,237 +626,961 @@ impl FyfthInterpreter {
         buffer
     }
 
-    pub fn add_language_extension(&mut self, ext: FyfthLanguageExtension) {
+    pub fn add_language_extension(
+        &mut self,
+        ext: FyfthLanguageExtension,
+    ) -> Result<(), FyfthError> {
         let lang = Arc::make_mut(&mut self.lang);
-        lang.merge(ext).unwrap();
+        lang.merge(ext)
     }
 
-    pub fn parse_code(&mut self, code: &str) {
+    /// Parses `code` and appends it to the queue of values to be run.
+    ///
+    /// If any word fails to parse, nothing is queued and all errors are returned.
+    pub fn parse_code(&mut self, code: &str) -> Result<(), Vec<FyfthError>> {
         let lexer = FyfthLexer::iter(code, self.lang.clone());
-        for res in lexer {
-            let word = res.unwrap();
-            FyfthVariant::parse(self, word);
-        }
+        self.parse_words(lexer)
+    }
+
+    /// Like [`FyfthInterpreter::parse_code`], but errors point into the file `file_name`.
+    pub fn parse_file(&mut self, code: &str, file_name: &str) -> Result<(), Vec<FyfthError>> {
+        let lexer = FyfthLexer::iter_file(code, file_name, self.lang.clone());
+        self.parse_words(lexer)
+    }
+
+    fn parse_words(&mut self, lexer: FyfthLexer) -> Result<(), Vec<FyfthError>> {
+        let mut resolver = MacroResolver::new(&self.macros, None);
+        let parsed = parse_lexed(&self.lang, &mut resolver, lexer)?;
+        let code = FyfthCode::compile(parsed).map_err(|err| vec![err])?;
+        self.queue
+            .push_back(FyfthFrame::new(Arc::new(code), FyfthFrameKind::Input));
+        Ok(())
     }
 
-    pub fn run(&mut self, world: &mut World) -> (String, Result<(), ()>) {
+    /// Runs the queue until it is empty, failing if that takes more than [`ITERATION_LIMIT`]
+    /// steps. See [`FyfthInterpreter::run_with_fuel`] for runs that can be resumed.
+    ///
+    /// Breakpoints are ignored. If the run fails, the rest of the code that failed is discarded,
+    /// while code that was queued after it is run by the next run.
+    pub fn run(&mut self, world: &mut World) -> (String, Result<(), FyfthError>) {
+        let (output, result) = self.run_steps(world, ITERATION_LIMIT, false);
+        let result = result.and_then(|status| match status {
+            FyfthRunStatus::Finished => Ok(()),
+            FyfthRunStatus::Suspended | FyfthRunStatus::Paused => {
+                // code that hit the limit is abandoned just like code that failed
+                self.calls.clear();
+                if let Some(trace) = &mut self.trace {
+                    trace.abort_macros();
+                }
+                Err(
+                    FyfthError::new(FyfthErrorKind::IterationLimit(ITERATION_LIMIT))
+                        .with_stack(&self.stack),
+                )
+            }
+        });
+
+        (output, result)
+    }
+
+    /// Runs at most `fuel` steps of the queue.
+    ///
+    /// If there is still code left to run after that, the run is suspended with the stack, queue
+    /// and variables intact, and calling this again (e.g. on the next frame) resumes it. The run
+    /// is also paused right before running a keyword or macro that has a breakpoint. Like with
+    /// [`FyfthInterpreter::run`], the rest of the code that failed is discarded on errors.
+    pub fn run_with_fuel(
+        &mut self,
+        world: &mut World,
+        fuel: usize,
+    ) -> (String, Result<FyfthRunStatus, FyfthError>) {
+        self.run_steps(world, fuel, true)
+    }
+
+    /// Runs exactly one step of the queue, ignoring breakpoints.
+    pub fn step(&mut self, world: &mut World) -> (String, Result<FyfthRunStatus, FyfthError>) {
+        self.run_steps(world, 1, false)
+    }
+
+    fn run_steps(
+        &mut self,
+        world: &mut World,
+        fuel: usize,
+        use_breakpoints: bool,
+    ) -> (String, Result<FyfthRunStatus, FyfthError>) {
         let mut output = String::new();
 
         let FyfthInterpreter {
             stack,
+            side_stacks,
             queue,
+            calls,
             vars,
+            macros,
+            modules,
+            breakpoints,
+            skip_breakpoint,
+            trace,
             lang,
         } = self;
 
         let mut result = Ok(());
 
-        let mut iterations = 0;
+        let mut steps = 0;
+        let mut paused = false;
+        // the step being traced is recorded once the next one starts
+        let mut pending_step = None;
+
+        while result.is_ok() && steps < fuel {
+            if let (Some(trace), Some(step)) = (trace.as_mut(), pending_step.take()) {
+                trace.finish_step(step, stack.len());
+            }
 
-        while !queue.is_empty() && result.is_ok() {
-            if iterations > 100_000 {
-                write!(&mut output, "Error: reached iteration limit").unwrap();
-                result = Err(());
+            if let Err(err) = calls.pop_finished(stack, trace) {
+                result = Err(err);
                 break;
             }
-            iterations += 1;
-
-            let current = queue.pop_front().unwrap();
-            match &current {
-                // These are all the values which cannot be executed. Simply push them onto the stack.
-                FyfthVariant::Bool(_)
-                | FyfthVariant::Num(_)
-                | FyfthVariant::Literal(_)
-                | FyfthVariant::Entity(_)
-                | FyfthVariant::Iter(_)
-                | FyfthVariant::Nil => {
-                    stack.push(current);
-                    continue;
+            let Some(frame) = calls.frames.last_mut() else {
+                match queue.pop_front() {
+                    Some(frame) => {
+                        calls.push(frame);
+                        continue;
+                    }
+                    None => break,
                 }
+            };
 
-                FyfthVariant::LineEnd => continue,
+            // the code is shared, so holding on to it while the frame changes is cheap
+            let code = frame.code.clone();
+            let op = &code.ops[frame.pc];
+            let span = code.spans[frame.pc].as_ref();
 
-                // All other values can be executed and so we continue below
-                _ => {}
+            let skip = std::mem::take(skip_breakpoint) || !use_breakpoints;
+            let name = op.command_name(lang);
+            if !skip && name.is_some_and(|name| breakpoints.contains(name)) {
+                *skip_breakpoint = true;
+                paused = true;
+                break;
+            }
+            steps += 1;
+            frame.pc += 1;
+
+            if let (Some(_), Some(value)) = (&trace, op.value()) {
+                // macros are profiled separately from keywords
+                let keyword = match value {
+                    FyfthVariant::MacroCall(_) | FyfthVariant::Word(_) => None,
+                    val => val.command_name(lang),
+                };
+                pending_step = Some(FyfthPendingStep::start(value, span, stack.len(), keyword));
             }
 
-            result = match current {
-                FyfthVariant::FnIter => {
-                    let mut iter_vec = vec![];
-                    std::mem::swap(&mut iter_vec, stack);
+            // lazy iterators are consumed within a single step, so they get a budget of their own
+            let mut lazy_budget = LAZY_STEP_LIMIT;
 
-                    stack.push(FyfthVariant::Iter(iter_vec));
-                    Ok(())
+            result = match op {
+                FyfthOp::Jump(target) => {
+                    calls.jump(*target);
+                    continue;
                 }
-                FyfthVariant::Macro => {
-                    if let Some(FyfthVariant::Literal(name)) = queue.pop_front() {
-                        let mut counter = 1;
-                        let mut end = 0;
-                        for val in queue.iter() {
-                            match val {
-                                FyfthVariant::Macro => counter += 1,
-                                FyfthVariant::LineEnd => {
-                                    counter -= 1;
-                                    if counter <= 0 {
-                                        assert_eq!(counter, 0);
-                                        break;
-                                    }
-                                }
-                                _ => {}
-                            }
-                            end += 1;
+                FyfthOp::JumpUnless { target, source } => match stack.last() {
+                    Some(&FyfthVariant::Bool(cond)) => {
+                        stack.pop();
+                        if !cond {
+                            calls.jump(*target);
                         }
-
-                        vars.insert(name, FyfthVariant::Iter(queue.drain(0..end).collect()));
-                        Ok(())
-                    } else {
-                        write!(
-                            &mut output,
-                            "Syntax error: `macro` needs to be followed by a name for the macro"
-                        )
-                        .unwrap();
-                        Err(())
+                        continue;
                     }
-                }
-                FyfthVariant::FnQueue => {
-                    if let Some(FyfthVariant::Iter(vals)) = stack.pop() {
-                        for val in vals.iter().rev() {
-                            queue.push_front(val.clone());
-                        }
-                        Ok(())
-                    } else {
-                        write!(
-                            &mut output,
-                            "Syntax error: `run` expects the top of the stack to be `iter`"
-                        )
-                        .unwrap();
-                        Err(())
+                    _ => {
+                        let keyword = source.command_name(lang).unwrap_or_default();
+                        Err(type_mismatch_on_top(keyword, "bool", stack))
                     }
+                },
+                FyfthOp::DefineMacro(name, mac) => {
+                    vars.remove(name);
+                    if let Some(module) = calls.module().and_then(|id| modules.imported.get_mut(id))
+                    {
+                        module.macros.insert(name.clone());
+                    }
+                    macros.insert(name.clone(), mac.clone());
+                    continue;
                 }
-                FyfthVariant::FnPush => {
-                    if let Some(FyfthVariant::Iter(vals)) = stack.pop() {
-                        stack.extend_from_slice(&vals);
+                FyfthOp::Value(current) => match current {
+                    // These are all the values which cannot be executed. Simply push them onto the stack.
+                    FyfthVariant::Bool(_)
+                    | FyfthVariant::Num(_)
+                    | FyfthVariant::Int(_)
+                    | FyfthVariant::Literal(_)
+                    | FyfthVariant::Entity(_)
+                    | FyfthVariant::Iter(_)
+                    | FyfthVariant::Lazy(_)
+                    | FyfthVariant::Map(_)
+                    | FyfthVariant::Vec2(_)
+                    | FyfthVariant::Vec3(_)
+                    | FyfthVariant::Quat(_)
+                    | FyfthVariant::Component(_)
+                    | FyfthVariant::Nil => {
+                        stack.push(current.clone());
+                        continue;
+                    }
+
+                    FyfthVariant::Macro
+                    | FyfthVariant::LineEnd
+                    | FyfthVariant::If
+                    | FyfthVariant::Else
+                    | FyfthVariant::Then => unreachable!("removed when compiling the code"),
+
+                    FyfthVariant::FnIter => {
+                        let iter_vec = std::mem::take(stack);
+                        stack.push(FyfthVariant::Iter(Arc::new(iter_vec)));
                         Ok(())
-                    } else {
-                        write!(
-                            &mut output,
-                            "Syntax error: `push` expects the top of the stack to be `iter`"
-                        )
-                        .unwrap();
-                        Err(())
                     }
-                }
-                FyfthVariant::FnDup => {
-                    if let Some(val) = stack.last() {
-                        stack.push(val.clone());
+                    FyfthVariant::FnQueue => match stack.last() {
+                        Some(FyfthVariant::Iter(_)) => {
+                            let Some(FyfthVariant::Iter(vals)) = stack.pop() else {
+                                unreachable!()
+                            };
+                            FyfthCode::compile_list(&vals, span)
+                                .map(|code| {
+                                    calls.push(FyfthFrame::new(
+                                        Arc::new(code),
+                                        FyfthFrameKind::Block,
+                                    ))
+                                })
+                                .map_err(|err| err.with_stack(stack))
+                        }
+                        _ => Err(type_mismatch_on_top("queue", "iter", stack)),
+                    },
+                    FyfthVariant::FnImport => match stack.last() {
+                        Some(FyfthVariant::Literal(path)) => {
+                            let path = path.clone();
+                            import_module(&path, None, span, lang, macros, modules, calls)
+                                .map(|frame| {
+                                    stack.pop();
+                                    if let Some(frame) = frame {
+                                        calls.push(frame);
+                                    }
+                                })
+                                .map_err(|err| err.with_keyword("import").with_stack(stack))
+                        }
+                        _ => Err(type_mismatch_on_top("import", "literal", stack)),
+                    },
+                    FyfthVariant::FnImportAs => match stack.as_slice() {
+                        [.., FyfthVariant::Literal(path), FyfthVariant::Literal(namespace)] => {
+                            let (path, namespace) = (path.clone(), namespace.clone());
+                            import_module(
+                                &path,
+                                Some(&namespace),
+                                span,
+                                lang,
+                                macros,
+                                modules,
+                                calls,
+                            )
+                            .map(|frame| {
+                                stack.truncate(stack.len() - 2);
+                                if let Some(frame) = frame {
+                                    calls.push(frame);
+                                }
+                            })
+                            .map_err(|err| err.with_keyword("import_as").with_stack(stack))
+                        }
+                        _ => Err(type_mismatch_on_top("import_as", "literal literal", stack)),
+                    },
+                    FyfthVariant::Word(name) => {
+                        if macros.contains_key(name) {
+                            calls
+                                .call_macro(name, span, vars, macros, trace)
+                                .map_err(|err| err.with_stack(stack))
+                        } else {
+                            stack.push(FyfthVariant::Literal(name.clone()));
+                            Ok(())
+                        }
                     }
-                    Ok(())
-                }
-                FyfthVariant::FnSwap => {
-                    let first = stack.pop();
-                    let second = stack.pop();
-
-                    match (first, second) {
-                        (Some(first), Some(second)) => {
-                            stack.push(first);
-                            stack.push(second);
+                    FyfthVariant::FnCall => match stack.last() {
+                        Some(FyfthVariant::Literal(_)) => {
+                            let Some(FyfthVariant::Literal(name)) = stack.pop() else {
+                                unreachable!()
+                            };
+                            calls
+                                .call_macro(&name, span, vars, macros, trace)
+                                .map_err(|err| err.with_stack(stack))
+                        }
+                        _ => Err(type_mismatch_on_top("call", "literal", stack)),
+                    },
+                    FyfthVariant::MacroCall(name) => calls
+                        .call_macro(name, span, vars, macros, trace)
+                        .map_err(|err| err.with_stack(stack)),
+                    FyfthVariant::FnPush => match stack.last() {
+                        Some(FyfthVariant::Iter(_)) => {
+                            let Some(FyfthVariant::Iter(vals)) = stack.pop() else {
+                                unreachable!()
+                            };
+                            stack.extend(Arc::unwrap_or_clone(vals));
                             Ok(())
                         }
-                        _ => {
-                            write!(
-                                &mut output,
-                                "Syntax error: `swap` expects two items on the stack"
-                            )
-                            .unwrap();
-                            Err(())
+                        Some(FyfthVariant::Lazy(lazy)) => {
+                            let lazy = lazy.clone();
+                            let mut ctx = FyfthContext {
+                                output: &mut output,
+                                world,
+                                vars,
+                                locals: calls.locals.last_mut(),
+                                macros,
+                                lang,
+                                lazy_budget: &mut lazy_budget,
+                            };
+                            match lazy.collect(&mut ctx) {
+                                Ok(vals) => {
+                                    stack.pop();
+                                    stack.extend(vals);
+                                    Ok(())
+                                }
+                                Err(err) => Err(err.with_keyword("push").with_stack(stack)),
+                            }
+                        }
+                        _ => Err(type_mismatch_on_top("push", "iter", stack)),
+                    },
+                    FyfthVariant::FnTimes => match stack.as_slice() {
+                        [.., count, FyfthVariant::Iter(_)] if count.as_int().is_some() => {
+                            let Some(FyfthVariant::Iter(body)) = stack.pop() else {
+                                unreachable!()
+                            };
+                            let Some(count) = stack.pop().as_ref().and_then(FyfthVariant::as_int)
+                            else {
+                                unreachable!()
+                            };
+
+                            // the body is compiled once and then run `count` times
+                            FyfthCode::compile_list(&body, span)
+                                .map(|code| {
+                                    if count >= 1 && !code.is_empty() {
+                                        let kind = FyfthFrameKind::Repeat(count as u64 - 1);
+                                        calls.push(FyfthFrame::new(Arc::new(code), kind));
+                                    }
+                                })
+                                .map_err(|err| err.with_stack(stack))
+                        }
+                        _ => Err(type_mismatch_on_top("times", "int iter", stack)),
+                    },
+                    FyfthVariant::FnEach(word) => {
+                        let (keyword, arity) = (word.keyword(), word.arity());
+                        let args = stack.len().checked_sub(arity).map(|start| &stack[start..]);
+                        match args {
+                            Some(
+                                [FyfthVariant::Iter(_) | FyfthVariant::Lazy(_), .., FyfthVariant::Iter(_)],
+                            ) => {
+                                let Some(FyfthVariant::Iter(body)) = stack.pop() else {
+                                    unreachable!()
+                                };
+                                let init = (arity == 3).then(|| stack.pop().unwrap());
+                                let vals = match stack.pop() {
+                                    Some(FyfthVariant::Iter(vals)) => Ok(vals),
+                                    Some(FyfthVariant::Lazy(lazy)) => lazy
+                                        .collect(&mut FyfthContext {
+                                            output: &mut output,
+                                            world,
+                                            vars,
+                                            locals: calls.locals.last_mut(),
+                                            macros,
+                                            lang,
+                                            lazy_budget: &mut lazy_budget,
+                                        })
+                                        .map(Arc::new),
+                                    _ => unreachable!(),
+                                };
+
+                                // the code runs in a frame of its own, see `pop_finished`
+                                vals.and_then(|vals| {
+                                    let code = FyfthCode::compile_list(&body, span)?;
+                                    let mut each = FyfthEach::new(*word, vals, init, span)?;
+                                    if each.start_run(stack) {
+                                        let kind = FyfthFrameKind::Each(Box::new(each));
+                                        calls.push(FyfthFrame::new(Arc::new(code), kind));
+                                    } else {
+                                        stack.push(each.finish()?);
+                                    }
+                                    Ok(())
+                                })
+                                .map_err(|err| err.with_keyword(keyword).with_stack(stack))
+                            }
+                            _ => Err(type_mismatch_on_top(keyword, word.expected(), stack)),
                         }
                     }
-                }
-                FyfthVariant::FnSwapN => {
-                    if let Some(FyfthVariant::Num(index)) = stack.pop() {
-                        let index = index as i32;
-                        if index < 0 || index as usize >= stack.len() {
-                            output
-                                .push_str("Error: not enough items on the stack to apply `swap_n`");
-                            Err(())
-                        } else if index == 0 {
+                    FyfthVariant::FnWhile => match stack.as_slice() {
+                        [.., FyfthVariant::Iter(_), FyfthVariant::Iter(_)] => {
+                            let Some(FyfthVariant::Iter(body)) = stack.pop() else {
+                                unreachable!()
+                            };
+                            let Some(FyfthVariant::Iter(cond)) = stack.pop() else {
+                                unreachable!()
+                            };
+
+                            FyfthCode::compile_while(&cond, &body, span)
+                                .map(|code| {
+                                    calls.push(FyfthFrame::new(
+                                        Arc::new(code),
+                                        FyfthFrameKind::Block,
+                                    ))
+                                })
+                                .map_err(|err| err.with_stack(stack))
+                        }
+                        _ => Err(type_mismatch_on_top("while", "iter iter", stack)),
+                    },
+                    FyfthVariant::Locals(names) => match calls.locals.last_mut() {
+                        Some(frame) if stack.len() >= names.len() => {
+                            let vals = stack.split_off(stack.len() - names.len());
+                            frame.extend(names.iter().cloned().zip(vals));
+                            Ok(())
+                        }
+                        Some(_) => Err(FyfthError::stack_underflow(names.len(), stack.len())
+                            .with_keyword("{:")
+                            .with_stack(stack)),
+                        None => Err(FyfthError::syntax(
+                            "local variables can only be declared inside of a macro",
+                        )
+                        .with_keyword("{:")
+                        .with_stack(stack)),
+                    },
+                    FyfthVariant::FnDup => match stack.last() {
+                        Some(val) => {
+                            stack.push(val.clone());
                             Ok(())
+                        }
+                        None => Err(FyfthError::stack_underflow(1, 0)
+                            .with_keyword("dup")
+                            .with_stack(stack)),
+                    },
+                    FyfthVariant::FnSwap => {
+                        if stack.len() < 2 {
+                            Err(FyfthError::stack_underflow(2, stack.len())
+                                .with_keyword("swap")
+                                .with_stack(stack))
                         } else {
-                            assert!(!stack.is_empty());
-                            let rhs_index = stack.len() - 1;
-                            let lhs_index = stack.len() - 1 - index as usize;
-
-                            stack.swap(lhs_index, rhs_index);
+                            let len = stack.len();
+                            stack.swap(len - 2, len - 1);
                             Ok(())
                         }
-                    } else {
-                        output.push_str("Syntax error: `swap_n` must follow a number");
-                        Err(())
                     }
-                }
-                FyfthVariant::FnRotRN => {
-                    if let Some(FyfthVariant::Num(index)) = stack.pop() {
-                        let size = index as i32;
-                        if size < 0 || size as usize > stack.len() {
-                            output.push_str("Error: not enough items on the stack to apply `rotr`");
-                            Err(())
-                        } else if size <= 1 {
-                            Ok(())
-                        } else {
-                            assert!(!stack.is_empty());
-                            let lhs_index = stack.len() - size as usize;
+                    // the index on top, the value it points to and the top value below it
+                    FyfthVariant::FnSwapN => count_on_top("swap_n", 2, stack).map(|index| {
+                        stack.pop();
+                        let rhs_index = stack.len() - 1;
+                        let lhs_index = stack.len() - 1 - index;
+
+                        stack.swap(lhs_index, rhs_index);
+                    }),
+                    FyfthVariant::FnRotRN => count_on_top("rotr", 1, stack).map(|size| {
+                        stack.pop();
+                        if size > 1 {
+                            let lhs_index = stack.len() - size;
                             let last = stack.pop().unwrap();
                             stack.insert(lhs_index, last);
-                            Ok(())
                         }
-                    } else {
-                        output.push_str("Syntax error: `rotr` must follow a number");
-                        Err(())
-                    }
-                }
-                FyfthVariant::FnRotLN => {
-                    if let Some(FyfthVariant::Num(index)) = stack.pop() {
-                        let size = index as i32;
-                        if size < 0 || size as usize > stack.len() {
-                            output.push_str("Error: not enough items on the stack to apply `rotl`");
-                            Err(())
-                        } else if size <= 1 {
-                            Ok(())
-                        } else {
-                            assert!(!stack.is_empty());
-                            let lhs_index = stack.len() - size as usize;
+                    }),
+                    FyfthVariant::FnRotLN => count_on_top("rotl", 1, stack).map(|size| {
+                        stack.pop();
+                        if size > 1 {
+                            let lhs_index = stack.len() - size;
                             let temp = stack.remove(lhs_index);
                             stack.push(temp);
-                            Ok(())
                         }
-                    } else {
-                        output.push_str("Syntax error: `rotl` must follow a number");
-                        Err(())
-                    }
-                }
-                FyfthVariant::LangFunc(index) => FyfthVariant::try_call_func(
-                    FyfthContext {
-                        output: &mut output,
-                        world,
-                        vars,
-                        lang: &lang,
+                    }),
+                    FyfthVariant::FnStack(word) => word
+                        .run(stack, side_stacks)
+                        .map_err(|err| err.with_stack(stack)),
+                    FyfthVariant::FnFormat => match stack.last() {
+                        Some(FyfthVariant::Literal(template)) => {
+                            // the template decides how many values are taken off the stack
+                            match FyfthTemplate::parse(template) {
+                                Ok(template) if template.placeholder_count() < stack.len() => {
+                                    stack.pop();
+                                    let count = template.placeholder_count();
+                                    let vals = stack.split_off(stack.len() - count);
+                                    let output = template.render(&vals, world, lang);
+                                    stack.push(FyfthVariant::Literal(output));
+                                    Ok(())
+                                }
+                                Ok(template) => Err(FyfthError::stack_underflow(
+                                    template.placeholder_count() + 1,
+                                    stack.len(),
+                                )),
+                                Err(err) => Err(err),
+                            }
+                            .map_err(|err| err.with_keyword("format").with_stack(stack))
+                        }
+                        _ => Err(type_mismatch_on_top("format", "literal", stack)),
                     },
-                    &lang.functions[index as usize],
-                    stack,
-                ),
-                _ => todo!(),
+                    FyfthVariant::LangFunc(index) => FyfthVariant::try_call_func(
+                        FyfthContext {
+                            output: &mut output,
+                            world,
+                            vars,
+                            locals: calls.locals.last_mut(),
+                            macros,
+                            lang,
+                            lazy_budget: &mut lazy_budget,
+                        },
+                        &lang.functions[*index as usize],
+                        stack,
+                    ),
+                },
             };
+
+            if let Some(span) = span {
+                result = result.map_err(|err| err.with_span(span));
+            }
+        }
+
+        if let (Some(trace), Some(step)) = (trace.as_mut(), pending_step) {
+            trace.finish_step(step, stack.len());
+        }
+        if result.is_ok() {
+            result = calls.pop_finished(stack, trace);
         }
+        if result.is_err() {
+            // the failed code is abandoned, only code queued after it is left to run
+            calls.clear();
+            if let Some(trace) = trace {
+                trace.abort_macros();
+            }
+        }
+
+        let result = result.map(|()| {
+            if paused {
+                FyfthRunStatus::Paused
+            } else if calls.is_empty() && queue.is_empty() {
+                FyfthRunStatus::Finished
+            } else {
+                FyfthRunStatus::Suspended
+            }
+        });
 
         (output, result)
     }
 }
 
+/// Parses the words of `lexer` into the values to queue for running them.
+///
+/// If any word fails to parse, all errors are returned instead.
+fn parse_lexed(
+    lang: &FyfthLanguageExtension,
+    resolver: &mut MacroResolver,
+    lexer: FyfthLexer,
+) -> Result<VecDeque<QueueEntry>, Vec<FyfthError>> {
+    let mut parsed = VecDeque::new();
+    let mut errors = vec![];
+
+    // the contents and opening brackets of all list and map literals we are currently in, and
+    // whether they are maps
+    let mut open_lists: Vec<(VecDeque<QueueEntry>, FyfthSpan, bool)> = vec![];
+    // the names and opening word of a local variable declaration `{: a b :}`
+    let mut open_locals: Option<(Vec<String>, FyfthSpan)> = None;
+
+    let mut after_macro = false;
+
+    for res in lexer {
+        let word = match res {
+            Ok(word) => word,
+            Err(err) => {
+                errors.push(err);
+                continue;
+            }
+        };
+
+        if let Some((names, _)) = open_locals.as_mut() {
+            if word.is_bare(":}") {
+                let (names, open_span) = open_locals.take().unwrap();
+                open_lists
+                    .last_mut()
+                    .map_or(&mut parsed, |(items, ..)| items)
+                    .push_back((FyfthVariant::Locals(names), Some(open_span)));
+            } else if word.maybe_prefix.is_some() {
+                errors.push(
+                    FyfthError::syntax("names of local variables cannot have a prefix")
+                        .with_span(&word.span),
+                );
+            } else {
+                names.push(word.word);
+            }
+            continue;
+        }
+
+        if !word.in_quotes && word.maybe_prefix.is_none() {
+            match word.word.as_str() {
+                "{:" => {
+                    open_locals = Some((vec![], word.span));
+                    continue;
+                }
+                ":}" => {
+                    errors.push(FyfthError::syntax("unmatched `:}`").with_span(&word.span));
+                    continue;
+                }
+                "[" | "{" => {
+                    open_lists.push((VecDeque::new(), word.span, word.word == "{"));
+                    continue;
+                }
+                "]" | "}" => {
+                    let is_map = word.word == "}";
+                    if !matches!(open_lists.last(), Some(&(.., open_map)) if open_map == is_map) {
+                        errors.push(
+                            FyfthError::syntax(format!("unmatched `{}`", word.word))
+                                .with_span(&word.span),
+                        );
+                        continue;
+                    }
+                    let (items, open_span, _) = open_lists.pop().unwrap();
+                    let literal = if is_map {
+                        match parse_map_literal(items, &open_span) {
+                            Ok(map) => map,
+                            Err(err) => {
+                                errors.push(err);
+                                continue;
+                            }
+                        }
+                    } else {
+                        FyfthVariant::Iter(Arc::new(
+                            items.into_iter().map(|(val, _)| val).collect(),
+                        ))
+                    };
+                    open_lists
+                        .last_mut()
+                        .map_or(&mut parsed, |(items, ..)| items)
+                        .push_back((literal, Some(open_span)));
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        let in_list = !open_lists.is_empty();
+        let in_map_key =
+            matches!(open_lists.last(), Some((items, _, true)) if items.len() % 2 == 0);
+        let target = open_lists
+            .last_mut()
+            .map_or(&mut parsed, |(items, ..)| items);
+
+        // map keys are the words as written, even if they name keywords or macros
+        let is_number = word.word.parse::<f32>().is_ok() && !word.in_quotes;
+        if in_map_key && word.maybe_prefix.is_none() && !is_number {
+            after_macro = false;
+            target.push_back((FyfthVariant::Literal(word.word), Some(word.span)));
+            continue;
+        }
+
+        // the name of a macro being defined is always a literal, even if the macro exists
+        if std::mem::take(&mut after_macro) && word.maybe_prefix.is_none() {
+            let name = resolver.define(&word.word);
+            target.push_back((FyfthVariant::Literal(name), Some(word.span)));
+            continue;
+        }
+        after_macro = word.is_bare("macro");
+
+        if let Err(err) = FyfthVariant::parse(lang, resolver, in_list, word, target) {
+            errors.push(err);
+        }
+    }
+
+    if let Some((_, open_span)) = open_locals {
+        errors.push(FyfthError::syntax("unclosed `{:`").with_span(&open_span));
+    }
+
+    for (_, open_span, is_map) in open_lists {
+        let bracket = if is_map { "{" } else { "[" };
+        errors.push(FyfthError::syntax(format!("unclosed `{bracket}`")).with_span(&open_span));
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(parsed)
+}
+
+/// Builds the map of a map literal `{ key val ... }` from its alternating keys and values.
+fn parse_map_literal(
+    items: VecDeque<QueueEntry>,
+    open_span: &FyfthSpan,
+) -> Result<FyfthVariant, FyfthError> {
+    let mut map = BTreeMap::new();
+    let mut items = items.into_iter();
+    while let Some((key, span)) = items.next() {
+        let span = span.as_ref().unwrap_or(open_span);
+        let FyfthVariant::Literal(key) = key else {
+            return Err(FyfthError::syntax(format!(
+                "the keys of a map literal need to be literals, found a `{}`",
+                key.type_name()
+            ))
+            .with_span(span));
+        };
+        let Some((val, _)) = items.next() else {
+            return Err(FyfthError::syntax(format!(
+                "the key \"{key}\" of a map literal has no value"
+            ))
+            .with_span(span));
+        };
+        map.insert(key, val);
+    }
+    Ok(FyfthVariant::Map(Arc::new(map)))
+}
+
+/// Parses and compiles the module at `path` into the frame to run it in, which is `None` if the
+/// module does not need to run.
+///
+/// Relative paths are resolved relative to the file containing the import. Modules that have
+/// already been imported are skipped, and importing a module that is still running is an error.
+fn import_module(
+    path: &str,
+    namespace: Option<&str>,
+    span: Option<&FyfthSpan>,
+    lang: &Arc<FyfthLanguageExtension>,
+    macros: &HashMap<String, FyfthMacro>,
+    modules: &mut FyfthModules,
+    calls: &FyfthCallStack,
+) -> Result<Option<FyfthFrame>, FyfthError> {
+    let path = match span.map(|span| &*span.file_name) {
+        Some(file_name) if file_name != FYFTH_INPUT_FILE_NAME => Path::new(file_name)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(path),
+        _ => PathBuf::from(path),
+    };
+    let file_name = path.display().to_string();
+
+    let id = modules.resolve(&path)?;
+    if calls.runs_module(&id) {
+        return Err(FyfthError::runtime(format!(
+            "circular import of `{file_name}`"
+        )));
+    }
+    if modules.imported.contains_key(&id) {
+        return Ok(None);
+    }
+
+    let code = modules.read(&id)?;
+    let lexer = FyfthLexer::iter_file(&code, &file_name, lang.clone());
+    let mut resolver = MacroResolver::new(macros, namespace);
+    let code = parse_lexed(lang, &mut resolver, lexer)
+        .and_then(|parsed| FyfthCode::compile(parsed).map_err(|err| vec![err]))
+        .map_err(|errors| {
+            FyfthError::new(FyfthErrorKind::Import {
+                path: file_name.clone(),
+                errors,
+            })
+        })?;
+
+    modules.imported.insert(
+        id.clone(),
+        FyfthModule {
+            namespace: namespace.map(str::to_string),
+            macros: default(),
+        },
+    );
+
+    Ok(Some(FyfthFrame::new(
+        Arc::new(code),
+        FyfthFrameKind::Module(id),
+    )))
+}
+
+/// Finds the paths of all modules that `code` imports by writing the path directly in front of
+/// `import` or `import_as`, without running it.
+pub(crate) fn find_imports(code: &str) -> Vec<String> {
+    let lang = Arc::new(FyfthLanguageExtension::base_fyfth());
+    let lexer = FyfthLexer::iter(code, lang.clone());
+    let Ok(parsed) = parse_lexed(&lang, &mut MacroResolver::new(&default(), None), lexer) else {
+        return vec![];
+    };
+
+    let values: Vec<_> = parsed.into_iter().map(|(val, _)| val).collect();
+    let mut imports = vec![];
+    for (i, val) in values.iter().enumerate() {
+        let path = match (val, &values[..i]) {
+            (FyfthVariant::FnImport, [.., path]) => path,
+            (
+                FyfthVariant::FnImportAs,
+                [.., path, FyfthVariant::Literal(_) | FyfthVariant::Word(_)],
+            ) => path,
+            _ => continue,
+        };
+        if let FyfthVariant::Literal(path) = path {
+            imports.push(path.clone());
+        }
+    }
+    imports
+}
+
+/// Applies the entries that differ between `base` and `changed` to `target`.
+fn apply_changes<V: PartialEq>(
+    target: &mut HashMap<String, V>,
+    base: &HashMap<String, V>,
+    changed: HashMap<String, V>,
+) {
+    target.retain(|name, _| changed.contains_key(name) || !base.contains_key(name));
+    for (name, val) in changed {
+        if base.get(&name) != Some(&val) {
+            target.insert(name, val);
+        }
+    }
+}
+
+/// Removes all `.` and `..` components from `path` without accessing the file system.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut output = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(output.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                output.pop();
+            }
+            component => output.push(component),
+        }
+    }
+    output
+}
+
+fn io_error(path: &Path, err: std::io::Error) -> FyfthError {
+    FyfthError::new(FyfthErrorKind::Io {
+        path: path.display().to_string(),
+        message: err.to_string(),
+    })
+}
+
+/// Decides which bare words are calls of macros while parsing.
+pub(crate) struct MacroResolver {
+    /// The macros that have been defined before the code is parsed.
+    known: HashSet<String>,
+    /// The namespace that macros defined by the parsed code are put into.
+    namespace: Option<String>,
+    /// The macros defined by the parsed code, without their namespace.
+    defined: HashSet<String>,
+}
+
+impl MacroResolver {
+    fn new(macros: &HashMap<String, FyfthMacro>, namespace: Option<&str>) -> Self {
+        Self {
+            known: macros.keys().cloned().collect(),
+            namespace: namespace.map(str::to_string),
+            defined: default(),
+        }
+    }
+
+    /// Registers a macro defined by the parsed code and returns its full name.
+    fn define(&mut self, name: &str) -> String {
+        self.defined.insert(name.to_string());
+        self.qualify(name)
+    }
+
+    fn qualify(&self, name: &str) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{namespace}.{name}"),
+            None => name.to_string(),
+        }
+    }
+
+    /// The full name of the macro a bare `name` refers to, if any.
+    ///
+    /// Macros defined by the parsed code shadow macros of the same name from outside of it.
+    fn resolve(&self, name: &str) -> Option<String> {
+        if self.defined.contains(name) {
+            Some(self.qualify(name))
+        } else if self.known.contains(name) {
+            Some(name.to_string())
+        } else {
+            None
+        }
+    }
+}
+
+/// Joins a list of errors into a single message, one error per line.
+pub fn format_diagnostics(errors: &[FyfthError]) -> String {
+    let mut buffer = String::new();
+    for err in errors {
+        if !buffer.is_empty() {
+            buffer.push('\n');
+        }
+        write!(&mut buffer, "{err}").unwrap();
+    }
+    buffer
+}
+
+/// Builds the error for an interpreter built-in that expects values of the space-separated types
+/// `expected` on top of the stack.
+fn type_mismatch_on_top(keyword: &str, expected: &str, stack: &[FyfthVariant]) -> FyfthError {
+    let arity = expected.split_whitespace().count();
+    if stack.len() < arity {
+        FyfthError::stack_underflow(arity, stack.len())
+    } else {
+        FyfthError::type_mismatch(expected, &stack[stack.len() - arity..])
+    }
+    .with_keyword(keyword)
+    .with_stack(stack)
+}
+
+/// Reads the count on top of the stack for `keyword`, which needs the count itself and `extra`
+/// more values besides the counted ones.
+fn count_on_top(keyword: &str, extra: usize, stack: &[FyfthVariant]) -> Result<usize, FyfthError> {
+    let count = match stack.last().map(FyfthVariant::as_index) {
+        Some(Some(count)) if count >= 0 => usize::try_from(count).unwrap_or(usize::MAX),
+        Some(Some(count)) => {
+            return Err(FyfthError::runtime(format!(
+                "the count of `{keyword}` cannot be negative, got {count}"
+            ))
+            .with_keyword(keyword)
+            .with_stack(stack))
+        }
+        _ => return Err(type_mismatch_on_top(keyword, "int", stack)),
+    };
+
+    let expected = count.saturating_add(extra);
+    if stack.len() < expected {
+        return Err(FyfthError::stack_underflow(expected, stack.len())
+            .with_keyword(keyword)
+            .with_stack(stack));
+    }
+    Ok(count)
+}
+
 pub struct FyfthContext<'a> {
     pub output: &'a mut String,
     pub world: &'a mut World,
     pub vars: &'a mut HashMap<String, FyfthVariant>,
+    /// The local variables of the innermost running macro, if any.
+    pub locals: Option<&'a mut HashMap<String, FyfthVariant>>,
+    pub macros: &'a HashMap<String, FyfthMacro>,
     pub lang: &'a FyfthLanguageExtension,
+    /// The number of values lazy iterators may still produce during the running command.
+    pub(crate) lazy_budget: &'a mut usize,
+}
+
+impl FyfthContext<'_> {
+    /// Borrows the context again, e.g. to call another command with it.
+    pub fn reborrow(&mut self) -> FyfthContext<'_> {
+        FyfthContext {
+            output: self.output,
+            world: self.world,
+            vars: self.vars,
+            locals: self.locals.as_deref_mut(),
+            macros: self.macros,
+            lang: self.lang,
+            lazy_budget: self.lazy_budget,
+        }
+    }
+
+    /// Counts a value produced by a lazy iterator, failing once [`LAZY_STEP_LIMIT`] values have
+    /// been produced during the running command.
+    pub(crate) fn consume_lazy_value(&mut self) -> Result<(), FyfthError> {
+        match self.lazy_budget.checked_sub(1) {
+            Some(budget) => {
+                *self.lazy_budget = budget;
+                Ok(())
+            }
+            None => Err(FyfthError::runtime(format!(
+                "lazy iterators cannot produce more than {LAZY_STEP_LIMIT} values in a single \
+                step"
+            ))),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -299,8 +1589,16 @@ pub enum FyfthVariant {
     Nil,
     Bool(bool),
     Num(f32),
+    /// A whole number, which is exact where a `num` would lose precision, e.g. for large ids.
+    Int(i64),
     Literal(String),
-    Iter(Vec<FyfthVariant>),
+    /// A list of values, which is shared between copies until one of them is changed.
+    Iter(Arc<Vec<FyfthVariant>>),
+    /// An iterator whose values are only produced when it is used, see [`FyfthLazyIter`].
+    Lazy(Arc<FyfthLazyIter>),
+    /// Values stored under literal keys, which are shared between copies until one of them is
+    /// changed.
+    Map(Arc<BTreeMap<String, FyfthVariant>>),
 
     // bevy specific
     Entity(Entity),
@@ -313,180 +1611,275 @@ pub enum FyfthVariant {
     FnIter,
     Macro,
     LineEnd,
+    If,
+    Else,
+    Then,
+    FnTimes,
+    FnWhile,
     FnQueue,
     FnPush,
+    FnCall,
     FnDup,
     FnSwap,
     FnSwapN,
     FnRotRN,
     FnRotLN,
+    FnFormat,
+    /// A higher-order word like `map` that runs a code block for every value of an iter.
+    FnEach(FyfthEachWord),
+    /// A word from Forth like `over` that rearranges the values on the stack.
+    FnStack(FyfthStackWord),
+    MacroCall(String),
+    /// A bare word that runs the macro of its name if there is one by the time it is run, and
+    /// that is pushed as a literal otherwise.
+    Word(String),
+    Locals(Vec<String>),
+    FnImport,
+    FnImportAs,
     LangFunc(u32),
 }
 
 impl FyfthVariant {
-    pub(crate) fn parse(interpreter: &mut FyfthInterpreter, word: FyfthWord) {
-        let FyfthInterpreter { queue, lang, .. } = interpreter;
-        let lang = Arc::clone(lang);
-
+    pub(crate) fn parse(
+        lang: &FyfthLanguageExtension,
+        resolver: &MacroResolver,
+        in_list: bool,
+        word: FyfthWord,
+        queue: &mut VecDeque<QueueEntry>,
+    ) -> Result<(), FyfthError> {
         if let Some(prefix_index) = word.maybe_prefix {
-            // TODO: handle errors gracefully
-            let res = (lang.prefixes[prefix_index as usize].fn_ptr)(&word.word, &lang).unwrap();
-
-            queue.extend(res);
-            return;
+            let prefix = &lang.prefixes[prefix_index as usize];
+            let res = (prefix.fn_ptr)(&word.word, lang).map_err(|err| {
+                err.with_keyword(&format!("{}{}", prefix.ch, word.word))
+                    .with_span(&word.span)
+            })?;
+
+            queue.extend(res.into_iter().map(|val| {
+                // let `$name` refer to macros of the parsed module as well
+                let val = match val {
+                    FyfthVariant::MacroCall(name) => {
+                        FyfthVariant::MacroCall(resolver.resolve(&name).unwrap_or(name))
+                    }
+                    val => val,
+                };
+                (val, Some(word.span.clone()))
+            }));
+            return Ok(());
         }
 
+        let span = Some(word.span);
+
         if word.in_quotes {
-            queue.push_back(FyfthVariant::Literal(word.word));
-            return;
+            queue.push_back((FyfthVariant::Literal(word.word), span));
+            return Ok(());
         }
 
         let command = word.word;
 
-        // check if it's a number
+        // check if it's a number, which is an int unless it has a fractional part or exponent
+        if let Ok(val) = command.parse() {
+            queue.push_back((FyfthVariant::Int(val), span));
+            return Ok(());
+        }
         if let Ok(val) = command.parse() {
-            queue.push_back(FyfthVariant::Num(val));
-            return;
-        }
-
-        match command.as_str() {
-            "nil" => queue.push_back(FyfthVariant::Nil),
-            "iter" => queue.push_back(FyfthVariant::FnIter),
-            "true" => queue.push_back(FyfthVariant::Bool(true)),
-            "false" => queue.push_back(FyfthVariant::Bool(false)),
-            "macro" => queue.push_back(FyfthVariant::Macro),
-            "queue" => queue.push_back(FyfthVariant::FnQueue),
-            "dup" => queue.push_back(FyfthVariant::FnDup),
-            "swap" => queue.push_back(FyfthVariant::FnSwap),
-            ";" => queue.push_back(FyfthVariant::LineEnd),
-            "swap_n" => queue.push_back(FyfthVariant::FnSwapN),
-            "rotr" => queue.push_back(FyfthVariant::FnRotRN),
-            "rotl" => queue.push_back(FyfthVariant::FnRotLN),
-            "push" => queue.push_back(FyfthVariant::FnPush),
+            queue.push_back((FyfthVariant::Num(val), span));
+            return Ok(());
+        }
+
+        let val = match command.as_str() {
+            "nil" => FyfthVariant::Nil,
+            "iter" => FyfthVariant::FnIter,
+            "true" => FyfthVariant::Bool(true),
+            "false" => FyfthVariant::Bool(false),
+            "macro" => FyfthVariant::Macro,
+            "queue" => FyfthVariant::FnQueue,
+            "dup" => FyfthVariant::FnDup,
+            "swap" => FyfthVariant::FnSwap,
+            ";" => FyfthVariant::LineEnd,
+            "swap_n" => FyfthVariant::FnSwapN,
+            "rotr" => FyfthVariant::FnRotRN,
+            "rotl" => FyfthVariant::FnRotLN,
+            "format" => FyfthVariant::FnFormat,
+            "map" => FyfthVariant::FnEach(FyfthEachWord::Map),
+            "filter_by" => FyfthVariant::FnEach(FyfthEachWord::FilterBy),
+            "fold" => FyfthVariant::FnEach(FyfthEachWord::Fold),
+            "reduce" => FyfthVariant::FnEach(FyfthEachWord::Reduce),
+            "sort_by" => FyfthVariant::FnEach(FyfthEachWord::SortBy),
+            "any" => FyfthVariant::FnEach(FyfthEachWord::Any),
+            "all" => FyfthVariant::FnEach(FyfthEachWord::All),
+            "group_by" => FyfthVariant::FnEach(FyfthEachWord::GroupBy),
+            "over" => FyfthVariant::FnStack(FyfthStackWord::Over),
+            "nip" => FyfthVariant::FnStack(FyfthStackWord::Nip),
+            "tuck" => FyfthVariant::FnStack(FyfthStackWord::Tuck),
+            "pick" => FyfthVariant::FnStack(FyfthStackWord::Pick),
+            "roll" => FyfthVariant::FnStack(FyfthStackWord::Roll),
+            "2dup" => FyfthVariant::FnStack(FyfthStackWord::TwoDup),
+            "2swap" => FyfthVariant::FnStack(FyfthStackWord::TwoSwap),
+            "2drop" => FyfthVariant::FnStack(FyfthStackWord::TwoDrop),
+            "depth" => FyfthVariant::FnStack(FyfthStackWord::Depth),
+            "clear" => FyfthVariant::FnStack(FyfthStackWord::Clear),
+            ">r" => FyfthVariant::FnStack(FyfthStackWord::ToReturn),
+            "r>" => FyfthVariant::FnStack(FyfthStackWord::FromReturn),
+            "r@" => FyfthVariant::FnStack(FyfthStackWord::CopyReturn),
+            "save_stack" => FyfthVariant::FnStack(FyfthStackWord::SaveStack),
+            "restore_stack" => FyfthVariant::FnStack(FyfthStackWord::RestoreStack),
+            "push" => FyfthVariant::FnPush,
+            "call" => FyfthVariant::FnCall,
+            "import" => FyfthVariant::FnImport,
+            "import_as" => FyfthVariant::FnImportAs,
+            "if" => FyfthVariant::If,
+            "else" => FyfthVariant::Else,
+            "then" => FyfthVariant::Then,
+            "times" => FyfthVariant::FnTimes,
+            "while" => FyfthVariant::FnWhile,
 
             _ if lang.keywords.contains_key(&command) => {
                 let index = *lang.keywords.get(&command).unwrap();
-                queue.push_back(FyfthVariant::LangFunc(index));
+                FyfthVariant::LangFunc(index)
             }
-            _ => queue.push_back(FyfthVariant::Literal(command)),
-        }
+            // keywords take precedence over macros of the same name
+            _ => match resolver.resolve(&command) {
+                Some(name) => FyfthVariant::MacroCall(name),
+                // outside of lists, the macro may still get defined before the word runs
+                None if !in_list => FyfthVariant::Word(command),
+                None => FyfthVariant::Literal(command),
+            },
+        };
+
+        queue.push_back((val, span));
+        Ok(())
     }
 
     pub(crate) fn try_call_func(
         ctx: FyfthContext,
         func: &FnInfo,
         stack: &mut Vec<FyfthVariant>,
-    ) -> Result<(), ()> {
+    ) -> Result<(), FyfthError> {
         let arity = func.simple_function.broadcast_behaviors.len();
 
         if stack.len() < arity {
-            write!(
-                ctx.output,
-                "Syntax error: function `{}` expects {} arguments but stack has only {} items",
-                &func.keyword,
-                arity,
-                stack.len(),
-            )
-            .unwrap();
-            return Err(());
+            return Err(FyfthError::stack_underflow(arity, stack.len())
+                .with_keyword(&func.keyword)
+                .with_stack(stack));
+        }
+
+        let args = stack.split_off(stack.len() - arity);
+
+        let result = Self::call_func_with_args(ctx, func, &args);
+
+        match result {
+            Ok(Some(result_value)) => {
+                stack.push(result_value);
+                Ok(())
+            }
+            Ok(None) => Ok(()),
+            Err(err) => {
+                // restore the arguments so the snapshot shows the stack the command was called on
+                stack.extend(args);
+                Err(err.with_keyword(&func.keyword).with_stack(stack))
+            }
+        }
+    }
+
+    fn call_func_with_args(
+        mut ctx: FyfthContext,
+        func: &FnInfo,
+        args: &[FyfthVariant],
+    ) -> Result<Option<FyfthVariant>, FyfthError> {
+        let behaviors = &func.simple_function.broadcast_behaviors;
+
+        // commands that do not broadcast over iterators need all of their values at once
+        let mut args = Cow::Borrowed(args);
+        for index in 0..args.len() {
+            if let (FyfthBroadcastBehavior::IgnoreIter, FyfthVariant::Lazy(lazy)) =
+                (behaviors[index], &args[index])
+            {
+                let vals = lazy.collect(&mut ctx)?;
+                args.to_mut()[index] = FyfthVariant::Iter(Arc::new(vals));
+            }
         }
 
-        let args = stack.split_off(stack.len() - arity);
-        let wants_to_iter = func
-            .simple_function
-            .broadcast_behaviors
-            .iter()
-            .enumerate()
-            .any(|(index, &beh)| match (beh, &args[index]) {
-                (FyfthBroadcastBehavior::MayIter, FyfthVariant::Iter(_)) => true,
-                (FyfthBroadcastBehavior::MayIter, _) => false,
-                (FyfthBroadcastBehavior::IgnoreIter, _) => false,
-            });
-
-        let maybe_output = if !wants_to_iter {
-            (func.simple_function.fn_ptr)(ctx, &args)?
-        } else {
-            // make sure all iters have the same length
-            let min_len = func
-                .simple_function
-                .broadcast_behaviors
-                .iter()
-                .zip(args.iter())
-                .filter_map(|(beh, arg)| match (beh, arg) {
-                    (FyfthBroadcastBehavior::MayIter, FyfthVariant::Iter(v)) => Some(v.len()),
-                    _ => None,
-                })
-                .min()
-                .unwrap();
-            let max_len = func
-                .simple_function
-                .broadcast_behaviors
-                .iter()
-                .zip(args.iter())
-                .filter_map(|(beh, arg)| match (beh, arg) {
-                    (FyfthBroadcastBehavior::MayIter, FyfthVariant::Iter(v)) => Some(v.len()),
-                    _ => None,
-                })
-                .max()
-                .unwrap();
-
-            if min_len != max_len {
-                write!(
-                    ctx.output,
-                    "Error: function `{}` cannot combine iterators of differing lenths.",
-                    &func.keyword,
-                )
-                .unwrap();
-                return Err(());
-            }
+        let broadcast: Vec<_> = (0..args.len())
+            .filter(|&index| {
+                behaviors[index] == FyfthBroadcastBehavior::MayIter
+                    && matches!(args[index], FyfthVariant::Iter(_) | FyfthVariant::Lazy(_))
+            })
+            .collect();
 
-            let len = min_len;
+        if broadcast.is_empty() {
+            return (func.simple_function.fn_ptr)(ctx, &args);
+        }
 
-            let mut output_vec = Vec::with_capacity(len);
-            let mut temp_args = Vec::with_capacity(arity);
+        let differing_lengths =
+            || FyfthError::runtime("cannot combine iterators of differing lengths");
+        // the arguments that are not broadcast over are the same for every element
+        let mut temp_args = args.to_vec();
+        let mut output_vec = vec![];
 
-            let FyfthContext {
-                output,
-                world,
-                vars,
-                lang,
-            } = ctx;
+        let lists: Option<Vec<_>> = broadcast
+            .iter()
+            .map(|&index| match &args[index] {
+                FyfthVariant::Iter(vals) => Some((index, vals)),
+                _ => None,
+            })
+            .collect();
+
+        if let Some(lists) = lists {
+            // plain lists are indexed directly, which is a lot faster than stepping cursors
+            let len = lists[0].1.len();
+            if lists.iter().any(|(_, vals)| vals.len() != len) {
+                return Err(differing_lengths());
+            }
 
+            output_vec.reserve(len);
             for i in 0..len {
-                temp_args.clear();
-                for j in 0..arity {
-                    let arg = &args[j];
-                    let beh = &func.simple_function.broadcast_behaviors[j];
-
-                    temp_args.push(match (beh, arg) {
-                        (FyfthBroadcastBehavior::MayIter, FyfthVariant::Iter(v)) => v[i].clone(),
-                        _ => arg.clone(),
-                    });
+                for (index, vals) in &lists {
+                    temp_args[*index] = vals[i].clone();
                 }
 
-                let maybe_value = (func.simple_function.fn_ptr)(
-                    FyfthContext {
-                        output,
-                        world,
-                        vars,
-                        lang,
-                    },
-                    &temp_args,
-                )?;
-
-                if let Some(val) = maybe_value {
+                if let Some(val) = (func.simple_function.fn_ptr)(ctx.reborrow(), &temp_args)? {
                     output_vec.push(val);
                 }
             }
 
-            Some(FyfthVariant::Iter(output_vec))
-        };
+            return Ok(Some(FyfthVariant::Iter(Arc::new(output_vec))));
+        }
+
+        let mut cursors: Vec<_> = broadcast
+            .into_iter()
+            .filter_map(|index| Some((index, FyfthLazyCursor::new(&args[index], ctx.world)?)))
+            .collect();
+
+        // make sure all iters have the same length, as far as it is known up front
+        let lens: Vec<_> = cursors
+            .iter()
+            .filter_map(|(_, cursor)| cursor.len())
+            .collect();
+        if lens.iter().any(|&len| len != lens[0]) {
+            return Err(differing_lengths());
+        }
+
+        output_vec.reserve(lens.first().copied().unwrap_or(0).min(RANGE_LIMIT));
+        loop {
+            let mut ended = 0;
+            for (index, cursor) in &mut cursors {
+                match cursor.next(&mut ctx)? {
+                    Some(val) => temp_args[*index] = val,
+                    None => ended += 1,
+                }
+            }
+            if ended == cursors.len() {
+                break;
+            } else if ended > 0 {
+                return Err(differing_lengths());
+            }
 
-        if let Some(result_value) = maybe_output {
-            stack.push(result_value);
+            if let Some(val) = (func.simple_function.fn_ptr)(ctx.reborrow(), &temp_args)? {
+                output_vec.push(val);
+            }
         }
 
-        Ok(())
+        Ok(Some(FyfthVariant::Iter(Arc::new(output_vec))))
     }
 
     pub(crate) fn pretty_print(
@@ -499,6 +1892,7 @@ impl FyfthVariant {
             FyfthVariant::Nil => write!(output, "nil").unwrap(),
             FyfthVariant::Bool(val) => write!(output, "{val}").unwrap(),
             FyfthVariant::Num(val) => write!(output, "{val}").unwrap(),
+            FyfthVariant::Int(val) => write!(output, "{val}").unwrap(),
             FyfthVariant::Literal(val) => write!(output, "\"{val}\"").unwrap(),
             FyfthVariant::Entity(entity) => {
                 let maybe_name = (|| world.get_entity(*entity)?.get::<Name>())();
@@ -512,7 +1906,7 @@ impl FyfthVariant {
             FyfthVariant::Iter(vec) => {
                 write!(output, "[{} items; ", vec.len()).unwrap();
                 let mut first = true;
-                for item in vec {
+                for item in vec.iter() {
                     if !first {
                         write!(output, ", ").unwrap();
                     }
@@ -521,6 +1915,20 @@ impl FyfthVariant {
                 }
                 write!(output, "]").unwrap();
             }
+            FyfthVariant::Lazy(lazy) => lazy.pretty_print(output, world, lang),
+            FyfthVariant::Map(map) => {
+                write!(output, "{{{} entries; ", map.len()).unwrap();
+                let mut first = true;
+                for (key, val) in map.iter() {
+                    if !first {
+                        write!(output, ", ").unwrap();
+                    }
+                    write!(output, "\"{key}\": ").unwrap();
+                    val.pretty_print(output, world, lang);
+                    first = false;
+                }
+                write!(output, "}}").unwrap();
+            }
             FyfthVariant::Vec2(val) => write!(output, "vec2({} {})", val.x, val.y).unwrap(),
             FyfthVariant::Vec3(val) => {
                 write!(output, "vec3({} {} {})", val.x, val.y, val.z).unwrap()
@@ -538,7 +1946,21 @@ impl FyfthVariant {
             FyfthVariant::FnSwapN => write!(output, "swap_n").unwrap(),
             FyfthVariant::FnRotRN => write!(output, "rotr").unwrap(),
             FyfthVariant::FnRotLN => write!(output, "rotl").unwrap(),
+            FyfthVariant::FnFormat => write!(output, "format").unwrap(),
+            FyfthVariant::FnEach(word) => write!(output, "{}", word.keyword()).unwrap(),
+            FyfthVariant::FnStack(word) => write!(output, "{}", word.keyword()).unwrap(),
             FyfthVariant::FnPush => write!(output, "push").unwrap(),
+            FyfthVariant::FnCall => write!(output, "call").unwrap(),
+            FyfthVariant::If => write!(output, "if").unwrap(),
+            FyfthVariant::Else => write!(output, "else").unwrap(),
+            FyfthVariant::Then => write!(output, "then").unwrap(),
+            FyfthVariant::FnTimes => write!(output, "times").unwrap(),
+            FyfthVariant::FnWhile => write!(output, "while").unwrap(),
+            FyfthVariant::MacroCall(name) => write!(output, "${name}").unwrap(),
+            FyfthVariant::Word(word) => write!(output, "{word}").unwrap(),
+            FyfthVariant::FnImport => write!(output, "import").unwrap(),
+            FyfthVariant::FnImportAs => write!(output, "import_as").unwrap(),
+            FyfthVariant::Locals(names) => write!(output, "{{: {} :}}", names.join(" ")).unwrap(),
             // TODO: print the keyword of the function
             //       requires some API changes for this function
             FyfthVariant::LangFunc(index) => {
@@ -547,14 +1969,79 @@ impl FyfthVariant {
         }
     }
 
+    /// The keyword or macro name of this value if it is a command, which is also the name that a
+    /// breakpoint needs to be set on to pause before it runs.
+    pub(crate) fn command_name<'a>(&'a self, lang: &'a FyfthLanguageExtension) -> Option<&'a str> {
+        Some(match self {
+            FyfthVariant::MacroCall(name) | FyfthVariant::Word(name) => name,
+            FyfthVariant::LangFunc(index) => &lang.functions[*index as usize].keyword,
+            FyfthVariant::FnIter => "iter",
+            FyfthVariant::Macro => "macro",
+            FyfthVariant::FnQueue => "queue",
+            FyfthVariant::FnDup => "dup",
+            FyfthVariant::FnSwap => "swap",
+            FyfthVariant::FnSwapN => "swap_n",
+            FyfthVariant::FnRotRN => "rotr",
+            FyfthVariant::FnRotLN => "rotl",
+            FyfthVariant::FnFormat => "format",
+            FyfthVariant::FnEach(word) => word.keyword(),
+            FyfthVariant::FnStack(word) => word.keyword(),
+            FyfthVariant::FnPush => "push",
+            FyfthVariant::FnCall => "call",
+            FyfthVariant::If => "if",
+            FyfthVariant::FnTimes => "times",
+            FyfthVariant::FnWhile => "while",
+            FyfthVariant::FnImport => "import",
+            FyfthVariant::FnImportAs => "import_as",
+            _ => return None,
+        })
+    }
+
+    /// The value of a `num`, or of an `int` converted to a `num`.
+    pub fn as_num(&self) -> Option<f32> {
+        match *self {
+            FyfthVariant::Num(val) => Some(val),
+            FyfthVariant::Int(val) => Some(val as f32),
+            _ => None,
+        }
+    }
+
+    /// The value of an `int`, or of a `num` rounded towards zero, e.g. to use it as an index.
+    pub fn as_int(&self) -> Option<i64> {
+        match *self {
+            FyfthVariant::Int(val) => Some(val),
+            FyfthVariant::Num(val) => Some(val as i64),
+            _ => None,
+        }
+    }
+
+    /// The value as an int if it is an int or a whole num, as used for indices and counts.
+    pub fn as_index(&self) -> Option<i64> {
+        match *self {
+            FyfthVariant::Int(val) => Some(val),
+            FyfthVariant::Num(val) if val.fract() == 0.0 => Some(val as i64),
+            _ => None,
+        }
+    }
+
+    /// The name of this value's type as returned by the `type` command.
+    pub fn type_name(&self) -> String {
+        let mut type_name = String::new();
+        self.pretty_print_type(&mut type_name);
+        type_name
+    }
+
     pub(crate) fn pretty_print_type(&self, output: &mut String) {
         match self {
             FyfthVariant::Nil => write!(output, "nil").unwrap(),
             FyfthVariant::Bool(_) => write!(output, "bool").unwrap(),
             FyfthVariant::Num(_) => write!(output, "num").unwrap(),
+            FyfthVariant::Int(_) => write!(output, "int").unwrap(),
             FyfthVariant::Literal(_) => write!(output, "literal").unwrap(),
             FyfthVariant::Entity(_) => write!(output, "Entity").unwrap(),
             FyfthVariant::Iter(_) => write!(output, "iter").unwrap(),
+            FyfthVariant::Lazy(_) => write!(output, "lazy").unwrap(),
+            FyfthVariant::Map(_) => write!(output, "map").unwrap(),
             FyfthVariant::Vec2(_) => write!(output, "vec2").unwrap(),
             FyfthVariant::Vec3(_) => write!(output, "vec3").unwrap(),
             FyfthVariant::Quat(_) => write!(output, "quat").unwrap(),
@@ -573,7 +2060,21 @@ impl FyfthVariant {
             FyfthVariant::FnSwapN => write!(output, "func").unwrap(),
             FyfthVariant::FnRotRN => write!(output, "func").unwrap(),
             FyfthVariant::FnRotLN => write!(output, "func").unwrap(),
+            FyfthVariant::FnFormat => write!(output, "func").unwrap(),
+            FyfthVariant::FnEach(_) => write!(output, "func").unwrap(),
+            FyfthVariant::FnStack(_) => write!(output, "func").unwrap(),
             FyfthVariant::FnPush => write!(output, "func").unwrap(),
+            FyfthVariant::FnCall => write!(output, "func").unwrap(),
+            FyfthVariant::If => write!(output, "special").unwrap(),
+            FyfthVariant::Else => write!(output, "special").unwrap(),
+            FyfthVariant::Then => write!(output, "special").unwrap(),
+            FyfthVariant::FnTimes => write!(output, "func").unwrap(),
+            FyfthVariant::FnWhile => write!(output, "func").unwrap(),
+            FyfthVariant::MacroCall(_) => write!(output, "func").unwrap(),
+            FyfthVariant::Word(_) => write!(output, "special").unwrap(),
+            FyfthVariant::FnImport => write!(output, "func").unwrap(),
+            FyfthVariant::FnImportAs => write!(output, "func").unwrap(),
+            FyfthVariant::Locals(_) => write!(output, "special").unwrap(),
             FyfthVariant::LangFunc(_) => write!(output, "func").unwrap(),
         }
     }
@@ -599,54 +2100,58 @@ impl FyfthVariant {
                 ));
             }
             id if id == TypeId::of::<i8>() => {
-                return Some(FyfthVariant::Num(
-                    value.downcast_ref::<i8>().unwrap().clone() as f32,
+                return Some(FyfthVariant::Int(
+                    *value.downcast_ref::<i8>().unwrap() as i64
                 ));
             }
             id if id == TypeId::of::<i16>() => {
-                return Some(FyfthVariant::Num(
-                    value.downcast_ref::<i16>().unwrap().clone() as f32,
+                return Some(FyfthVariant::Int(
+                    *value.downcast_ref::<i16>().unwrap() as i64
                 ));
             }
             id if id == TypeId::of::<i32>() => {
-                return Some(FyfthVariant::Num(
-                    value.downcast_ref::<i32>().unwrap().clone() as f32,
+                return Some(FyfthVariant::Int(
+                    *value.downcast_ref::<i32>().unwrap() as i64
                 ));
             }
             id if id == TypeId::of::<i64>() => {
-                return Some(FyfthVariant::Num(
-                    value.downcast_ref::<i64>().unwrap().clone() as f32,
-                ));
+                return Some(FyfthVariant::Int(*value.downcast_ref::<i64>().unwrap()));
             }
             id if id == TypeId::of::<isize>() => {
-                return Some(FyfthVariant::Num(
-                    value.downcast_ref::<isize>().unwrap().clone() as f32,
+                return Some(FyfthVariant::Int(
+                    *value.downcast_ref::<isize>().unwrap() as i64
                 ));
             }
             id if id == TypeId::of::<u8>() => {
-                return Some(FyfthVariant::Num(
-                    value.downcast_ref::<u8>().unwrap().clone() as f32,
+                return Some(FyfthVariant::Int(
+                    *value.downcast_ref::<u8>().unwrap() as i64
                 ));
             }
             id if id == TypeId::of::<u16>() => {
-                return Some(FyfthVariant::Num(
-                    value.downcast_ref::<u16>().unwrap().clone() as f32,
+                return Some(FyfthVariant::Int(
+                    *value.downcast_ref::<u16>().unwrap() as i64
                 ));
             }
             id if id == TypeId::of::<u32>() => {
-                return Some(FyfthVariant::Num(
-                    value.downcast_ref::<u32>().unwrap().clone() as f32,
+                return Some(FyfthVariant::Int(
+                    *value.downcast_ref::<u32>().unwrap() as i64
                 ));
             }
             id if id == TypeId::of::<u64>() => {
-                return Some(FyfthVariant::Num(
-                    value.downcast_ref::<u64>().unwrap().clone() as f32,
-                ));
+                let val = *value.downcast_ref::<u64>().unwrap();
+                // only values beyond the range of an int lose precision
+                return Some(match i64::try_from(val) {
+                    Ok(val) => FyfthVariant::Int(val),
+                    Err(_) => FyfthVariant::Num(val as f32),
+                });
             }
             id if id == TypeId::of::<usize>() => {
-                return Some(FyfthVariant::Num(
-                    value.downcast_ref::<usize>().unwrap().clone() as f32,
-                ));
+                let val = *value.downcast_ref::<usize>().unwrap();
+                // only values beyond the range of an int lose precision
+                return Some(match i64::try_from(val) {
+                    Ok(val) => FyfthVariant::Int(val),
+                    Err(_) => FyfthVariant::Num(val as f32),
+                });
             }
             id if id == TypeId::of::<String>() => {
                 return Some(FyfthVariant::Literal(
@@ -698,95 +2203,1448 @@ impl FyfthVariant {
         None
     }
 
+    /// Sets the field `field_name` of `value` to this value, converting numbers to the type of
+    /// the field. Ints are only set if they fit into the field exactly.
     pub(crate) fn try_set_reflect_field(
         &self,
         value: &mut dyn Struct,
         field_name: &str,
-    ) -> Result<(), ()> {
-        match self {
-            FyfthVariant::Bool(val) => {
-                *value.get_field_mut(field_name).ok_or(())? = *val;
-                return Ok(());
-            }
-            FyfthVariant::Literal(val) => {
-                *value.get_field_mut(field_name).ok_or(())? = val.clone();
-                return Ok(());
+    ) -> Result<(), FyfthError> {
+        let Some(field_type) = value.field(field_name).map(|field| field.type_id()) else {
+            return Err(FyfthErrorKind::UnknownField {
+                type_name: value.reflect_type_path().to_string(),
+                field: field_name.to_string(),
             }
-            FyfthVariant::Entity(val) => {
-                *value.get_field_mut(field_name).ok_or(())? = *val;
-                return Ok(());
+            .into());
+        };
+
+        let res = match self {
+            FyfthVariant::Bool(val) => set_field(value, field_name, *val),
+            FyfthVariant::Literal(val) => set_field(value, field_name, val.clone()),
+            FyfthVariant::Entity(val) => set_field(value, field_name, *val),
+            FyfthVariant::Vec2(val) => set_field(value, field_name, *val),
+            FyfthVariant::Vec3(val) => set_field(value, field_name, *val),
+            FyfthVariant::Quat(val) => set_field(value, field_name, *val),
+            // integer fields are set exactly, failing if the value does not fit
+            &FyfthVariant::Int(val) => match field_type {
+                id if id == TypeId::of::<f32>() => set_field(value, field_name, val as f32),
+                id if id == TypeId::of::<f64>() => set_field(value, field_name, val as f64),
+                id if id == TypeId::of::<i8>() => set_int_field::<i8>(value, field_name, val),
+                id if id == TypeId::of::<i16>() => set_int_field::<i16>(value, field_name, val),
+                id if id == TypeId::of::<i32>() => set_int_field::<i32>(value, field_name, val),
+                id if id == TypeId::of::<i64>() => set_field(value, field_name, val),
+                id if id == TypeId::of::<isize>() => set_int_field::<isize>(value, field_name, val),
+                id if id == TypeId::of::<u8>() => set_int_field::<u8>(value, field_name, val),
+                id if id == TypeId::of::<u16>() => set_int_field::<u16>(value, field_name, val),
+                id if id == TypeId::of::<u32>() => set_int_field::<u32>(value, field_name, val),
+                id if id == TypeId::of::<u64>() => set_int_field::<u64>(value, field_name, val),
+                id if id == TypeId::of::<usize>() => set_int_field::<usize>(value, field_name, val),
+                _ => None,
+            },
+            &FyfthVariant::Num(val) => match field_type {
+                id if id == TypeId::of::<f32>() => set_field(value, field_name, val),
+                id if id == TypeId::of::<f64>() => set_field(value, field_name, val as f64),
+                id if id == TypeId::of::<i8>() => set_field(value, field_name, val as i8),
+                id if id == TypeId::of::<i16>() => set_field(value, field_name, val as i16),
+                id if id == TypeId::of::<i32>() => set_field(value, field_name, val as i32),
+                id if id == TypeId::of::<i64>() => set_field(value, field_name, val as i64),
+                id if id == TypeId::of::<isize>() => set_field(value, field_name, val as isize),
+                id if id == TypeId::of::<u8>() => set_field(value, field_name, val as u8),
+                id if id == TypeId::of::<u16>() => set_field(value, field_name, val as u16),
+                id if id == TypeId::of::<u32>() => set_field(value, field_name, val as u32),
+                id if id == TypeId::of::<u64>() => set_field(value, field_name, val as u64),
+                id if id == TypeId::of::<usize>() => set_field(value, field_name, val as usize),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        res.ok_or_else(|| {
+            let value_desc = match self {
+                FyfthVariant::Bool(val) => val.to_string(),
+                FyfthVariant::Num(val) => val.to_string(),
+                FyfthVariant::Int(val) => val.to_string(),
+                FyfthVariant::Literal(val) => format!("\"{val}\""),
+                val => format!("a value of type `{}`", val.type_name()),
+            };
+            FyfthComponentError::SetFieldFailed {
+                component: value.reflect_type_path().to_string(),
+                field: field_name.to_string(),
+                field_type: value
+                    .field(field_name)
+                    .map_or("?", |field| field.reflect_type_path())
+                    .to_string(),
+                value: value_desc,
             }
-            FyfthVariant::Vec2(val) => {
-                *value.get_field_mut(field_name).ok_or(())? = *val;
-                return Ok(());
+            .into()
+        })
+    }
+}
+
+/// Sets the field `field_name` of `value`, returning `None` if it is not of type `T`.
+fn set_field<T: Reflect>(value: &mut dyn Struct, field_name: &str, val: T) -> Option<()> {
+    *value.get_field_mut::<T>(field_name)? = val;
+    Some(())
+}
+
+/// Like [`set_field`], but also returns `None` if `val` does not fit into a `T`.
+fn set_int_field<T: Reflect + TryFrom<i64>>(
+    value: &mut dyn Struct,
+    field_name: &str,
+    val: i64,
+) -> Option<()> {
+    set_field(value, field_name, T::try_from(val).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f32::consts::FRAC_PI_2, sync::Arc};
+
+    use bevy::prelude::*;
+
+    use crate::{
+        bevy_component::BevyComponentRegistry,
+        error::{FyfthComponentError, FyfthErrorKind},
+    };
+
+    use super::{find_imports, FyfthInterpreter, FyfthRunStatus, FyfthVariant};
+
+    #[test]
+    fn test_error_type_mismatch() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code(r#"1 "foo" sub"#).unwrap();
+        let (_, res) = interpreter.run(&mut world);
+        let err = res.unwrap_err();
+
+        assert_eq!(err.keyword.as_deref(), Some("sub"));
+        assert_eq!(
+            err.kind,
+            FyfthErrorKind::TypeMismatch {
+                expected: "num num, vec2 vec2 or vec3 vec3".to_string(),
+                found: vec!["int".to_string(), "literal".to_string()],
             }
-            FyfthVariant::Vec3(val) => {
-                *value.get_field_mut(field_name).ok_or(())? = *val;
-                return Ok(());
+        );
+        assert_eq!(
+            err.stack,
+            vec![
+                FyfthVariant::Int(1),
+                FyfthVariant::Literal("foo".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_stack_underflow() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code("1 swap").unwrap();
+        let (_, res) = interpreter.run(&mut world);
+        let err = res.unwrap_err();
+
+        assert_eq!(err.keyword.as_deref(), Some("swap"));
+        assert_eq!(
+            err.kind,
+            FyfthErrorKind::StackUnderflow {
+                expected: 2,
+                found: 1
             }
-            FyfthVariant::Quat(val) => {
-                *value.get_field_mut(field_name).ok_or(())? = *val;
-                return Ok(());
+        );
+    }
+
+    #[test]
+    fn test_error_span_in_macro() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter
+            .parse_file("macro foo\n  1 swap ;\n", "prelude.fy")
+            .unwrap();
+        interpreter.parse_code("$foo").unwrap();
+        let (_, res) = interpreter.run(&mut world);
+        let err = res.unwrap_err();
+
+        let span = err.span.clone().unwrap();
+        assert_eq!(&*span.file_name, "prelude.fy");
+        assert_eq!((span.line, span.column, span.len), (2, 5, 4));
+        assert!(err.to_string().ends_with("2 |   1 swap ;\n  |     ^^^^"));
+    }
+
+    #[test]
+    fn test_parse_error_queues_nothing() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        let errors = interpreter.parse_code("1 2 add @").unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0].kind, FyfthErrorKind::Syntax(_)));
+        assert_eq!(errors[0].span.as_ref().unwrap().column, 9);
+
+        let (_, res) = interpreter.run(&mut world);
+        res.unwrap();
+        assert!(interpreter.stack.is_empty());
+    }
+
+    #[test]
+    fn test_missing_prelude() {
+        let mut world = World::new();
+        let errors = FyfthInterpreter::try_new_with_prelude("does/not/exist.fy", &mut world)
+            .err()
+            .unwrap();
+
+        assert!(matches!(errors[0].kind, FyfthErrorKind::Io { .. }));
+    }
+
+    #[test]
+    fn test_list_literals() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter
+            .parse_code(r#"[1 [2 "a b"] *x] [] [1 2 add] push"#)
+            .unwrap();
+        let (_, res) = interpreter.run(&mut world);
+        res.unwrap();
+
+        let load = FyfthVariant::LangFunc(*interpreter.lang.keywords.get("load").unwrap());
+        assert_eq!(
+            interpreter.stack,
+            vec![
+                FyfthVariant::Iter(Arc::new(vec![
+                    FyfthVariant::Int(1),
+                    FyfthVariant::Iter(Arc::new(vec![
+                        FyfthVariant::Int(2),
+                        FyfthVariant::Literal("a b".to_string()),
+                    ])),
+                    FyfthVariant::Literal("x".to_string()),
+                    load,
+                ])),
+                FyfthVariant::Iter(default()),
+                FyfthVariant::Int(1),
+                FyfthVariant::Int(2),
+                FyfthVariant::LangFunc(*interpreter.lang.keywords.get("add").unwrap()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_list_literals() {
+        let mut interpreter = FyfthInterpreter::new();
+        let errors = interpreter.parse_code("[1 [2] ] ] [").unwrap_err();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].span.as_ref().unwrap().column, 10);
+        assert_eq!(errors[1].span.as_ref().unwrap().column, 12);
+    }
+
+    #[test]
+    fn test_maps() {
+        let literal = |val: &str| FyfthVariant::Literal(val.to_string());
+
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter
+            .parse_code(r#"{ a 1 "b c" [2 { d 3 }] } m store"#)
+            .unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(
+            interpreter.pretty_print_stack(&world, " "),
+            "",
+            "storing consumes the map"
+        );
+        assert_eq!(
+            interpreter.vars.get("m").map(|m| m.type_name()).as_deref(),
+            Some("map")
+        );
+
+        // keys are never run, even if they name keywords or macros
+        interpreter.parse_code("macro k 1 ;").unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        interpreter
+            .parse_code(r#"{ name "cube" filter 1 k 2 } keys"#)
+            .unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(
+            interpreter.stack.pop(),
+            Some(FyfthVariant::Iter(Arc::new(vec![
+                literal("filter"),
+                literal("k"),
+                literal("name")
+            ])))
+        );
+
+        // braces do not need to be separated from other words
+        let stack = run_to_stack(r#"{a 1 b{c 2}} dup {a 1 b {c 2}} eq"#);
+        assert_eq!(stack[1], FyfthVariant::Bool(true));
+
+        interpreter
+            .parse_code(r#"*m a get *m x get *m "b c" get 1 index d get *m [a x] has *m len"#)
+            .unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(
+            interpreter.stack,
+            [
+                FyfthVariant::Int(1),
+                FyfthVariant::Nil,
+                FyfthVariant::Int(3),
+                FyfthVariant::Iter(Arc::new(vec![
+                    FyfthVariant::Bool(true),
+                    FyfthVariant::Bool(false)
+                ])),
+                FyfthVariant::Int(2),
+            ]
+        );
+
+        // changing a map leaves its copies untouched
+        interpreter.stack.clear();
+        interpreter
+            .parse_code(r#"*m a 5 set x [1] insert "b c" remove dup keys swap values *m keys"#)
+            .unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(
+            interpreter.stack,
+            [
+                FyfthVariant::Iter(Arc::new(vec![literal("a"), literal("x")])),
+                FyfthVariant::Iter(Arc::new(vec![
+                    FyfthVariant::Int(5),
+                    FyfthVariant::Iter(Arc::new(ints(&[1])))
+                ])),
+                FyfthVariant::Iter(Arc::new(vec![literal("a"), literal("b c")])),
+            ]
+        );
+
+        interpreter.stack.clear();
+        interpreter
+            .parse_code(r#"{ a 1 b nil } dup dup { b nil a 1 } eqq swap { a 1.5 b nil } eq"#)
+            .unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(
+            interpreter.pretty_print_stack(&world, " "),
+            r#"{2 entries; "a": 1, "b": nil} true false"#
+        );
+
+        let errors = interpreter.parse_code("{ a } { 1 2 } { a 1 ]").unwrap_err();
+        let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(errors.len(), 4, "{messages:?}");
+        assert!(messages[0].contains("has no value"));
+        assert!(messages[1].contains("need to be literals"));
+        assert!(messages[2].contains("unmatched `]`"));
+        assert!(messages[3].contains("unclosed `{`"));
+    }
+
+    fn run_to_stack(code: &str) -> Vec<FyfthVariant> {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code(code).unwrap();
+        let (_, res) = interpreter.run(&mut world);
+        res.unwrap();
+        interpreter.stack
+    }
+
+    fn ints(vals: &[i64]) -> Vec<FyfthVariant> {
+        vals.iter().map(|&val| FyfthVariant::Int(val)).collect()
+    }
+
+    #[test]
+    fn test_if_else_then() {
+        assert_eq!(run_to_stack("true if 1 else 2 then 3"), ints(&[1, 3]));
+        assert_eq!(run_to_stack("false if 1 else 2 then 3"), ints(&[2, 3]));
+        assert_eq!(run_to_stack("false if 1 then 3"), ints(&[3]));
+        assert_eq!(
+            run_to_stack("false if true if 1 else 2 then else true if 3 else 4 then then"),
+            ints(&[3])
+        );
+    }
+
+    #[test]
+    fn test_recursion_with_if() {
+        let code = "macro fac dup 1 leq if pop 1 else dup -1 add $fac mul then ; 5 $fac";
+        assert_eq!(run_to_stack(code), ints(&[120]));
+    }
+
+    #[test]
+    fn test_times_and_while() {
+        assert_eq!(run_to_stack("1 3 [2 mul] times"), ints(&[8]));
+        assert_eq!(run_to_stack("1 0 [2 mul] times"), ints(&[1]));
+        assert_eq!(
+            run_to_stack("0 [dup 3 leq] [dup 1 add] while"),
+            ints(&[0, 1, 2, 3, 4])
+        );
+        assert_eq!(run_to_stack("0 2 [3 [1 add] times] times"), ints(&[6]));
+    }
+
+    #[test]
+    fn test_unbalanced_branches() {
+        let mut interpreter = FyfthInterpreter::new();
+        let errors = interpreter.parse_code("true if 1 else 2").unwrap_err();
+        assert_eq!(errors[0].keyword.as_deref(), Some("else"));
+        assert!(errors[0].to_string().contains("missing `then`"));
+
+        let errors = interpreter.parse_code("1 then").unwrap_err();
+        assert_eq!(errors[0].keyword.as_deref(), Some("then"));
+        assert!(interpreter.is_finished());
+    }
+
+    #[test]
+    fn test_compiled_code_is_not_copied() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code("macro f 1 2 ; f").unwrap();
+        interpreter.step(&mut world).1.unwrap();
+        interpreter.step(&mut world).1.unwrap();
+
+        // calling a macro runs its code in place
+        let frame = interpreter.calls.frames.last().unwrap();
+        assert!(Arc::ptr_eq(&frame.code, &interpreter.macros["f"].code));
+
+        // and so does every iteration of a loop
+        interpreter.parse_code("0 1000 [1 add] times").unwrap();
+        interpreter.run_with_fuel(&mut world, 500).1.unwrap();
+        assert_eq!(interpreter.calls.frames.len(), 2);
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(interpreter.stack, ints(&[1, 2, 1000]));
+    }
+
+    #[test]
+    fn test_iters_are_copied_on_write() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter
+            .parse_code("[[1 2] [3 4]] x store *x dup 0 5 set *x")
+            .unwrap();
+        interpreter.run(&mut world).1.unwrap();
+
+        let [FyfthVariant::Iter(lhs), FyfthVariant::Iter(changed), FyfthVariant::Iter(rhs)] =
+            interpreter.stack.as_slice()
+        else {
+            panic!("expected three iters, got {:?}", interpreter.stack);
+        };
+        let FyfthVariant::Iter(stored) = &interpreter.vars["x"] else {
+            panic!("expected an iter");
+        };
+
+        // copies share their values until one of them is changed
+        assert!(Arc::ptr_eq(lhs, rhs));
+        assert!(Arc::ptr_eq(lhs, stored));
+        assert_eq!(
+            **changed,
+            vec![
+                FyfthVariant::Iter(Arc::new(ints(&[5, 2]))),
+                FyfthVariant::Iter(Arc::new(ints(&[5, 4]))),
+            ]
+        );
+        assert_eq!(
+            **stored,
+            vec![
+                FyfthVariant::Iter(Arc::new(ints(&[1, 2]))),
+                FyfthVariant::Iter(Arc::new(ints(&[3, 4]))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lazy_ranges() {
+        let iter = |vals: &[i64]| vec![FyfthVariant::Iter(Arc::new(ints(vals)))];
+        assert_eq!(run_to_stack("0 10 3 range to_iter"), iter(&[0, 3, 6, 9]));
+        assert_eq!(run_to_stack("3 0 -1 range 1 add"), iter(&[4, 3, 2]));
+        assert_eq!(run_to_stack("3 enum 3 enum add"), iter(&[0, 2, 4]));
+        assert_eq!(
+            run_to_stack("0 1 0.25 range to_iter"),
+            vec![FyfthVariant::Iter(Arc::new(
+                [0.0, 0.25, 0.5, 0.75].map(FyfthVariant::Num).to_vec()
+            ))]
+        );
+        assert_eq!(run_to_stack("5 enum dup len swap 2 index"), ints(&[5, 2]));
+        assert_eq!(
+            run_to_stack("5 enum type"),
+            vec![FyfthVariant::Literal("lazy".to_string())]
+        );
+
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code("0 1 0 range").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("range"));
+
+        // ranges are limited in length instead of running out of memory
+        for code in ["0 1e30 1 range len", "0 9223372036854775807 1 range len"] {
+            let mut interpreter = FyfthInterpreter::new();
+            interpreter.parse_code(code).unwrap();
+            let err = interpreter.run(&mut world).1.unwrap_err();
+            assert_eq!(err.keyword.as_deref(), Some("range"));
+            assert!(err.to_string().contains("at most 1000000 values"));
+        }
+        assert_eq!(
+            run_to_stack("0 1000000 1 range len 1000000 enum len"),
+            ints(&[1_000_000, 1_000_000])
+        );
+    }
+
+    #[test]
+    fn test_int_promotion() {
+        assert_eq!(run_to_stack("1 2 add 7 2 mod -7 2 mod"), ints(&[3, 1, -1]));
+        assert_eq!(
+            run_to_stack("1 2.5 add 1 2 div 7.5 2 mod"),
+            [3.5, 0.5, 1.5].map(FyfthVariant::Num)
+        );
+        assert_eq!(
+            run_to_stack("1 1.0 eq [10 20 30] 1 1 add index"),
+            vec![FyfthVariant::Bool(true), FyfthVariant::Int(30)]
+        );
+
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code("9223372036854775807 1 add").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("add"));
+        assert!(err.to_string().contains("does not fit in an int"));
+
+        interpreter.parse_code("5 0 mod").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("mod"));
+        for (code, keyword) in [("5 0 div", "div"), ("0 -1 pow", "pow")] {
+            interpreter.parse_code(code).unwrap();
+            let err = interpreter.run(&mut world).1.unwrap_err();
+            assert_eq!(err.keyword.as_deref(), Some(keyword));
+        }
+        // nums follow floating point rules instead
+        assert_eq!(
+            run_to_stack("5.0 0 div 0.0 -1 pow"),
+            [f32::INFINITY, f32::INFINITY].map(FyfthVariant::Num)
+        );
+
+        // nums are only valid indices if they are whole numbers
+        interpreter.parse_code("[1 2] 1.0 index").unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(interpreter.stack().last(), Some(&FyfthVariant::Int(2)));
+        interpreter.parse_code("[1 2] 0.5 index").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert!(matches!(err.kind, FyfthErrorKind::TypeMismatch { .. }));
+    }
+
+    #[test]
+    fn test_math() {
+        assert_eq!(
+            run_to_stack("2 10 pow -3 abs 2.7 floor -2.5 ceil 2.5 round 7 0 5 clamp"),
+            ints(&[1024, 3, 2, -2, 3, 5])
+        );
+        assert_eq!(
+            run_to_stack("[3 8] 5 min"),
+            [FyfthVariant::Iter(Arc::new(ints(&[3, 5])))]
+        );
+        assert_eq!(
+            run_to_stack("16 sqrt 2 0.5 pow 2.0 2 pow 1 3 0.25 lerp 1.5 -0.5 0 clamp"),
+            [4.0, 2f32.sqrt(), 4.0, 1.5, 0.0].map(FyfthVariant::Num)
+        );
+        assert_eq!(
+            run_to_stack(
+                "3 4 vec2 length 0 0 0 vec3 3 4 0 vec3 distance 1 2 vec2 3 4 vec2 dot \
+                 1 0 0 vec3 0 1 0 vec3 cross 4 5 vec2 1 7 vec2 sub 0 3 0 vec3 normalize"
+            ),
+            vec![
+                FyfthVariant::Num(5.0),
+                FyfthVariant::Num(5.0),
+                FyfthVariant::Num(11.0),
+                FyfthVariant::Vec3(Vec3::Z),
+                FyfthVariant::Vec2(Vec2::new(3.0, -2.0)),
+                FyfthVariant::Vec3(Vec3::Y),
+            ]
+        );
+        assert_eq!(
+            run_to_stack("0 3 vec2 -4 -1 vec2 1 2 vec2 clamp 2.5 -1.2 0 vec3 floor"),
+            vec![
+                FyfthVariant::Vec2(Vec2::new(0.0, 2.0)),
+                FyfthVariant::Vec3(Vec3::new(2.0, -2.0, 0.0)),
+            ]
+        );
+
+        let approx = |code: &str, expected: Vec3| match run_to_stack(code).as_slice() {
+            [FyfthVariant::Vec3(val)] => assert!(val.abs_diff_eq(expected, 1e-5), "{val}"),
+            stack => panic!("unexpected stack {stack:?}"),
+        };
+        let quarter_z = "0 0 45 to_rad sin 45 to_rad cos quat";
+        approx(&format!("1 0 0 vec3 {quarter_z} rotate"), Vec3::Y);
+        approx(
+            &format!("1 0 0 vec3 {quarter_z} inverse rotate"),
+            Vec3::NEG_Y,
+        );
+        approx(
+            &format!("1 0 0 vec3 0 0 0 1 quat {quarter_z} 0.5 slerp rotate"),
+            Vec3::new(1.0, 1.0, 0.0).normalize(),
+        );
+        match run_to_stack("1 0 vec2 90 to_rad rotate 180 to_rad to_deg").as_slice() {
+            [FyfthVariant::Vec2(val), FyfthVariant::Num(deg)] => {
+                assert!(val.abs_diff_eq(Vec2::Y, 1e-5), "{val}");
+                assert!((deg - 180.0).abs() < 1e-4, "{deg}");
             }
-            FyfthVariant::Num(val) => {
-                return match value.field(field_name).ok_or(())?.type_id() {
-                    id if id == TypeId::of::<f32>() => {
-                        *value.get_field_mut(field_name).unwrap() = *val as f32;
-                        Ok(())
-                    }
-                    id if id == TypeId::of::<f64>() => {
-                        *value.get_field_mut(field_name).unwrap() = *val as f64;
-                        Ok(())
-                    }
-                    id if id == TypeId::of::<i8>() => {
-                        *value.get_field_mut(field_name).unwrap() = *val as i8;
-                        Ok(())
-                    }
-                    id if id == TypeId::of::<i16>() => {
-                        *value.get_field_mut(field_name).unwrap() = *val as i16;
-                        Ok(())
-                    }
-                    id if id == TypeId::of::<i32>() => {
-                        *value.get_field_mut(field_name).unwrap() = *val as i32;
-                        Ok(())
-                    }
-                    id if id == TypeId::of::<i64>() => {
-                        *value.get_field_mut(field_name).unwrap() = *val as i64;
-                        Ok(())
-                    }
-                    id if id == TypeId::of::<isize>() => {
-                        *value.get_field_mut(field_name).unwrap() = *val as isize;
-                        Ok(())
-                    }
-                    id if id == TypeId::of::<u8>() => {
-                        *value.get_field_mut(field_name).unwrap() = *val as u8;
-                        Ok(())
-                    }
-                    id if id == TypeId::of::<u16>() => {
-                        *value.get_field_mut(field_name).unwrap() = *val as u16;
-                        Ok(())
-                    }
-                    id if id == TypeId::of::<u32>() => {
-                        *value.get_field_mut(field_name).unwrap() = *val as u32;
-                        Ok(())
-                    }
-                    id if id == TypeId::of::<u64>() => {
-                        *value.get_field_mut(field_name).unwrap() = *val as u64;
-                        Ok(())
-                    }
-                    id if id == TypeId::of::<usize>() => {
-                        *value.get_field_mut(field_name).unwrap() = *val as usize;
-                        Ok(())
-                    }
-                    _ => Err(()),
-                }
+            stack => panic!("unexpected stack {stack:?}"),
+        }
+
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code("1 2 vec2 1 2 3 vec3 dot").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("dot"));
+
+        interpreter.parse_code("1e30 round").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("round"));
+    }
+
+    #[test]
+    fn test_stack_words() {
+        assert_eq!(run_to_stack("1 2 over"), ints(&[1, 2, 1]));
+        assert_eq!(run_to_stack("1 2 nip"), ints(&[2]));
+        assert_eq!(run_to_stack("1 2 tuck"), ints(&[2, 1, 2]));
+        assert_eq!(run_to_stack("1 2 3 0 pick 3 pick"), ints(&[1, 2, 3, 3, 1]));
+        assert_eq!(run_to_stack("1 2 3 2 roll 1 roll 0 roll"), ints(&[2, 1, 3]));
+        assert_eq!(run_to_stack("1 2 2dup"), ints(&[1, 2, 1, 2]));
+        assert_eq!(run_to_stack("1 2 3 4 2swap"), ints(&[3, 4, 1, 2]));
+        assert_eq!(run_to_stack("1 2 3 2drop"), ints(&[1]));
+        assert_eq!(run_to_stack("depth 7 7 depth"), ints(&[0, 7, 7, 3]));
+        assert_eq!(run_to_stack("1 2 3 clear 4"), ints(&[4]));
+
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        for (code, keyword, expected, found) in [
+            ("1 over", "over", 2, 1),
+            ("1 2 3 2swap", "2swap", 4, 3),
+            ("1 2 pick", "pick", 4, 2),
+            ("roll", "roll", 1, 0),
+            ("dup", "dup", 1, 0),
+            ("1 2 3 5 rotr", "rotr", 6, 4),
+            ("1 3 rotl", "rotl", 4, 2),
+            // huge counts saturate instead of overflowing
+            ("1e20 pick", "pick", 1 << 63 | 1, 1),
+            ("1 9223372036854775807 swap_n", "swap_n", 1 << 63 | 1, 2),
+        ] {
+            interpreter.stack.clear();
+            interpreter.parse_code(code).unwrap();
+            let err = interpreter.run(&mut world).1.unwrap_err();
+            assert_eq!(err.keyword.as_deref(), Some(keyword), "{code}");
+            assert_eq!(
+                err.kind,
+                FyfthErrorKind::StackUnderflow { expected, found },
+                "{code}"
+            );
+        }
+
+        // counts must be whole and not negative
+        assert_eq!(run_to_stack("1 2 3 1.0 swap_n"), ints(&[1, 3, 2]));
+        for (code, keyword) in [("1 2 3 -1 rotr", "rotr"), ("1 2 -1 swap_n", "swap_n")] {
+            interpreter.stack.clear();
+            interpreter.parse_code(code).unwrap();
+            let err = interpreter.run(&mut world).1.unwrap_err();
+            assert_eq!(err.keyword.as_deref(), Some(keyword), "{code}");
+            assert!(matches!(err.kind, FyfthErrorKind::Runtime(_)), "{code}");
+        }
+        interpreter.stack.clear();
+        interpreter.parse_code("1 2 0.5 pick").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert!(matches!(err.kind, FyfthErrorKind::TypeMismatch { .. }));
+
+        interpreter.stack.clear();
+        interpreter.parse_code("1 2 -1 pick").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("pick"));
+        assert_eq!(
+            err.stack,
+            ints(&[1, 2, -1]),
+            "a failed word leaves the stack as it was"
+        );
+    }
+
+    #[test]
+    fn test_return_and_saved_stacks() {
+        assert_eq!(run_to_stack("1 2 >r 3 r@ add r> 4 >r"), ints(&[1, 5, 2]));
+        assert_eq!(
+            run_to_stack("macro park >r 10 mul r> ; 1 2 3 park"),
+            ints(&[1, 20, 3])
+        );
+
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code("1 2 >r >r").unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(interpreter.return_stack(), ints(&[2, 1]));
+        interpreter.parse_code("r> r> r>").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("r>"));
+        assert_eq!(interpreter.stack(), ints(&[1, 2]));
+
+        interpreter.stack.clear();
+        interpreter
+            .parse_code("1 2 a save_stack 3 depth b save_stack a restore_stack")
+            .unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(interpreter.stack(), ints(&[1, 2]));
+        assert_eq!(interpreter.saved_stacks().get("b"), Some(&ints(&[3, 1])));
+        assert!(
+            interpreter.vars().is_empty(),
+            "saved stacks are not variables"
+        );
+
+        interpreter.parse_code("a restore_stack").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("restore_stack"));
+        assert_eq!(err.stack[2], FyfthVariant::Literal("a".to_string()));
+
+        // a module that fails drops the values it parked on the return stack
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.add_module_source("park.fy", "macro park >r swap r> ; 1 2 park 3 park");
+        assert!(interpreter.load_prelude("park.fy", &mut world).is_err());
+        assert!(interpreter.return_stack().is_empty());
+        interpreter.parse_code("r>").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("r>"));
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        let bools =
+            |vals: &[bool]| -> Vec<_> { vals.iter().map(|&val| FyfthVariant::Bool(val)).collect() };
+        assert_eq!(
+            run_to_stack("1 2 lt 2 2.0 gt 2 2.0 geq abc abd lt b a gt 1 1.0 neq a b neq"),
+            bools(&[true, false, true, true, true, false, true])
+        );
+        assert_eq!(
+            run_to_stack("[true false] true and [true false] false or true true xor"),
+            [
+                FyfthVariant::Iter(Arc::new(bools(&[true, false]))),
+                FyfthVariant::Iter(Arc::new(bools(&[true, false]))),
+                FyfthVariant::Bool(false),
+            ]
+        );
+        assert_eq!(
+            run_to_stack(
+                "0.3 0.3001 eq 0.3 0.3001 0.001 approx_eq \
+                 1 2 vec2 1.01 2 vec2 0.001 approx_eq 1 2 vec2 1.01 2 vec2 0.1 approx_eq \
+                 1 0 0 vec3 dup 180 to_rad axis_angle swap 90 to_rad axis_angle dup mul \
+                 1e-5 approx_eq a a 0 approx_eq"
+            ),
+            bools(&[false, true, false, true, true, true])
+        );
+
+        let mut world = World::new();
+        let entities = [world.spawn_empty().id(), world.spawn_empty().id()];
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.stack = entities.map(FyfthVariant::Entity).to_vec();
+        interpreter.parse_code("lt").unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(interpreter.stack, bools(&[entities[0] < entities[1]]));
+
+        interpreter.stack.clear();
+        interpreter.parse_code("1 a lt").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("lt"));
+
+        interpreter.parse_code("true 1 and").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("and"));
+    }
+
+    #[test]
+    fn test_rotations() {
+        let quat = |code: &str| match run_to_stack(code).as_slice() {
+            [FyfthVariant::Quat(val)] => *val,
+            stack => panic!("unexpected stack {stack:?}"),
+        };
+        let vec3 = |code: &str| match run_to_stack(code).as_slice() {
+            [FyfthVariant::Vec3(val)] => *val,
+            stack => panic!("unexpected stack {stack:?}"),
+        };
+
+        // the angles are given per axis, whatever the order they are applied in
+        let expected = Quat::from_rotation_z(0.3) * Quat::from_rotation_y(0.2);
+        let rot = quat("0 0.2 0.3 vec3 ZYX euler");
+        assert!(rot.abs_diff_eq(expected, 1e-5), "{rot}");
+        let rot = quat("0 0.2 0.3 vec3 yzx euler");
+        assert!(!rot.abs_diff_eq(expected, 1e-5), "{rot}");
+
+        let angles = vec3("10 20 30 vec3 to_rad XZY euler XZY to_euler to_deg");
+        assert!(
+            angles.abs_diff_eq(Vec3::new(10.0, 20.0, 30.0), 1e-3),
+            "{angles}"
+        );
+
+        let rot = quat("0 0 2 vec3 90 to_rad axis_angle");
+        assert!(
+            rot.abs_diff_eq(Quat::from_rotation_z(FRAC_PI_2), 1e-5),
+            "{rot}"
+        );
+
+        // the forward direction -z ends up pointing along the direction
+        let forward = vec3("0 0 -1 vec3 1 0 0 vec3 0 1 0 vec3 look_to rotate");
+        assert!(forward.abs_diff_eq(Vec3::X, 1e-5), "{forward}");
+
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code("0 0 0 1 quat XXY to_euler").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("to_euler"));
+
+        interpreter.parse_code("0 0 0 vec3 1 axis_angle").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("axis_angle"));
+    }
+
+    #[test]
+    fn test_string_commands() {
+        let literal = |val: &str| FyfthVariant::Literal(val.to_string());
+        let bools = |vals: &[bool]| {
+            let vals = vals.iter().map(|&val| FyfthVariant::Bool(val)).collect();
+            FyfthVariant::Iter(Arc::new(vals))
+        };
+
+        assert_eq!(
+            run_to_stack(r#""  Hello World " trim upper "a,b,c" "," split "-" join"#),
+            [literal("HELLO WORLD"), literal("a-b-c")]
+        );
+        assert_eq!(
+            run_to_stack(r#""héllo" 1 3 substr "héllo" -1 char_at "a.b" "." "::" replace"#),
+            [literal("éll"), literal("o"), literal("a::b")]
+        );
+        assert_eq!(
+            run_to_stack(r#"[1 2.5 "x"] ", " join"#),
+            [literal("1, 2.5, x")]
+        );
+        assert_eq!(
+            run_to_stack(r#""héllo" str_len "12" parse_num " 1.5" parse_num "x" parse_num"#),
+            [
+                FyfthVariant::Int(5),
+                FyfthVariant::Int(12),
+                FyfthVariant::Num(1.5),
+                FyfthVariant::Nil
+            ]
+        );
+
+        // broadcasting over names, where unnamed entities have `nil` as their name
+        assert_eq!(
+            run_to_stack(r#"["foo" "bar" nil] dup "ba" starts_with swap upper"#),
+            [
+                bools(&[false, true, false]),
+                FyfthVariant::Iter(Arc::new(vec![
+                    literal("FOO"),
+                    literal("BAR"),
+                    FyfthVariant::Nil
+                ]))
+            ]
+        );
+
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code(r#""abc" 2 5 substr"#).unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("substr"));
+    }
+
+    #[test]
+    fn test_reflected_ints_round_trip() {
+        #[derive(Reflect, Default)]
+        struct Counter {
+            ticks: u64,
+            offset: i8,
+        }
+
+        // too large to be represented exactly by a num
+        let ticks = i64::MAX as u64 - 1;
+        let registry = BevyComponentRegistry::default();
+        let val = FyfthVariant::try_reflect_from_type_id(&ticks, &registry).unwrap();
+        assert_eq!(val, FyfthVariant::Int(ticks as i64));
+
+        let mut counter = Counter::default();
+        val.try_set_reflect_field(&mut counter, "ticks").unwrap();
+        assert_eq!(counter.ticks, ticks);
+
+        // ints that do not fit into a field are not truncated
+        assert!(FyfthVariant::Int(300)
+            .try_set_reflect_field(&mut counter, "offset")
+            .is_err());
+        assert_eq!(counter.offset, 0);
+
+        let err = FyfthVariant::Int(-1)
+            .try_set_reflect_field(&mut counter, "ticks")
+            .unwrap_err();
+        let FyfthErrorKind::Component(component_err) = &err.kind else {
+            panic!("expected a component error, got {:?}", err.kind);
+        };
+        let FyfthComponentError::SetFieldFailed {
+            field,
+            field_type,
+            value,
+            ..
+        } = &**component_err
+        else {
+            panic!("expected a failure to set the field, got {:?}", err.kind);
+        };
+        assert_eq!((field.as_str(), field_type.as_str()), ("ticks", "u64"));
+        assert_eq!(value, "-1");
+        assert!(err.to_string().contains("to -1"));
+        assert_eq!(counter.ticks, ticks);
+
+        let err = FyfthVariant::Int(1)
+            .try_set_reflect_field(&mut counter, "missing")
+            .unwrap_err();
+        assert!(matches!(err.kind, FyfthErrorKind::UnknownField { .. }));
+    }
+
+    #[test]
+    fn test_lazy_views() {
+        assert_eq!(
+            run_to_stack("5 enum [2 mul] lazy_map [3 leq] lazy_filter push"),
+            ints(&[0, 2])
+        );
+        assert_eq!(
+            run_to_stack("[1 2 3] [10 mul] lazy_map 1 add to_iter"),
+            vec![FyfthVariant::Iter(Arc::new(ints(&[11, 21, 31])))]
+        );
+
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        // the length of a filtered view is only known once it has been stepped through
+        interpreter
+            .parse_code("4 enum [2 mod 0 eq] lazy_filter 3 enum add")
+            .unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert!(err.to_string().contains("differing lengths"));
+
+        // views can only run commands, not control flow
+        interpreter.parse_code("[1 2] [dup] lazy_map").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("lazy_map"));
+
+        // a single command cannot consume lazy iterators for arbitrarily long
+        interpreter
+            .parse_code("20 enum [1000000 enum add len] lazy_map 1 add")
+            .unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("add"));
+        assert!(err.to_string().contains("in a single step"));
+    }
+
+    #[test]
+    fn test_lazy_entities() {
+        let mut world = World::new();
+        world.spawn(Name::new("a"));
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code("entities x store *x len").unwrap();
+        interpreter.run(&mut world).1.unwrap();
+
+        // stored lazy iterators are evaluated again whenever they are used
+        world.spawn(Name::new("b"));
+        interpreter.parse_code("*x len *x name to_iter").unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(interpreter.stack[..2], ints(&[1, 2]));
+        let names = ["a", "b"].map(|name| FyfthVariant::Literal(name.to_string()));
+        assert_eq!(
+            interpreter.stack[2],
+            FyfthVariant::Iter(Arc::new(names.to_vec()))
+        );
+
+        // filtering entities by broadcasting over them, like the `entity` macro of the prelude
+        interpreter
+            .parse_code(r#"entities dup "b" swap name eq filter 0 index name"#)
+            .unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(interpreter.stack[3], FyfthVariant::Literal("b".to_string()));
+    }
+
+    #[test]
+    fn test_format() {
+        let mut world = World::new();
+        let cube = world.spawn(Name::new("cube")).id();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter
+            .parse_code(
+                r#"entities 0 index 1 2.5 3 vec3 "{} is at {:.2}" format
+                "x" 1.5 [1 0.34] "{{{}}} {} {:.1}" format"#,
+            )
+            .unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(
+            interpreter.stack,
+            [
+                FyfthVariant::Literal(format!("({cube} - \"cube\") is at vec3(1.00 2.50 3.00)")),
+                FyfthVariant::Literal("{x} 1.5 [2 items; 1, 0.3]".to_string()),
+            ]
+        );
+
+        // the template decides how many values are needed
+        interpreter.stack.clear();
+        interpreter.parse_code(r#"1 "{} {}" format"#).unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert_eq!(err.keyword.as_deref(), Some("format"));
+        assert!(matches!(
+            err.kind,
+            FyfthErrorKind::StackUnderflow {
+                expected: 3,
+                found: 2
             }
-            FyfthVariant::Component(_) => todo!(),
-            FyfthVariant::Iter(_) => Err(())?,
-            FyfthVariant::Nil => panic!("Cannot set field to nil"),
-            _ => panic!("Cannot set field to non-value type `FyfthVariant`"),
+        ));
+
+        interpreter.stack.clear();
+        interpreter.parse_code(r#"1 "{:x}" format"#).unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert!(err.to_string().contains("invalid placeholder"));
+    }
+
+    #[test]
+    fn test_if_requires_bool() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code("1 if 2 then").unwrap();
+        let (_, res) = interpreter.run(&mut world);
+        let err = res.unwrap_err();
+
+        assert_eq!(err.keyword.as_deref(), Some("if"));
+    }
+
+    #[test]
+    fn test_locals() {
+        let code = "macro f {: a b :} *a *b sub ; 5 2 $f";
+        assert_eq!(run_to_stack(code), ints(&[3]));
+
+        // locals shadow globals and assigning to a local leaves the global untouched
+        let code = "1 x store macro f 2 {: x :} 3 x store *x ; $f *x";
+        assert_eq!(run_to_stack(code), ints(&[3, 1]));
+
+        // a macro cannot see the locals of its caller
+        let code = "1 x store macro g *x ; macro f 2 {: x :} $g *x ; $f";
+        assert_eq!(run_to_stack(code), ints(&[1, 2]));
+
+        // every invocation gets its own frame
+        let code = "macro fac {: n :} *n 1 leq if 1 else *n -1 add $fac *n mul then ; 5 $fac";
+        assert_eq!(run_to_stack(code), ints(&[120]));
+    }
+
+    #[test]
+    fn test_locals_outside_of_macro() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code("1 {: a :}").unwrap();
+        let (_, res) = interpreter.run(&mut world);
+        let err = res.unwrap_err();
+
+        assert_eq!(err.keyword.as_deref(), Some("{:"));
+    }
+
+    #[test]
+    fn test_bare_macro_calls() {
+        assert_eq!(run_to_stack("macro sq dup mul ; 3 sq"), ints(&[9]));
+        assert_eq!(run_to_stack("macro f 1 ; macro f 2 ; f"), ints(&[2]));
+        assert_eq!(
+            run_to_stack("macro fac dup 1 leq if pop 1 else dup -1 add fac mul then ; 4 fac"),
+            ints(&[24])
+        );
+
+        // keywords win over macros of the same name
+        let stack = run_to_stack(r#"macro "add" 42 ; 1 2 add $add"#);
+        assert_eq!(stack, ints(&[3, 42]));
+
+        // only variables holding a list shadow a macro
+        let stack = run_to_stack(r#"macro foo 1 ; 5 "foo" store foo"#);
+        assert_eq!(stack, ints(&[1]));
+        let stack = run_to_stack(r#"macro foo 1 ; [2] "foo" store foo"#);
+        assert_eq!(stack, ints(&[2]));
+
+        // a macro has to be defined before it can be called by its bare name
+        let stack = run_to_stack("foo macro foo 1 ;");
+        assert_eq!(stack, vec![FyfthVariant::Literal("foo".to_string())]);
+
+        // macros defined in previous runs are known as well
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code("macro sq dup mul ;").unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        interpreter.parse_code("3 sq").unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(interpreter.stack, ints(&[9]));
+    }
+
+    #[test]
+    fn test_imports() {
+        let dir = std::env::temp_dir().join(format!("fyfth_test_imports_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(
+            dir.join("main.fy"),
+            r#""lib/math.fy" math import_as "lib/math.fy" import 3 math.sq"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("lib/math.fy"),
+            "1 macro mul_self dup mul ; macro sq mul_self ;",
+        )
+        .unwrap();
+        std::fs::write(dir.join("lib/a.fy"), r#""b.fy" import"#).unwrap();
+        std::fs::write(dir.join("lib/b.fy"), r#""a.fy" import"#).unwrap();
+
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        let main = dir.join("main.fy").display().to_string();
+        interpreter.load_prelude(&main, &mut world).unwrap();
+
+        // the module only ran once and its macros are namespaced
+        assert_eq!(interpreter.stack, ints(&[1, 9]));
+        assert!(interpreter.macros.contains_key("math.mul_self"));
+        assert!(!interpreter.macros.contains_key("sq"));
+
+        let a = dir.join("lib/a.fy").display().to_string();
+        let errors = interpreter.load_prelude(&a, &mut world).unwrap_err();
+        assert_eq!(errors[0].keyword.as_deref(), Some("import"));
+        assert!(errors[0].to_string().contains("circular import"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_reload_module() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.add_module_source("lib/util.fy", "macro one 1 ; macro two 2 ;");
+        interpreter.add_module_source(
+            "scripts/main.fy",
+            r#""../lib/util.fy" util import_as macro three 3 ;"#,
+        );
+        interpreter
+            .load_prelude("scripts/./main.fy", &mut world)
+            .unwrap();
+        assert!(interpreter.macros.contains_key("util.two"));
+
+        // macros that are no longer defined are removed, the others are kept
+        interpreter.add_module_source("lib/util.fy", "macro one 11 ;");
+        assert!(interpreter
+            .reload_module("lib/util.fy", &mut world)
+            .unwrap());
+        assert!(!interpreter.macros.contains_key("util.two"));
+        assert!(interpreter.macros.contains_key("three"));
+        interpreter.parse_code("util.one").unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(interpreter.stack, ints(&[11]));
+
+        // a module that fails to parse does not change anything
+        interpreter.add_module_source("lib/util.fy", "macro one [ ;");
+        assert!(interpreter
+            .reload_module("lib/util.fy", &mut world)
+            .is_err());
+        assert!(interpreter.macros.contains_key("util.one"));
+
+        assert!(!interpreter
+            .reload_module("lib/other.fy", &mut world)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_asset_relative_imports() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.set_module_sources_only(true);
+        interpreter.add_module_source("scripts/lib/math.fy", "macro sq dup mul ;");
+        interpreter.add_module_source(
+            "scripts/main.fy",
+            r#""lib/math.fy" math import_as 3 math.sq"#,
+        );
+        interpreter.add_module_source(
+            "scripts/dynamic.fy",
+            r#""lib/other.fy" "path" store *path import"#,
+        );
+
+        // imports are relative to the importing script, not the working directory
+        interpreter
+            .load_prelude("scripts/main.fy", &mut world)
+            .unwrap();
+        assert_eq!(interpreter.stack, ints(&[9]));
+
+        // modules without a source are not read from the file system
+        let errors = interpreter
+            .load_prelude("scripts/dynamic.fy", &mut world)
+            .unwrap_err();
+        assert_eq!(errors[0].keyword.as_deref(), Some("import"));
+        let FyfthErrorKind::Io { path, message } = &errors[0].kind else {
+            panic!("expected an io error, got {:?}", errors[0].kind);
+        };
+        assert_eq!(path, "scripts/lib/other.fy");
+        assert!(message.contains("not been loaded as an asset"));
+
+        let errors = interpreter
+            .load_prelude("scripts/missing.fy", &mut world)
+            .unwrap_err();
+        assert!(matches!(errors[0].kind, FyfthErrorKind::Io { .. }));
+    }
+
+    #[test]
+    fn test_find_imports() {
+        let imports = find_imports(r#""a.fy" import "b.fy" b import_as "c.fy" *ns import_as"#);
+        assert_eq!(imports, vec!["a.fy".to_string(), "b.fy".to_string()]);
+    }
+
+    #[test]
+    fn test_run_with_fuel() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.parse_code("0 1000 [1 add] times").unwrap();
+
+        let (_, res) = interpreter.run_with_fuel(&mut world, 100);
+        assert_eq!(res.unwrap(), FyfthRunStatus::Suspended);
+        assert!(!interpreter.is_finished());
+
+        let mut runs = 1;
+        while interpreter.run_with_fuel(&mut world, 100).1.unwrap() == FyfthRunStatus::Suspended {
+            runs += 1;
         }
+        assert!(runs > 10);
+        assert!(interpreter.is_finished());
+        assert_eq!(interpreter.stack, ints(&[1000]));
+
+        interpreter.parse_code("0 100000 [1 add] times").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert!(matches!(err.kind, FyfthErrorKind::IterationLimit(_)));
+    }
+
+    #[test]
+    fn test_higher_order_words() {
+        let iter = |vals: Vec<FyfthVariant>| FyfthVariant::Iter(Arc::new(vals));
+        let literal = |val: &str| FyfthVariant::Literal(val.to_string());
+
+        assert_eq!(
+            run_to_stack("[3 1 2] [2 mul] map [1 2 3 4] [2 mod 0 eq] filter_by"),
+            [iter(ints(&[6, 2, 4])), iter(ints(&[2, 4]))]
+        );
+        assert_eq!(
+            run_to_stack("[1 2 3] 10 [add] fold [1 2 3 4] [mul] reduce"),
+            ints(&[16, 24])
+        );
+        assert_eq!(
+            run_to_stack(r#"["bb" "a" "ccc"] [str_len] sort_by [3 1.5 2] sort"#),
+            [
+                iter(vec![literal("a"), literal("bb"), literal("ccc")]),
+                iter(vec![
+                    FyfthVariant::Num(1.5),
+                    FyfthVariant::Int(2),
+                    FyfthVariant::Int(3)
+                ]),
+            ]
+        );
+        assert_eq!(
+            run_to_stack("[1 2 3] [2 geq] any [1 2 3] [2 geq] all [] [2 geq] all"),
+            [
+                FyfthVariant::Bool(true),
+                FyfthVariant::Bool(false),
+                FyfthVariant::Bool(true)
+            ]
+        );
+        assert_eq!(
+            run_to_stack("[1 2 3 4 5] [2 mod] group_by"),
+            [iter(vec![
+                iter(vec![FyfthVariant::Int(1), iter(ints(&[1, 3, 5]))]),
+                iter(vec![FyfthVariant::Int(0), iter(ints(&[2, 4]))]),
+            ])]
+        );
+        assert_eq!(
+            run_to_stack("[1 2] [a b] zip [[1 2] 3 [4]] flatten [1 2 1 1.0 3] unique"),
+            [
+                iter(vec![
+                    iter(vec![FyfthVariant::Int(1), literal("a")]),
+                    iter(vec![FyfthVariant::Int(2), literal("b")]),
+                ]),
+                iter(ints(&[1, 2, 3, 4])),
+                iter(ints(&[1, 2, 3])),
+            ]
+        );
+
+        // the code can use macros, control flow, other higher-order words and lazy iterators
+        assert_eq!(
+            run_to_stack(
+                "macro sq dup mul ; [-1 2 -3] [dup 0 leq if -1 mul then sq] map \
+                 [[1 2] [3]] [[1 add] map] map 3 enum [] map"
+            ),
+            [
+                iter(ints(&[1, 4, 9])),
+                iter(vec![iter(ints(&[2, 3])), iter(ints(&[4]))]),
+                iter(ints(&[0, 1, 2])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_higher_order_words_errors() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+
+        for (code, keyword, message) in [
+            ("[1 2] [dup] map", "map", "exactly one value"),
+            ("[1 2] [1 add] filter_by", "filter_by", "`bool`"),
+            ("[] [add] reduce", "reduce", "empty iter"),
+            (r#"[1 "a"] sort"#, "sort", "expected"),
+            ("[1] 2 map", "map", "`iter iter`"),
+        ] {
+            interpreter.parse_code(code).unwrap();
+            let err = interpreter.run(&mut world).1.unwrap_err();
+            assert_eq!(err.keyword.as_deref(), Some(keyword), "{code}");
+            assert!(err.to_string().contains(message), "{code}: {err}");
+            interpreter = FyfthInterpreter::new();
+        }
+
+        // the code runs step by step, so it can be suspended and counts towards the step limit
+        interpreter.parse_code("[1 2 3] [1 add] map").unwrap();
+        let (_, res) = interpreter.run_with_fuel(&mut world, 3);
+        assert_eq!(res.unwrap(), FyfthRunStatus::Suspended);
+        while interpreter.run_with_fuel(&mut world, 3).1.unwrap() == FyfthRunStatus::Suspended {}
+        assert_eq!(
+            interpreter.stack,
+            [FyfthVariant::Iter(Arc::new(ints(&[2, 3, 4])))]
+        );
+
+        interpreter.parse_code("[1] [[true] [] while] map").unwrap();
+        let err = interpreter.run(&mut world).1.unwrap_err();
+        assert!(matches!(err.kind, FyfthErrorKind::IterationLimit(_)));
+    }
 
-        Err(())
+    #[test]
+    fn test_breakpoints() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.add_breakpoint("sq");
+        interpreter.add_breakpoint("add");
+        interpreter
+            .parse_code("macro sq dup mul ; 3 sq 1 add")
+            .unwrap();
+
+        let (_, res) = interpreter.run_with_fuel(&mut world, 1000);
+        assert_eq!(res.unwrap(), FyfthRunStatus::Paused);
+        assert_eq!(interpreter.stack(), ints(&[3]));
+        assert_eq!(
+            interpreter.pretty_print_queue(&world, 2, " "),
+            "$sq 1".to_string()
+        );
+
+        // stepping runs a single value, even if it has a breakpoint
+        let (_, res) = interpreter.step(&mut world);
+        assert_eq!(res.unwrap(), FyfthRunStatus::Suspended);
+        let (_, res) = interpreter.step(&mut world);
+        assert_eq!(res.unwrap(), FyfthRunStatus::Suspended);
+        assert_eq!(interpreter.stack(), ints(&[3, 3]));
+
+        let (_, res) = interpreter.run_with_fuel(&mut world, 1000);
+        assert_eq!(res.unwrap(), FyfthRunStatus::Paused);
+        assert_eq!(interpreter.stack(), ints(&[9, 1]));
+
+        assert!(interpreter.remove_breakpoint("add"));
+        let (_, res) = interpreter.run_with_fuel(&mut world, 1000);
+        assert_eq!(res.unwrap(), FyfthRunStatus::Finished);
+        assert_eq!(interpreter.stack(), ints(&[10]));
+
+        // `run` ignores breakpoints
+        interpreter.parse_code("sq").unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(interpreter.stack(), ints(&[100]));
+    }
+
+    #[test]
+    fn test_run_after_error() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter
+            .parse_code("macro f {: a :} 1 swap 2 ; 0 f 3")
+            .unwrap();
+        interpreter.parse_code("4").unwrap();
+        assert!(interpreter.run(&mut world).1.is_err());
+        assert!(interpreter.locals().is_none());
+
+        // the failed code does not resume, but code queued after it still runs
+        interpreter.parse_code("5").unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(interpreter.stack(), ints(&[1, 4, 5]));
+
+        // a failing module only drops its own code
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.add_module_source("lib.fy", "6");
+        interpreter.parse_code("1 swap").unwrap();
+        interpreter.parse_code("2").unwrap();
+        assert!(interpreter.load_prelude("lib.fy", &mut world).is_err());
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(interpreter.stack(), ints(&[1, 2]));
+    }
+
+    #[test]
+    fn test_apply_run() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.add_module_source("lib.fy", "macro m 1 ; macro gone 2 ;");
+        interpreter.parse_code("\"lib.fy\" import").unwrap();
+        interpreter.run(&mut world).1.unwrap();
+
+        let base = interpreter.clone();
+        let mut run = interpreter.clone();
+        run.parse_code("macro n 3 ; 4 x store m").unwrap();
+
+        // the module is reloaded while the run is still going
+        interpreter.add_module_source("lib.fy", "macro m 5 ;");
+        assert!(interpreter.reload_module("lib.fy", &mut world).unwrap());
+        run.run(&mut world).1.unwrap();
+        interpreter.apply_run(&base, run);
+
+        interpreter.parse_code("m n *x").unwrap();
+        interpreter.run(&mut world).1.unwrap();
+        assert_eq!(interpreter.stack(), ints(&[1, 5, 3, 4]));
+        assert!(!interpreter.macros.contains_key("gone"));
+    }
+
+    #[test]
+    fn test_tracing() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.start_tracing();
+        interpreter
+            .parse_code("macro sq dup mul ; 3 sq sq")
+            .unwrap();
+        interpreter.run(&mut world).1.unwrap();
+
+        let trace = interpreter.stop_tracing().unwrap();
+        assert!(interpreter.trace().is_none());
+        assert_eq!(trace.macros["sq"].calls, 2);
+        assert_eq!(trace.keywords["dup"].calls, 2);
+        assert_eq!(trace.keywords["mul"].calls, 2);
+        assert!(!trace.keywords.contains_key("sq"));
+        assert!(trace.macros["sq"].total_time >= trace.keywords["mul"].total_time);
+
+        let step = trace
+            .steps
+            .iter()
+            .find(|step| step.value == FyfthVariant::Int(3))
+            .unwrap();
+        assert_eq!((step.stack_depth_before, step.stack_depth_after), (0, 1));
+
+        let table = trace.pretty_print_table();
+        assert!(table.lines().any(|line| line.starts_with("sq ")));
+    }
+
+    #[test]
+    fn test_tracing_after_error() {
+        let mut world = World::new();
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.start_tracing();
+        interpreter
+            .parse_code("macro fail 1 swap swap ; macro outer fail ; outer")
+            .unwrap();
+        assert!(interpreter.run(&mut world).1.is_err());
+
+        // the failed run still counts towards the macros that were running
+        let trace = interpreter.stop_tracing().unwrap();
+        assert_eq!(trace.macros["outer"].calls, 1);
+        assert!(trace.macros["outer"].total_time >= trace.keywords["swap"].total_time);
+        let outer_time = trace.macros["outer"].total_time;
+
+        // finishing a macro that started before tracing does not charge the failed macros
+        let mut interpreter = FyfthInterpreter::new();
+        interpreter.add_breakpoint("mul");
+        interpreter.parse_code("macro sq dup mul ; 3 sq").unwrap();
+        let status = interpreter.run_with_fuel(&mut world, 1000).1;
+        assert_eq!(status.unwrap(), FyfthRunStatus::Paused);
+
+        interpreter.continue_tracing(trace);
+        let status = interpreter.run_with_fuel(&mut world, 1000).1;
+        assert_eq!(status.unwrap(), FyfthRunStatus::Finished);
+
+        let trace = interpreter.stop_tracing().unwrap();
+        assert_eq!(trace.macros["outer"].total_time, outer_time);
+        assert_eq!(trace.keywords["mul"].calls, 1);
     }
 }
@@ -1,4 +1,4 @@
-use std::fmt::Write;
+use std::{cmp::Ordering, fmt::Write, sync::Arc};
 
 use bevy::prelude::*;
 use bevy::utils::HashMap;
@@ -7,7 +7,9 @@ use regex::Regex;
 
 use crate::{
     bevy_component::{BevyComponentRegistry, BevyComponentRegistryError},
+    error::{FyfthComponentError, FyfthError, FyfthErrorKind},
     interpreter::{FyfthContext, FyfthVariant},
+    lazy::{FyfthLazyIter, RANGE_LIMIT},
     util, FyfthIgnoreEntity,
 };
 
@@ -26,8 +28,12 @@ pub(crate) struct FnInfo {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FyfthBroadcastBehavior {
+    /// The argument is passed as it is, with lazy iterators collected into an iter first.
     IgnoreIter,
+    /// The command is run for every value if the argument is an iter or a lazy iterator.
     MayIter,
+    /// The argument is passed as it is, without collecting lazy iterators.
+    KeepLazy,
 }
 
 #[derive(Debug, Clone)]
@@ -36,9 +42,10 @@ pub(crate) struct PrefixInfo {
     pub(crate) fn_ptr: FyfthPrefixParserFnPtr,
 }
 
-pub type FyfthFuncFnPtr = fn(FyfthContext, &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()>;
+pub type FyfthFuncFnPtr =
+    fn(FyfthContext, &[FyfthVariant]) -> Result<Option<FyfthVariant>, FyfthError>;
 pub type FyfthPrefixParserFnPtr =
-    fn(&str, &FyfthLanguageExtension) -> Result<Vec<FyfthVariant>, ()>;
+    fn(&str, &FyfthLanguageExtension) -> Result<Vec<FyfthVariant>, FyfthError>;
 
 #[derive(Debug, Clone)]
 pub(crate) struct SimpleFunc {
@@ -62,19 +69,23 @@ impl FyfthLanguageExtension {
         self.keywords.get(keyword).copied()
     }
 
-    pub fn merge(&mut self, other: Self) -> Result<(), ()> {
+    pub fn merge(&mut self, other: Self) -> Result<(), FyfthError> {
         // Make sure the other language extension does not conflict with this one
-        if other.keywords.keys().any(|k| self.keywords.contains_key(k)) {
+        if let Some(keyword) = other
+            .keywords
+            .keys()
+            .find(|k| self.keywords.contains_key(*k))
+        {
             // Colliding keywords!
-            return Err(());
+            return Err(FyfthErrorKind::KeywordCollision(keyword.clone()).into());
         }
-        if other
+        if let Some(prefix) = other
             .prefixes
             .iter()
-            .any(|op| self.prefixes.iter().any(|sp| op.ch == sp.ch))
+            .find(|op| self.prefixes.iter().any(|sp| op.ch == sp.ch))
         {
             // Colliding prefixes!
-            return Err(());
+            return Err(FyfthErrorKind::PrefixCollision(prefix.ch).into());
         }
 
         let FyfthLanguageExtension {
@@ -122,6 +133,10 @@ impl FyfthLanguageExtension {
             panic!("FyfthLanguageExtension::with_prefix: prefix cannot be `\"`");
         }
 
+        if matches!(prefix_char, '[' | ']' | '{' | '}' | ':') {
+            panic!("FyfthLanguageExtension::with_prefix: prefix cannot be `{prefix_char}`");
+        }
+
         if self.prefixes.iter().any(|pi| pi.ch == prefix_char) {
             panic!("FyfthLanguageExtension::with_prefix: `{prefix_char}` already in use");
         }
@@ -200,7 +215,7 @@ impl FyfthLanguageExtension {
                 "store",
                 fyfth_func_store,
                 &[
-                    FyfthBroadcastBehavior::IgnoreIter,
+                    FyfthBroadcastBehavior::KeepLazy,
                     FyfthBroadcastBehavior::IgnoreIter,
                 ],
             )
@@ -222,6 +237,22 @@ impl FyfthLanguageExtension {
                     FyfthBroadcastBehavior::MayIter,
                 ],
             )
+            .with_command(
+                "gt",
+                fyfth_func_gt,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "lt",
+                fyfth_func_lt,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
             .with_command(
                 "eq",
                 fyfth_func_eq,
@@ -238,9 +269,50 @@ impl FyfthLanguageExtension {
                     FyfthBroadcastBehavior::IgnoreIter,
                 ],
             )
+            .with_command(
+                "neq",
+                fyfth_func_neq,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "approx_eq",
+                fyfth_func_approx_eq,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
             .with_command("not", fyfth_func_not, &[FyfthBroadcastBehavior::MayIter])
+            .with_command(
+                "and",
+                fyfth_func_and,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "or",
+                fyfth_func_or,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "xor",
+                fyfth_func_xor,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
             .with_command("name", fyfth_func_name, &[FyfthBroadcastBehavior::MayIter])
-            .with_command("pop", fyfth_func_pop, &[FyfthBroadcastBehavior::IgnoreIter])
+            .with_command("pop", fyfth_func_pop, &[FyfthBroadcastBehavior::KeepLazy])
             .with_command(
                 "index",
                 fyfth_func_index,
@@ -256,10 +328,36 @@ impl FyfthLanguageExtension {
             )
             .with_command("len", fyfth_func_len, &[FyfthBroadcastBehavior::IgnoreIter])
             .with_command(
-                "type",
-                fyfth_func_type,
+                "range",
+                fyfth_func_range,
+                &[
+                    FyfthBroadcastBehavior::IgnoreIter,
+                    FyfthBroadcastBehavior::IgnoreIter,
+                    FyfthBroadcastBehavior::IgnoreIter,
+                ],
+            )
+            .with_command(
+                "lazy_map",
+                fyfth_func_lazy_map,
+                &[
+                    FyfthBroadcastBehavior::KeepLazy,
+                    FyfthBroadcastBehavior::IgnoreIter,
+                ],
+            )
+            .with_command(
+                "lazy_filter",
+                fyfth_func_lazy_filter,
+                &[
+                    FyfthBroadcastBehavior::KeepLazy,
+                    FyfthBroadcastBehavior::IgnoreIter,
+                ],
+            )
+            .with_command(
+                "to_iter",
+                fyfth_func_to_iter,
                 &[FyfthBroadcastBehavior::IgnoreIter],
             )
+            .with_command("type", fyfth_func_type, &[FyfthBroadcastBehavior::KeepLazy])
             .with_command(
                 "append",
                 fyfth_func_append,
@@ -281,6 +379,29 @@ impl FyfthLanguageExtension {
                 fyfth_func_reverse,
                 &[FyfthBroadcastBehavior::IgnoreIter],
             )
+            .with_command(
+                "sort",
+                fyfth_func_sort,
+                &[FyfthBroadcastBehavior::IgnoreIter],
+            )
+            .with_command(
+                "zip",
+                fyfth_func_zip,
+                &[
+                    FyfthBroadcastBehavior::IgnoreIter,
+                    FyfthBroadcastBehavior::IgnoreIter,
+                ],
+            )
+            .with_command(
+                "flatten",
+                fyfth_func_flatten,
+                &[FyfthBroadcastBehavior::IgnoreIter],
+            )
+            .with_command(
+                "unique",
+                fyfth_func_unique,
+                &[FyfthBroadcastBehavior::IgnoreIter],
+            )
             .with_command(
                 "filter",
                 fyfth_func_filter,
@@ -298,6 +419,41 @@ impl FyfthLanguageExtension {
                     FyfthBroadcastBehavior::MayIter,
                 ],
             )
+            .with_command(
+                "insert",
+                fyfth_func_insert,
+                &[
+                    FyfthBroadcastBehavior::IgnoreIter,
+                    FyfthBroadcastBehavior::IgnoreIter,
+                    FyfthBroadcastBehavior::IgnoreIter,
+                ],
+            )
+            .with_command(
+                "remove",
+                fyfth_func_remove,
+                &[
+                    FyfthBroadcastBehavior::IgnoreIter,
+                    FyfthBroadcastBehavior::IgnoreIter,
+                ],
+            )
+            .with_command(
+                "has",
+                fyfth_func_has,
+                &[
+                    FyfthBroadcastBehavior::IgnoreIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "keys",
+                fyfth_func_keys,
+                &[FyfthBroadcastBehavior::IgnoreIter],
+            )
+            .with_command(
+                "values",
+                fyfth_func_values,
+                &[FyfthBroadcastBehavior::IgnoreIter],
+            )
             .with_command(
                 "mod",
                 fyfth_func_mod,
@@ -341,6 +497,93 @@ impl FyfthLanguageExtension {
                     FyfthBroadcastBehavior::MayIter,
                 ],
             )
+            .with_command(
+                "str_len",
+                fyfth_func_str_len,
+                &[FyfthBroadcastBehavior::MayIter],
+            )
+            .with_command(
+                "split",
+                fyfth_func_split,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "join",
+                fyfth_func_join,
+                &[
+                    FyfthBroadcastBehavior::IgnoreIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "substr",
+                fyfth_func_substr,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "char_at",
+                fyfth_func_char_at,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command("trim", fyfth_func_trim, &[FyfthBroadcastBehavior::MayIter])
+            .with_command(
+                "upper",
+                fyfth_func_upper,
+                &[FyfthBroadcastBehavior::MayIter],
+            )
+            .with_command(
+                "lower",
+                fyfth_func_lower,
+                &[FyfthBroadcastBehavior::MayIter],
+            )
+            .with_command(
+                "starts_with",
+                fyfth_func_starts_with,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "ends_with",
+                fyfth_func_ends_with,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "contains",
+                fyfth_func_contains,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "replace",
+                fyfth_func_replace,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "parse_num",
+                fyfth_func_parse_num,
+                &[FyfthBroadcastBehavior::MayIter],
+            )
             .with_command("sin", fyfth_func_sin, &[FyfthBroadcastBehavior::MayIter])
             .with_command("cos", fyfth_func_cos, &[FyfthBroadcastBehavior::MayIter])
             .with_command("tan", fyfth_func_tan, &[FyfthBroadcastBehavior::MayIter])
@@ -354,228 +597,327 @@ impl FyfthLanguageExtension {
                 ],
             )
             .with_command(
-                "component",
-                fyfth_func_component,
+                "to_rad",
+                fyfth_func_to_rad,
                 &[FyfthBroadcastBehavior::MayIter],
-            );
-
-        #[cfg(feature = "regex")]
-        {
-            lang.with_command(
-                "regex",
-                fyfth_func_regex,
+            )
+            .with_command(
+                "to_deg",
+                fyfth_func_to_deg,
+                &[FyfthBroadcastBehavior::MayIter],
+            )
+            .with_command("sqrt", fyfth_func_sqrt, &[FyfthBroadcastBehavior::MayIter])
+            .with_command(
+                "pow",
+                fyfth_func_pow,
                 &[
                     FyfthBroadcastBehavior::MayIter,
                     FyfthBroadcastBehavior::MayIter,
                 ],
-            );
-        }
-
-        // prefixes
-        lang.with_prefix('*', fyfth_prefix_load);
-        lang.with_prefix('$', fyfth_prefix_queue_macro);
-        lang.with_prefix('@', fyfth_prefix_fuzzy_entity);
-
-        lang
-    }
-}
-
-//--------------------------------------------------
-// Command Implementations
-//--------------------------------------------------
-
-fn fyfth_func_entities(
-    ctx: FyfthContext,
-    args: &[FyfthVariant],
-) -> Result<Option<FyfthVariant>, ()> {
-    let [] = args else {
-        panic!("received the wrong number of arguments")
-    };
-
-    let mut entity_query = ctx
-        .world
-        .query_filtered::<Entity, Without<FyfthIgnoreEntity>>();
-    let entities = entity_query
-        .iter(ctx.world)
-        .map(|ent| FyfthVariant::Entity(ent))
-        .collect();
-
-    Ok(Some(FyfthVariant::Iter(entities)))
-}
-
-fn fyfth_func_set(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
-    let [lhs, mhs, rhs] = args else {
-        panic!("received the wrong number of arguments")
-    };
-
-    match (lhs, mhs, rhs) {
-        (&FyfthVariant::Vec2(vec), FyfthVariant::Literal(comp), &FyfthVariant::Num(val)) => {
-            match comp.as_str() {
-                "x" => Ok(Some(FyfthVariant::Vec2(Vec2::new(val, vec.y)))),
-                "y" => Ok(Some(FyfthVariant::Vec2(Vec2::new(vec.x, val)))),
-                _ => {
-                    write!(ctx.output, "Error: vec2 has no `{comp}` component",).unwrap();
-                    Err(())
-                }
-            }
-        }
-        (&FyfthVariant::Vec3(vec), FyfthVariant::Literal(comp), &FyfthVariant::Num(val)) => {
-            match comp.as_str() {
-                "x" => Ok(Some(FyfthVariant::Vec3(Vec3::new(val, vec.y, vec.z)))),
-                "y" => Ok(Some(FyfthVariant::Vec3(Vec3::new(vec.x, val, vec.z)))),
-                "z" => Ok(Some(FyfthVariant::Vec3(Vec3::new(vec.x, vec.y, val)))),
-                _ => {
-                    write!(ctx.output, "Error: vec3 has no `{comp}` component",).unwrap();
-                    Err(())
-                }
-            }
-        }
-        (&FyfthVariant::Quat(quat), FyfthVariant::Literal(comp), &FyfthVariant::Num(val)) => {
-            match comp.as_str() {
-                "x" => Ok(Some(FyfthVariant::Quat(
-                    Quat::from_xyzw(val, quat.y, quat.z, quat.w).normalize(),
-                ))),
-                "y" => Ok(Some(FyfthVariant::Quat(
-                    Quat::from_xyzw(quat.x, val, quat.z, quat.w).normalize(),
-                ))),
-                "z" => Ok(Some(FyfthVariant::Quat(
-                    Quat::from_xyzw(quat.x, quat.y, val, quat.w).normalize(),
-                ))),
-                "w" => Ok(Some(FyfthVariant::Quat(
-                    Quat::from_xyzw(quat.x, quat.y, quat.z, val).normalize(),
-                ))),
-                _ => {
-                    write!(ctx.output, "Error: quat has no `{comp}` component",).unwrap();
-                    Err(())
-                }
-            }
-        }
-        (FyfthVariant::Iter(vec), &FyfthVariant::Num(index), val) => {
-            let mut vec = vec.clone();
-            let index = if index >= 0.0 {
-                index as i32
-            } else {
-                vec.len() as i32 + index as i32
-            };
-
-            if 0 <= index && index < vec.len() as i32 {
-                vec[index as usize] = val.clone();
-                Ok(Some(FyfthVariant::Iter(vec)))
-            } else {
-                write!(
-                    ctx.output,
-                    "Error: `set` tried setting index {index} of an iter of length {}",
-                    vec.len()
-                )
-                .unwrap();
-                Err(())
-            }
-        }
-        (FyfthVariant::Component(dyn_comp), FyfthVariant::Literal(field_name), val) => {
-            let mut dyn_comp = dyn_comp.clone();
-            match dyn_comp.0.reflect_mut() {
-                bevy::reflect::ReflectMut::Struct(strct) => {
-                    if strct.field(&field_name).is_none() {
-                        write!(
-                            ctx.output,
-                            "Error: component `{}` does not have a field `{}`",
-                            dyn_comp.0.reflect_type_path(),
-                            &field_name,
-                        )
-                        .unwrap();
-                        return Err(());
-                    }
-
-                    if let Ok(_) = val.try_set_reflect_field(strct, &field_name) {
-                        Ok(Some(FyfthVariant::Component(dyn_comp)))
-                    } else {
-                        write!(
-                            ctx.output,
-                            "Error: failed to set field `{}` of component `{}` to value `",
-                            &field_name,
-                            dyn_comp.0.reflect_type_path(),
-                        )
-                        .unwrap();
-                        val.pretty_print(ctx.output, ctx.world, ctx.lang);
-                        ctx.output.push('`');
-                        Err(())
-                    }
-                }
-                _ => {
-                    write!(
-                        ctx.output,
-                        "Error: component `{}` is not a struct",
-                        dyn_comp.0.reflect_type_path(),
-                    )
-                    .unwrap();
-                    Err(())
-                }
-            }
-        }
-        (lhs, mhs, rhs) => {
-            ctx.output
-                .push_str("Syntax error: the operation `set` is incompatible with types `");
-            lhs.pretty_print_type(ctx.output);
-            ctx.output.push(' ');
-            mhs.pretty_print_type(ctx.output);
-            ctx.output.push(' ');
-            rhs.pretty_print_type(ctx.output);
-            ctx.output.push_str(" `.");
-            Err(())
-        }
-    }
+            )
+            .with_command("abs", fyfth_func_abs, &[FyfthBroadcastBehavior::MayIter])
+            .with_command(
+                "min",
+                fyfth_func_min,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "max",
+                fyfth_func_max,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "floor",
+                fyfth_func_floor,
+                &[FyfthBroadcastBehavior::MayIter],
+            )
+            .with_command("ceil", fyfth_func_ceil, &[FyfthBroadcastBehavior::MayIter])
+            .with_command(
+                "round",
+                fyfth_func_round,
+                &[FyfthBroadcastBehavior::MayIter],
+            )
+            .with_command(
+                "clamp",
+                fyfth_func_clamp,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "lerp",
+                fyfth_func_lerp,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "dot",
+                fyfth_func_dot,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "cross",
+                fyfth_func_cross,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "length",
+                fyfth_func_length,
+                &[FyfthBroadcastBehavior::MayIter],
+            )
+            .with_command(
+                "normalize",
+                fyfth_func_normalize,
+                &[FyfthBroadcastBehavior::MayIter],
+            )
+            .with_command(
+                "distance",
+                fyfth_func_distance,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "slerp",
+                fyfth_func_slerp,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "inverse",
+                fyfth_func_inverse,
+                &[FyfthBroadcastBehavior::MayIter],
+            )
+            .with_command(
+                "rotate",
+                fyfth_func_rotate,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "euler",
+                fyfth_func_euler,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "to_euler",
+                fyfth_func_to_euler,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "axis_angle",
+                fyfth_func_axis_angle,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "look_to",
+                fyfth_func_look_to,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            )
+            .with_command(
+                "component",
+                fyfth_func_component,
+                &[FyfthBroadcastBehavior::MayIter],
+            );
+
+        #[cfg(feature = "regex")]
+        {
+            lang.with_command(
+                "regex",
+                fyfth_func_regex,
+                &[
+                    FyfthBroadcastBehavior::MayIter,
+                    FyfthBroadcastBehavior::MayIter,
+                ],
+            );
+        }
+
+        // prefixes
+        lang.with_prefix('*', fyfth_prefix_load);
+        lang.with_prefix('$', fyfth_prefix_queue_macro);
+        lang.with_prefix('@', fyfth_prefix_fuzzy_entity);
+
+        lang
+    }
 }
 
-fn fyfth_func_get(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
-    let [lhs, rhs] = args else {
+//--------------------------------------------------
+// Command Implementations
+//--------------------------------------------------
+
+fn fyfth_func_entities(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [] = args else {
         panic!("received the wrong number of arguments")
     };
-    match (lhs, rhs) {
-        (FyfthVariant::Iter(vec), &FyfthVariant::Num(index)) => {
-            let index = if index >= 0.0 {
-                index as i32
-            } else {
-                vec.len() as i32 + index as i32
-            };
 
-            if 0 <= index && index < vec.len() as i32 {
-                Ok(Some(vec[index as usize].clone()))
-            } else {
-                write!(
-                    ctx.output,
-                    "Error: `get` tried getting index {index} of an iter of length {}",
-                    vec.len()
+    // the entities are only queried once they are used
+    Ok(Some(FyfthVariant::Lazy(Arc::new(FyfthLazyIter::Entities))))
+}
+
+fn fyfth_func_set(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, mhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+
+    match (lhs, mhs, rhs.as_num()) {
+        (&FyfthVariant::Vec2(vec), FyfthVariant::Literal(comp), Some(val)) => match comp.as_str() {
+            "x" => Ok(Some(FyfthVariant::Vec2(Vec2::new(val, vec.y)))),
+            "y" => Ok(Some(FyfthVariant::Vec2(Vec2::new(vec.x, val)))),
+            _ => Err(FyfthErrorKind::UnknownField {
+                type_name: "vec2".to_string(),
+                field: comp.clone(),
+            }
+            .into()),
+        },
+        (&FyfthVariant::Vec3(vec), FyfthVariant::Literal(comp), Some(val)) => match comp.as_str() {
+            "x" => Ok(Some(FyfthVariant::Vec3(Vec3::new(val, vec.y, vec.z)))),
+            "y" => Ok(Some(FyfthVariant::Vec3(Vec3::new(vec.x, val, vec.z)))),
+            "z" => Ok(Some(FyfthVariant::Vec3(Vec3::new(vec.x, vec.y, val)))),
+            _ => Err(FyfthErrorKind::UnknownField {
+                type_name: "vec3".to_string(),
+                field: comp.clone(),
+            }
+            .into()),
+        },
+        (&FyfthVariant::Quat(quat), FyfthVariant::Literal(comp), Some(val)) => {
+            match comp.as_str() {
+                "x" => Ok(Some(FyfthVariant::Quat(
+                    Quat::from_xyzw(val, quat.y, quat.z, quat.w).normalize(),
+                ))),
+                "y" => Ok(Some(FyfthVariant::Quat(
+                    Quat::from_xyzw(quat.x, val, quat.z, quat.w).normalize(),
+                ))),
+                "z" => Ok(Some(FyfthVariant::Quat(
+                    Quat::from_xyzw(quat.x, quat.y, val, quat.w).normalize(),
+                ))),
+                "w" => Ok(Some(FyfthVariant::Quat(
+                    Quat::from_xyzw(quat.x, quat.y, quat.z, val).normalize(),
+                ))),
+                _ => Err(FyfthErrorKind::UnknownField {
+                    type_name: "quat".to_string(),
+                    field: comp.clone(),
+                }
+                .into()),
+            }
+        }
+        (FyfthVariant::Iter(vec), index @ (FyfthVariant::Int(_) | FyfthVariant::Num(_)), _) => {
+            let index = list_index(index, vec.len())?;
+            let mut vec = vec.clone();
+            // only copies the list if it is shared
+            Arc::make_mut(&mut vec)[index] = rhs.clone();
+            Ok(Some(FyfthVariant::Iter(vec)))
+        }
+        (FyfthVariant::Map(map), FyfthVariant::Literal(key), _) => {
+            let mut map = map.clone();
+            Arc::make_mut(&mut map).insert(key.clone(), rhs.clone());
+            Ok(Some(FyfthVariant::Map(map)))
+        }
+        (FyfthVariant::Component(dyn_comp), FyfthVariant::Literal(field_name), _) => {
+            let val = rhs;
+            let mut dyn_comp = dyn_comp.clone();
+            match dyn_comp.0.reflect_mut() {
+                bevy::reflect::ReflectMut::Struct(strct) => {
+                    val.try_set_reflect_field(strct, field_name)?;
+                    Ok(Some(FyfthVariant::Component(dyn_comp)))
+                }
+                _ => Err(FyfthComponentError::NotAStruct(
+                    dyn_comp.0.reflect_type_path().to_string(),
                 )
-                .unwrap();
-                Err(())
+                .into()),
             }
         }
+        _ => Err(FyfthError::type_mismatch(
+            "vec2|vec3|quat literal num, iter int X, map literal X or component literal X",
+            args,
+        )),
+    }
+}
+
+fn fyfth_func_get(
+    ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (lhs, rhs) {
+        (FyfthVariant::Iter(vec), index @ (FyfthVariant::Int(_) | FyfthVariant::Num(_))) => {
+            Ok(Some(vec[list_index(index, vec.len())?].clone()))
+        }
+        // unlike fields, keys may be missing
+        (FyfthVariant::Map(map), FyfthVariant::Literal(key)) => {
+            Ok(Some(map.get(key).cloned().unwrap_or(FyfthVariant::Nil)))
+        }
         (&FyfthVariant::Vec2(vec), FyfthVariant::Literal(comp)) => match comp.as_str() {
             "x" => Ok(Some(FyfthVariant::Num(vec.x))),
             "y" => Ok(Some(FyfthVariant::Num(vec.y))),
-            _ => {
-                write!(ctx.output, "Error: vec2 has no `{comp}` component",).unwrap();
-                Err(())
+            _ => Err(FyfthErrorKind::UnknownField {
+                type_name: "vec2".to_string(),
+                field: comp.clone(),
             }
+            .into()),
         },
         (&FyfthVariant::Vec3(vec), FyfthVariant::Literal(comp)) => match comp.as_str() {
             "x" => Ok(Some(FyfthVariant::Num(vec.x))),
             "y" => Ok(Some(FyfthVariant::Num(vec.y))),
             "z" => Ok(Some(FyfthVariant::Num(vec.z))),
-            _ => {
-                write!(ctx.output, "Error: vec3 has no `{comp}` component",).unwrap();
-                Err(())
+            _ => Err(FyfthErrorKind::UnknownField {
+                type_name: "vec3".to_string(),
+                field: comp.clone(),
             }
+            .into()),
         },
         (&FyfthVariant::Quat(quat), FyfthVariant::Literal(comp)) => match comp.as_str() {
             "x" => Ok(Some(FyfthVariant::Num(quat.x))),
             "y" => Ok(Some(FyfthVariant::Num(quat.y))),
             "z" => Ok(Some(FyfthVariant::Num(quat.z))),
             "w" => Ok(Some(FyfthVariant::Num(quat.w))),
-            _ => {
-                write!(ctx.output, "Error: quat has no `{comp}` component",).unwrap();
-                Err(())
+            _ => Err(FyfthErrorKind::UnknownField {
+                type_name: "quat".to_string(),
+                field: comp.clone(),
             }
+            .into()),
         },
         (&FyfthVariant::Entity(entity), FyfthVariant::Literal(component_name)) => {
             let registry = ctx.world.resource::<BevyComponentRegistry>();
@@ -592,45 +934,18 @@ fn fyfth_func_get(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<Fyf
                     if let Some(component_dyn) = maybe_component_dyn {
                         Ok(Some(FyfthVariant::Component(component_dyn)))
                     } else {
-                        write!(
-                            ctx.output,
-                            "Error: entity ({}) does not contain component `{}`",
+                        Err(FyfthComponentError::MissingComponent {
                             entity,
-                            &registry.get_info(component_type_id).unwrap().full_path,
-                        )
-                        .unwrap();
-                        Err(())
-                    }
-                }
-                Err(error) => match error {
-                    BevyComponentRegistryError::NoMatchingComponent => {
-                        write!(
-                            ctx.output,
-                            "Error: no component type path matches '{component_name}'. Make sure the component is registered using `app.register_shell_component::<T>()`.",
-                        )
-                        .unwrap();
-                        Err(())
-                    }
-                    BevyComponentRegistryError::MultipleMatchingComponents(vec) => {
-                        let num = vec.len();
-                        write!(
-                            ctx.output,
-                            "Error: {num} component type paths match '{component_name}':",
-                        )
-                        .unwrap();
-
-                        for index in vec {
-                            writeln!(
-                                ctx.output,
-                                "    {}",
-                                &registry.registered_components[index].full_path,
-                            )
-                            .unwrap();
+                            component: registry
+                                .get_info(component_type_id)
+                                .unwrap()
+                                .full_path
+                                .clone(),
                         }
-
-                        Err(())
+                        .into())
                     }
-                },
+                }
+                Err(error) => Err(component_lookup_error(registry, component_name, error)),
             }
         }
         (FyfthVariant::Component(dyn_comp), FyfthVariant::Literal(field_name)) => {
@@ -643,57 +958,45 @@ fn fyfth_func_get(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<Fyf
                         {
                             Ok(Some(shell_value))
                         } else {
-                            write!(
-                                ctx.output,
-                                "Error: field `{}` of component `{}` has an unsupported type",
-                                &field_name,
-                                dyn_comp.0.reflect_type_path(),
-                            )
-                            .unwrap();
-                            Err(())
+                            Err(FyfthComponentError::UnsupportedFieldType {
+                                component: dyn_comp.0.reflect_type_path().to_string(),
+                                field: field_name.clone(),
+                            }
+                            .into())
                         }
                     } else {
-                        write!(
-                            ctx.output,
-                            "Error: component `{}` does not have a field `{}`",
-                            dyn_comp.0.reflect_type_path(),
-                            &field_name,
-                        )
-                        .unwrap();
-                        Err(())
+                        Err(FyfthErrorKind::UnknownField {
+                            type_name: dyn_comp.0.reflect_type_path().to_string(),
+                            field: field_name.clone(),
+                        }
+                        .into())
                     }
                 }
-                _ => {
-                    write!(
-                        ctx.output,
-                        "Error: component `{}` is not a struct",
-                        dyn_comp.0.reflect_type_path(),
-                    )
-                    .unwrap();
-                    Err(())
-                }
+                _ => Err(FyfthComponentError::NotAStruct(
+                    dyn_comp.0.reflect_type_path().to_string(),
+                )
+                .into()),
             }
         }
-        (lhs, rhs) => {
-            ctx.output
-                .push_str("Syntax error: the operation `get` is incompatible with types `");
-            lhs.pretty_print_type(ctx.output);
-            ctx.output.push(' ');
-            rhs.pretty_print_type(ctx.output);
-            ctx.output.push_str(" `.");
-            Err(())
-        }
+        _ => Err(FyfthError::type_mismatch(
+            "iter int, vec2|vec3|quat literal, map literal, Entity literal or component literal",
+            args,
+        )),
     }
 }
 
-fn fyfth_func_add(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_add(
+    ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [lhs, rhs] = args else {
         panic!("received the wrong number of arguments")
     };
     match (lhs, rhs) {
-        (&FyfthVariant::Num(lhs), &FyfthVariant::Num(rhs)) => {
-            Ok(Some(FyfthVariant::Num(lhs + rhs)))
-        }
+        (
+            FyfthVariant::Int(_) | FyfthVariant::Num(_),
+            FyfthVariant::Int(_) | FyfthVariant::Num(_),
+        ) => combine_nums(lhs, rhs, i64::checked_add, |lhs, rhs| lhs + rhs).map(Some),
         (&FyfthVariant::Vec2(lhs), &FyfthVariant::Vec2(rhs)) => {
             Ok(Some(FyfthVariant::Vec2(lhs + rhs)))
         }
@@ -703,6 +1006,9 @@ fn fyfth_func_add(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<Fyf
         (FyfthVariant::Literal(lhs), &FyfthVariant::Num(rhs)) => {
             Ok(Some(FyfthVariant::Literal(format!("{lhs}{rhs}"))))
         }
+        (FyfthVariant::Literal(lhs), &FyfthVariant::Int(rhs)) => {
+            Ok(Some(FyfthVariant::Literal(format!("{lhs}{rhs}"))))
+        }
         (FyfthVariant::Literal(lhs), FyfthVariant::Literal(rhs)) => {
             Ok(Some(FyfthVariant::Literal(format!("{lhs}{rhs}"))))
         }
@@ -718,39 +1024,42 @@ fn fyfth_func_add(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<Fyf
 
             Ok(None)
         }
-        (lhs, rhs) => {
-            ctx.output
-                .push_str("Syntax error: the operation `add` is incompatible with types `");
-            lhs.pretty_print_type(ctx.output);
-            ctx.output.push(' ');
-            rhs.pretty_print_type(ctx.output);
-            ctx.output.push_str(" `.");
-            Err(())
-        }
+        _ => Err(FyfthError::type_mismatch(
+            "num num, vec2 vec2, vec3 vec3, literal num|literal or Entity component",
+            args,
+        )),
     }
 }
 
-fn fyfth_func_sub(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_sub(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [lhs, rhs] = args else {
         panic!("received the wrong number of arguments")
     };
     match (lhs, rhs) {
-        (&FyfthVariant::Num(lhs), &FyfthVariant::Num(rhs)) => {
-            Ok(Some(FyfthVariant::Num(lhs - rhs)))
+        (
+            FyfthVariant::Int(_) | FyfthVariant::Num(_),
+            FyfthVariant::Int(_) | FyfthVariant::Num(_),
+        ) => combine_nums(lhs, rhs, i64::checked_sub, |lhs, rhs| lhs - rhs).map(Some),
+        (&FyfthVariant::Vec2(lhs), &FyfthVariant::Vec2(rhs)) => {
+            Ok(Some(FyfthVariant::Vec2(lhs - rhs)))
         }
-        (lhs, rhs) => {
-            ctx.output
-                .push_str("Syntax error: the operation `add` is incompatible with types `");
-            lhs.pretty_print_type(ctx.output);
-            ctx.output.push(' ');
-            rhs.pretty_print_type(ctx.output);
-            ctx.output.push_str(" `.");
-            Err(())
+        (&FyfthVariant::Vec3(lhs), &FyfthVariant::Vec3(rhs)) => {
+            Ok(Some(FyfthVariant::Vec3(lhs - rhs)))
         }
+        _ => Err(FyfthError::type_mismatch(
+            "num num, vec2 vec2 or vec3 vec3",
+            args,
+        )),
     }
 }
 
-fn fyfth_func_print(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_print(
+    ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [val] = args else {
         panic!("received the wrong number of arguments")
     };
@@ -758,53 +1067,56 @@ fn fyfth_func_print(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<F
     Ok(None)
 }
 
-fn fyfth_func_store(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_store(
+    ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [lhs, rhs] = args else {
         panic!("received the wrong number of arguments")
     };
     match (lhs, rhs) {
         (val, FyfthVariant::Literal(ident)) => {
-            ctx.vars.insert(ident.clone(), val.clone());
+            // assign to a local variable if one with this name has been declared
+            match ctx.locals {
+                Some(locals) if locals.contains_key(ident) => {
+                    locals.insert(ident.clone(), val.clone())
+                }
+                _ => ctx.vars.insert(ident.clone(), val.clone()),
+            };
             Ok(None)
         }
-        _ => {
-            ctx.output
-                .push_str("Syntax error: the operation `store` needs to operate on `X literal`");
-            Err(())
-        }
+        _ => Err(FyfthError::type_mismatch("X literal", args)),
     }
 }
 
-fn fyfth_func_load(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_load(
+    ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [val] = args else {
         panic!("received the wrong number of arguments")
     };
     match val {
         FyfthVariant::Literal(ident) => {
-            if let Some(val) = ctx.vars.get(ident) {
+            // local variables shadow global ones
+            if let Some(val) = ctx.locals.as_ref().and_then(|locals| locals.get(ident)) {
+                Ok(Some(val.clone()))
+            } else if let Some(val) = ctx.vars.get(ident) {
                 Ok(Some(val.clone()))
+            } else if let Some(mac) = ctx.macros.get(ident) {
+                Ok(Some(FyfthVariant::Iter(Arc::new(mac.body.clone()))))
             } else {
-                write!(
-                    ctx.output,
-                    "Error: no variable of the name `{}` found",
-                    &ident
-                )
-                .unwrap();
-                Err(())
+                Err(FyfthErrorKind::UnknownVariable(ident.clone()).into())
             }
         }
-        _ => {
-            ctx.output
-                .push_str("Syntax error: the operation `load` needs to operate on `literal`");
-            Err(())
-        }
+        _ => Err(FyfthError::type_mismatch("literal", args)),
     }
 }
 
 fn fyfth_func_print_vars(
     ctx: FyfthContext,
     args: &[FyfthVariant],
-) -> Result<Option<FyfthVariant>, ()> {
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [] = args else {
         panic!("received the wrong number of arguments")
     };
@@ -813,77 +1125,158 @@ fn fyfth_func_print_vars(
         val.pretty_print(ctx.output, ctx.world, ctx.lang);
         ctx.output.push('\n');
     }
+    for (ident, mac) in ctx.macros.iter() {
+        write!(ctx.output, "macro \"{ident}\" : ").unwrap();
+        FyfthVariant::Iter(Arc::new(mac.body.clone()))
+            .pretty_print(ctx.output, ctx.world, ctx.lang);
+        ctx.output.push('\n');
+    }
     Ok(None)
 }
 
-/// `lhs: Num`, `rhs: Num`
-fn fyfth_func_geq(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
-    let [lhs, rhs] = args else {
-        panic!("received the wrong number of arguments")
-    };
-    match (lhs, rhs) {
-        (&FyfthVariant::Num(lhs), &FyfthVariant::Num(rhs)) => {
-            Ok(Some(FyfthVariant::Bool(lhs >= rhs)))
-        }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `geq` needs to operate on two `bool` types."
-            )
-            .unwrap();
-            Err(())
-        }
-    }
+/// `lhs: X`, `rhs: X`
+fn fyfth_func_geq(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    compare_args(args, Ordering::is_ge)
+}
+
+/// `lhs: X`, `rhs: X`
+fn fyfth_func_leq(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    compare_args(args, Ordering::is_le)
+}
+
+/// `lhs: X`, `rhs: X`
+fn fyfth_func_gt(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    compare_args(args, Ordering::is_gt)
+}
+
+/// `lhs: X`, `rhs: X`
+fn fyfth_func_lt(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    compare_args(args, Ordering::is_lt)
 }
 
-/// `lhs: Num`, `rhs: Num`
-fn fyfth_func_leq(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+/// `lhs: X`, `rhs: Y`
+fn fyfth_func_eq(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [lhs, rhs] = args else {
         panic!("received the wrong number of arguments")
     };
-    match (lhs, rhs) {
-        (&FyfthVariant::Num(lhs), &FyfthVariant::Num(rhs)) => {
-            Ok(Some(FyfthVariant::Bool(lhs <= rhs)))
-        }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `leq` needs to operate on two `bool` types."
-            )
-            .unwrap();
-            Err(())
-        }
-    }
+    Ok(Some(FyfthVariant::Bool(values_eq(lhs, rhs))))
 }
 
 /// `lhs: X`, `rhs: Y`
-fn fyfth_func_eq(_ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_neq(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [lhs, rhs] = args else {
         panic!("received the wrong number of arguments")
     };
-    Ok(Some(FyfthVariant::Bool(lhs == rhs)))
+    Ok(Some(FyfthVariant::Bool(!values_eq(lhs, rhs))))
 }
 
-/// `val: bool`
-fn fyfth_func_not(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
-    let [val] = args else {
+/// `lhs: num|vec2|vec3|quat`, `rhs: num|vec2|vec3|quat`, `epsilon: num`
+fn fyfth_func_approx_eq(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, rhs, epsilon] = args else {
         panic!("received the wrong number of arguments")
     };
-    match val {
-        &FyfthVariant::Bool(cond) => Ok(Some(FyfthVariant::Bool(!cond))),
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `not` needs to operate on `bool`."
-            )
-            .unwrap();
-            Err(())
+    let Some(epsilon) = epsilon.as_num() else {
+        return Err(FyfthError::type_mismatch("X Y num", args));
+    };
+    // anything but numbers, vectors and quats has to be exactly equal, just like with `eq`
+    let approx_eq = match (&int_to_num(lhs), &int_to_num(rhs)) {
+        (&FyfthVariant::Num(lhs), &FyfthVariant::Num(rhs)) => (lhs - rhs).abs() <= epsilon,
+        (&FyfthVariant::Vec2(lhs), &FyfthVariant::Vec2(rhs)) => lhs.abs_diff_eq(rhs, epsilon),
+        (&FyfthVariant::Vec3(lhs), &FyfthVariant::Vec3(rhs)) => lhs.abs_diff_eq(rhs, epsilon),
+        (&FyfthVariant::Quat(lhs), &FyfthVariant::Quat(rhs)) => lhs.abs_diff_eq(rhs, epsilon),
+        _ => values_eq(lhs, rhs),
+    };
+    Ok(Some(FyfthVariant::Bool(approx_eq)))
+}
+
+/// `val: bool`
+fn fyfth_func_not(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match val {
+        &FyfthVariant::Bool(cond) => Ok(Some(FyfthVariant::Bool(!cond))),
+        _ => Err(FyfthError::type_mismatch("bool", args)),
+    }
+}
+
+/// `lhs: bool`, `rhs: bool`
+fn fyfth_func_and(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (lhs, rhs) {
+        (&FyfthVariant::Bool(lhs), &FyfthVariant::Bool(rhs)) => {
+            Ok(Some(FyfthVariant::Bool(lhs && rhs)))
+        }
+        _ => Err(FyfthError::type_mismatch("bool bool", args)),
+    }
+}
+
+/// `lhs: bool`, `rhs: bool`
+fn fyfth_func_or(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (lhs, rhs) {
+        (&FyfthVariant::Bool(lhs), &FyfthVariant::Bool(rhs)) => {
+            Ok(Some(FyfthVariant::Bool(lhs || rhs)))
+        }
+        _ => Err(FyfthError::type_mismatch("bool bool", args)),
+    }
+}
+
+/// `lhs: bool`, `rhs: bool`
+fn fyfth_func_xor(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (lhs, rhs) {
+        (&FyfthVariant::Bool(lhs), &FyfthVariant::Bool(rhs)) => {
+            Ok(Some(FyfthVariant::Bool(lhs != rhs)))
         }
+        _ => Err(FyfthError::type_mismatch("bool bool", args)),
     }
 }
 
 /// `val: Entity`
-fn fyfth_func_name(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_name(
+    ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [val] = args else {
         panic!("received the wrong number of arguments")
     };
@@ -906,113 +1299,154 @@ fn fyfth_func_name(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<Fy
         FyfthVariant::Component(dyn_comp) => Ok(Some(FyfthVariant::Literal(
             dyn_comp.0.reflect_type_path().to_string(),
         ))),
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `name` needs to operate on `Entity`."
-            )
-            .unwrap();
-            Err(())
-        }
+        _ => Err(FyfthError::type_mismatch("Entity", args)),
     }
 }
 
 // `val: X`
-fn fyfth_func_pop(_ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_pop(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [_val] = args else {
         panic!("received the wrong number of arguments")
     };
     Ok(None)
 }
 
-/// `lhs: iter`, `rhs: num`
-fn fyfth_func_index(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+/// `lhs: iter`, `rhs: int`
+fn fyfth_func_index(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [lhs, rhs] = args else {
         panic!("received the wrong number of arguments")
     };
     match (lhs, rhs) {
-        (FyfthVariant::Iter(vec), &FyfthVariant::Num(index)) => {
-            let index = if index >= 0.0 {
-                index as i32
-            } else {
-                vec.len() as i32 + index as i32
-            };
+        (FyfthVariant::Iter(vec), index @ (FyfthVariant::Int(_) | FyfthVariant::Num(_))) => {
+            Ok(Some(vec[list_index(index, vec.len())?].clone()))
+        }
+        _ => Err(FyfthError::type_mismatch("iter int", args)),
+    }
+}
 
-            if 0 <= index && index < vec.len() as i32 {
-                Ok(Some(vec[index as usize].clone()))
+fn fyfth_func_enum(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    let range = |len: i64| {
+        let range = FyfthLazyIter::int_range(0, len, 1)?;
+        Ok(Some(FyfthVariant::Lazy(Arc::new(range))))
+    };
+    match val {
+        FyfthVariant::Iter(vec) => range(vec.len() as i64),
+        FyfthVariant::Int(_) | FyfthVariant::Num(_) => {
+            let num = val.as_num().unwrap();
+            if 0.0 <= num && num <= RANGE_LIMIT as f32 {
+                range(val.as_int().unwrap())
             } else {
-                write!(
-                    ctx.output,
-                    "Error: index `{index}` out of range for an iterator of length {}.",
-                    vec.len()
-                )
-                .unwrap();
-                Err(())
+                Err(FyfthError::runtime(format!(
+                    "{num} is not a valid `enum` range"
+                )))
             }
         }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `index` needs to operate on `iter num`."
-            )
-            .unwrap();
-            Err(())
-        }
+        _ => Err(FyfthError::type_mismatch("iter", args)),
     }
 }
 
-fn fyfth_func_enum(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_len(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [val] = args else {
         panic!("received the wrong number of arguments")
     };
     match val {
-        FyfthVariant::Iter(vec) => Ok(Some(FyfthVariant::Iter(
-            (0..vec.len())
-                .map(|i| FyfthVariant::Num(i as f32))
-                .collect(),
-        ))),
-        &FyfthVariant::Num(num) => {
-            if 0.0 <= num && num <= 1_000_000.0 {
-                Ok(Some(FyfthVariant::Iter(
-                    (0..(num as i32))
-                        .map(|i| FyfthVariant::Num(i as f32))
-                        .collect(),
-                )))
-            } else {
-                write!(ctx.output, "Error: {num} is not a valid `enum` range").unwrap();
-                Err(())
-            }
+        FyfthVariant::Iter(vec) => Ok(Some(FyfthVariant::Int(vec.len() as i64))),
+        FyfthVariant::Map(map) => Ok(Some(FyfthVariant::Int(map.len() as i64))),
+        _ => Err(FyfthError::type_mismatch("iter|map", args)),
+    }
+}
+
+/// `start: num`, `end: num`, `step: num`
+fn fyfth_func_range(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [start, end, step] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    let range = match (start, end, step) {
+        (&FyfthVariant::Int(start), &FyfthVariant::Int(end), &FyfthVariant::Int(step)) => {
+            FyfthLazyIter::int_range(start, end, step)?
         }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `enum` needs to operate on `iter` or `num`."
-            )
-            .unwrap();
-            Err(())
+        _ => match (start.as_num(), end.as_num(), step.as_num()) {
+            (Some(start), Some(end), Some(step)) => FyfthLazyIter::range(start, end, step)?,
+            _ => return Err(FyfthError::type_mismatch("num num num", args)),
+        },
+    };
+    Ok(Some(FyfthVariant::Lazy(Arc::new(range))))
+}
+
+/// `source: iter`, `code: iter`
+fn fyfth_func_lazy_map(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [source, code] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (source, code) {
+        (FyfthVariant::Iter(_) | FyfthVariant::Lazy(_), FyfthVariant::Iter(code)) => {
+            let view = FyfthLazyIter::view(source, code, false)?;
+            Ok(Some(FyfthVariant::Lazy(Arc::new(view))))
         }
+        _ => Err(FyfthError::type_mismatch("iter iter", args)),
     }
 }
 
-fn fyfth_func_len(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+/// `source: iter`, `code: iter`
+fn fyfth_func_lazy_filter(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [source, code] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (source, code) {
+        (FyfthVariant::Iter(_) | FyfthVariant::Lazy(_), FyfthVariant::Iter(code)) => {
+            let view = FyfthLazyIter::view(source, code, true)?;
+            Ok(Some(FyfthVariant::Lazy(Arc::new(view))))
+        }
+        _ => Err(FyfthError::type_mismatch("iter iter", args)),
+    }
+}
+
+/// `val: iter`
+///
+/// Lazy iterators are collected before any command that ignores iterators is called, so this
+/// only has to check the type.
+fn fyfth_func_to_iter(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [val] = args else {
         panic!("received the wrong number of arguments")
     };
     match val {
-        FyfthVariant::Iter(vec) => Ok(Some(FyfthVariant::Num(vec.len() as f32))),
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `len` needs to operate on `iter`."
-            )
-            .unwrap();
-            Err(())
-        }
+        FyfthVariant::Iter(_) => Ok(Some(val.clone())),
+        _ => Err(FyfthError::type_mismatch("iter", args)),
     }
 }
 
 /// `val: X`
-fn fyfth_func_type(_ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_type(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [val] = args else {
         panic!("received the wrong number of arguments")
     };
@@ -1021,7 +1455,10 @@ fn fyfth_func_type(_ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<F
     Ok(Some(FyfthVariant::Literal(type_name)))
 }
 
-fn fyfth_func_append(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_append(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [lhs, rhs] = args else {
         panic!("received the wrong number of arguments")
     };
@@ -1030,20 +1467,16 @@ fn fyfth_func_append(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<
             let mut new_vec = Vec::with_capacity(vec.len() + 1);
             new_vec.extend_from_slice(&vec);
             new_vec.push(val.clone());
-            Ok(Some(FyfthVariant::Iter(new_vec)))
-        }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `append` needs to operate on `iter X`."
-            )
-            .unwrap();
-            Err(())
+            Ok(Some(FyfthVariant::Iter(Arc::new(new_vec))))
         }
+        _ => Err(FyfthError::type_mismatch("iter X", args)),
     }
 }
 
-fn fyfth_func_extend(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_extend(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [lhs, rhs] = args else {
         panic!("received the wrong number of arguments")
     };
@@ -1052,43 +1485,119 @@ fn fyfth_func_extend(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<
             let mut new_vec = Vec::with_capacity(lhs.len() + rhs.len());
             new_vec.extend_from_slice(&lhs);
             new_vec.extend_from_slice(&rhs);
-            Ok(Some(FyfthVariant::Iter(new_vec)))
-        }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `extend` needs to operate on two `iter` types."
-            )
-            .unwrap();
-            Err(())
+            Ok(Some(FyfthVariant::Iter(Arc::new(new_vec))))
         }
+        _ => Err(FyfthError::type_mismatch("iter iter", args)),
     }
 }
 
 fn fyfth_func_reverse(
-    ctx: FyfthContext,
+    _ctx: FyfthContext,
     args: &[FyfthVariant],
-) -> Result<Option<FyfthVariant>, ()> {
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [val] = args else {
         panic!("received the wrong number of arguments")
     };
     match val {
-        FyfthVariant::Iter(vec) => Ok(Some(FyfthVariant::Iter(
+        FyfthVariant::Iter(vec) => Ok(Some(FyfthVariant::Iter(Arc::new(
             vec.iter().rev().cloned().collect(),
-        ))),
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `reverse` needs to operate on `iter`."
-            )
-            .unwrap();
-            Err(())
+        )))),
+        _ => Err(FyfthError::type_mismatch("iter", args)),
+    }
+}
+
+/// `vals: iter`
+fn fyfth_func_sort(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match val {
+        FyfthVariant::Iter(vec) => Ok(Some(FyfthVariant::Iter(Arc::new(sort_by_keys(vec, vec)?)))),
+        _ => Err(FyfthError::type_mismatch("iter", args)),
+    }
+}
+
+/// `lhs: iter`, `rhs: iter`
+fn fyfth_func_zip(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (lhs, rhs) {
+        (FyfthVariant::Iter(lhs), FyfthVariant::Iter(rhs)) => {
+            // like broadcasting, only iterators of the same length can be combined
+            if lhs.len() != rhs.len() {
+                return Err(FyfthError::runtime(format!(
+                    "cannot zip iterators of differing lengths {} and {}",
+                    lhs.len(),
+                    rhs.len()
+                )));
+            }
+            let pairs = lhs
+                .iter()
+                .zip(rhs.iter())
+                .map(|(lhs, rhs)| FyfthVariant::Iter(Arc::new(vec![lhs.clone(), rhs.clone()])));
+            Ok(Some(FyfthVariant::Iter(Arc::new(pairs.collect()))))
         }
+        _ => Err(FyfthError::type_mismatch("iter iter", args)),
     }
 }
 
+/// `vals: iter`
+fn fyfth_func_flatten(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    let FyfthVariant::Iter(vec) = val else {
+        return Err(FyfthError::type_mismatch("iter", args));
+    };
+
+    // only flattens one level, and values that are not iters are kept as they are
+    let mut flat = Vec::with_capacity(vec.len());
+    for val in vec.iter() {
+        match val {
+            FyfthVariant::Iter(inner) => flat.extend(inner.iter().cloned()),
+            val => flat.push(val.clone()),
+        }
+    }
+    Ok(Some(FyfthVariant::Iter(Arc::new(flat))))
+}
+
+/// `vals: iter`
+fn fyfth_func_unique(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    let FyfthVariant::Iter(vec) = val else {
+        return Err(FyfthError::type_mismatch("iter", args));
+    };
+
+    // keeps the first of all values that are `eq`
+    let mut unique: Vec<FyfthVariant> = vec![];
+    for val in vec.iter() {
+        if !unique.iter().any(|kept| values_eq(kept, val)) {
+            unique.push(val.clone());
+        }
+    }
+    Ok(Some(FyfthVariant::Iter(Arc::new(unique))))
+}
+
 /// `lhs: X`, `rhs: bool`
-fn fyfth_func_filter(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_filter(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [lhs, rhs] = args else {
         panic!("received the wrong number of arguments")
     };
@@ -1099,317 +1608,1022 @@ fn fyfth_func_filter(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<
             } else {
                 Ok(None)
             }
-        }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `filter` needs to operate on `X bool`."
-            )
-            .unwrap();
-            Err(())
-        }
+        }
+        _ => Err(FyfthError::type_mismatch("X bool", args)),
+    }
+}
+
+/// `cond: bool` `then: X`, `else: Y`
+fn fyfth_func_select(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, mhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (lhs, mhs, rhs) {
+        (&FyfthVariant::Bool(cond), then_val, else_val) => {
+            if cond {
+                Ok(Some(then_val.clone()))
+            } else {
+                Ok(Some(else_val.clone()))
+            }
+        }
+        _ => Err(FyfthError::type_mismatch("bool X Y", args)),
+    }
+}
+
+/// `map: map`, `key: literal`, `val: X`
+fn fyfth_func_insert(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [map, key, val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (map, key) {
+        (FyfthVariant::Map(map), FyfthVariant::Literal(key)) => {
+            let mut map = map.clone();
+            // only copies the map if it is shared
+            Arc::make_mut(&mut map).insert(key.clone(), val.clone());
+            Ok(Some(FyfthVariant::Map(map)))
+        }
+        _ => Err(FyfthError::type_mismatch("map literal X", args)),
+    }
+}
+
+/// `map: map`, `key: literal`
+fn fyfth_func_remove(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [map, key] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (map, key) {
+        (FyfthVariant::Map(map), FyfthVariant::Literal(key)) => {
+            let mut map = map.clone();
+            // removing a missing key does not need to copy a shared map
+            if map.contains_key(key) {
+                Arc::make_mut(&mut map).remove(key);
+            }
+            Ok(Some(FyfthVariant::Map(map)))
+        }
+        _ => Err(FyfthError::type_mismatch("map literal", args)),
+    }
+}
+
+/// `map: map`, `key: literal`
+fn fyfth_func_has(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [map, key] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (map, key) {
+        (FyfthVariant::Map(map), FyfthVariant::Literal(key)) => {
+            Ok(Some(FyfthVariant::Bool(map.contains_key(key))))
+        }
+        _ => Err(FyfthError::type_mismatch("map literal", args)),
+    }
+}
+
+/// `map: map`
+fn fyfth_func_keys(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [map] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match map {
+        FyfthVariant::Map(map) => {
+            let keys = map.keys().cloned().map(FyfthVariant::Literal).collect();
+            Ok(Some(FyfthVariant::Iter(Arc::new(keys))))
+        }
+        _ => Err(FyfthError::type_mismatch("map", args)),
+    }
+}
+
+/// `map: map`
+fn fyfth_func_values(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [map] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match map {
+        FyfthVariant::Map(map) => Ok(Some(FyfthVariant::Iter(Arc::new(
+            map.values().cloned().collect(),
+        )))),
+        _ => Err(FyfthError::type_mismatch("map", args)),
+    }
+}
+
+/// `lhs: num`, `rhs: num`
+fn fyfth_func_mod(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (lhs, rhs) {
+        (FyfthVariant::Int(_), FyfthVariant::Int(0)) => {
+            Err(FyfthError::runtime("cannot take an int modulo 0"))
+        }
+        (
+            FyfthVariant::Int(_) | FyfthVariant::Num(_),
+            FyfthVariant::Int(_) | FyfthVariant::Num(_),
+        ) => combine_nums(lhs, rhs, i64::checked_rem, |lhs, rhs| lhs % rhs).map(Some),
+        (_, FyfthVariant::Int(_) | FyfthVariant::Num(_)) => Ok(Some(FyfthVariant::Nil)),
+        _ => Err(FyfthError::type_mismatch("X num", args)),
+    }
+}
+
+/// `lhs: num`, `rhs: num`
+fn fyfth_func_vec2(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (lhs.as_num(), rhs.as_num()) {
+        (Some(x), Some(y)) => Ok(Some(FyfthVariant::Vec2(Vec2::new(x, y)))),
+        _ => Err(FyfthError::type_mismatch("num num", args)),
+    }
+}
+
+/// `num num num`
+fn fyfth_func_vec3(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, mhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (lhs.as_num(), mhs.as_num(), rhs.as_num()) {
+        (Some(x), Some(y), Some(z)) => Ok(Some(FyfthVariant::Vec3(Vec3::new(x, y, z)))),
+        _ => Err(FyfthError::type_mismatch("num num num", args)),
+    }
+}
+
+/// `num num num num`
+fn fyfth_func_quat(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [x, y, z, w] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (x.as_num(), y.as_num(), z.as_num(), w.as_num()) {
+        (Some(x), Some(y), Some(z), Some(w)) => Ok(Some(FyfthVariant::Quat(
+            Quat::from_xyzw(x, y, z, w).normalize(),
+        ))),
+        _ => Err(FyfthError::type_mismatch("num num num num", args)),
+    }
+}
+
+/// `lhs: literal`, `rhs: literal`
+fn fyfth_func_fuzzy(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (lhs, rhs) {
+        (FyfthVariant::Literal(lhs), FyfthVariant::Literal(rhs)) => {
+            Ok(Some(FyfthVariant::Bool(util::fuzzy_match(&lhs, &rhs))))
+        }
+        (_, FyfthVariant::Literal(_)) => Ok(Some(FyfthVariant::Bool(false))),
+        _ => Err(FyfthError::type_mismatch("X literal", args)),
+    }
+}
+
+/// `lhs: literal`, `rhs: literal`
+#[cfg(feature = "regex")]
+fn fyfth_func_regex(
+    ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (lhs, rhs) {
+        (FyfthVariant::Literal(lhs), FyfthVariant::Literal(rhs)) => {
+            match Regex::new(&rhs) {
+                Ok(re) => {
+                    if re.is_match(&lhs) {
+                        // add the captures as variables
+                        for capture_name in re.capture_names().filter_map(|n| n) {
+                            let captures: Vec<_> = re
+                                .captures_iter(&lhs)
+                                .filter_map(|c| c.name(capture_name))
+                                .map(|m| m.as_str())
+                                .collect();
+
+                            match captures.len() {
+                                // don't do anything
+                                0 => {}
+                                // put it in a var as a literal
+                                1 => {
+                                    ctx.vars.insert(
+                                        capture_name.to_string(),
+                                        FyfthVariant::Literal(captures[0].to_string()),
+                                    );
+                                }
+                                // put an iterator of all of them into the var
+                                _ => {
+                                    ctx.vars.insert(
+                                        capture_name.to_string(),
+                                        FyfthVariant::Iter(Arc::new(
+                                            captures
+                                                .iter()
+                                                .map(|&c| FyfthVariant::Literal(c.to_string()))
+                                                .collect(),
+                                        )),
+                                    );
+                                }
+                            }
+                        }
+                        Ok(Some(FyfthVariant::Bool(true)))
+                    } else {
+                        Ok(Some(FyfthVariant::Bool(false)))
+                    }
+                }
+                Err(err) => Err(FyfthError::runtime(format!("failed to parse regex: {err}"))),
+            }
+            // Ok(Some(FyfthVariant::Bool(matcher.fuzzy_match(&lhs, &rhs).is_some())))
+        }
+        (_, FyfthVariant::Literal(_)) => Ok(Some(FyfthVariant::Bool(false))),
+        _ => Err(FyfthError::type_mismatch("X literal", args)),
+    }
+}
+
+/// `val: literal`
+fn fyfth_func_str_len(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    map_literal(args, |val| FyfthVariant::Int(val.chars().count() as i64))
+}
+
+/// `val: literal`, `sep: literal`
+fn fyfth_func_split(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val, sep] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (val, sep) {
+        (FyfthVariant::Literal(val), FyfthVariant::Literal(sep)) => {
+            // an empty separator splits into single characters
+            let parts: Vec<_> = if sep.is_empty() {
+                val.chars().map(String::from).collect()
+            } else {
+                val.split(sep.as_str()).map(str::to_string).collect()
+            };
+            let parts = parts.into_iter().map(FyfthVariant::Literal).collect();
+            Ok(Some(FyfthVariant::Iter(Arc::new(parts))))
+        }
+        (FyfthVariant::Nil, FyfthVariant::Literal(_)) => Ok(Some(FyfthVariant::Nil)),
+        _ => Err(FyfthError::type_mismatch("literal literal", args)),
+    }
+}
+
+/// `vals: iter`, `sep: literal`
+fn fyfth_func_join(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [vals, sep] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    let (FyfthVariant::Iter(vals), FyfthVariant::Literal(sep)) = (vals, sep) else {
+        return Err(FyfthError::type_mismatch("iter literal", args));
+    };
+
+    let mut joined = String::new();
+    for (i, val) in vals.iter().enumerate() {
+        if i > 0 {
+            joined.push_str(sep);
+        }
+        // numbers and bools are joined as they are printed
+        match val {
+            FyfthVariant::Literal(val) => joined.push_str(val),
+            FyfthVariant::Int(val) => write!(joined, "{val}").unwrap(),
+            FyfthVariant::Num(val) => write!(joined, "{val}").unwrap(),
+            FyfthVariant::Bool(val) => write!(joined, "{val}").unwrap(),
+            _ => {
+                return Err(FyfthError::type_mismatch(
+                    "literal|int|num|bool",
+                    std::slice::from_ref(val),
+                ))
+            }
+        }
+    }
+    Ok(Some(FyfthVariant::Literal(joined)))
+}
+
+/// `val: literal`, `start: int`, `count: int`
+fn fyfth_func_substr(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val, start, count] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (val, start.as_int(), count.as_int()) {
+        (FyfthVariant::Literal(val), Some(start), Some(count)) if count >= 0 => {
+            let len = val.chars().count();
+            // like indices, negative starts count from the end
+            let start = if start >= 0 {
+                start
+            } else {
+                len as i64 + start
+            };
+            let end = start.saturating_add(count);
+            if start < 0 || end > len as i64 {
+                return Err(FyfthErrorKind::IndexOutOfRange {
+                    index: if start < 0 { start } else { end },
+                    len,
+                }
+                .into());
+            }
+
+            let substr = val.chars().skip(start as usize).take(count as usize);
+            Ok(Some(FyfthVariant::Literal(substr.collect())))
+        }
+        (FyfthVariant::Nil, Some(_), Some(_)) => Ok(Some(FyfthVariant::Nil)),
+        _ => Err(FyfthError::type_mismatch("literal int int", args)),
+    }
+}
+
+/// `val: literal`, `index: int`
+fn fyfth_func_char_at(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val, index] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (val, index) {
+        (FyfthVariant::Literal(val), FyfthVariant::Int(_) | FyfthVariant::Num(_)) => {
+            let index = list_index(index, val.chars().count())?;
+            let ch = val.chars().nth(index).unwrap();
+            Ok(Some(FyfthVariant::Literal(ch.to_string())))
+        }
+        (FyfthVariant::Nil, FyfthVariant::Int(_) | FyfthVariant::Num(_)) => {
+            Ok(Some(FyfthVariant::Nil))
+        }
+        _ => Err(FyfthError::type_mismatch("literal int", args)),
+    }
+}
+
+/// `val: literal`
+fn fyfth_func_trim(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    map_literal(args, |val| FyfthVariant::Literal(val.trim().to_string()))
+}
+
+/// `val: literal`
+fn fyfth_func_upper(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    map_literal(args, |val| FyfthVariant::Literal(val.to_uppercase()))
+}
+
+/// `val: literal`
+fn fyfth_func_lower(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    map_literal(args, |val| FyfthVariant::Literal(val.to_lowercase()))
+}
+
+/// `val: literal`, `prefix: literal`
+fn fyfth_func_starts_with(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    match_literals(args, |val, prefix| val.starts_with(prefix))
+}
+
+/// `val: literal`, `suffix: literal`
+fn fyfth_func_ends_with(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    match_literals(args, |val, suffix| val.ends_with(suffix))
+}
+
+/// `val: literal`, `part: literal`
+fn fyfth_func_contains(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    match_literals(args, |val, part| val.contains(part))
+}
+
+/// `val: literal`, `from: literal`, `to: literal`
+fn fyfth_func_replace(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val, from, to] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (val, from, to) {
+        (FyfthVariant::Literal(val), FyfthVariant::Literal(from), FyfthVariant::Literal(to)) => {
+            Ok(Some(FyfthVariant::Literal(val.replace(from.as_str(), to))))
+        }
+        (FyfthVariant::Nil, FyfthVariant::Literal(_), FyfthVariant::Literal(_)) => {
+            Ok(Some(FyfthVariant::Nil))
+        }
+        _ => Err(FyfthError::type_mismatch("literal literal literal", args)),
+    }
+}
+
+/// `val: literal`
+fn fyfth_func_parse_num(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    // numbers are parsed like they are in code, and anything else becomes `nil`
+    map_literal(args, |val| {
+        let val = val.trim();
+        if let Ok(val) = val.parse() {
+            FyfthVariant::Int(val)
+        } else if let Ok(val) = val.parse() {
+            FyfthVariant::Num(val)
+        } else {
+            FyfthVariant::Nil
+        }
+    })
+}
+
+/// `val: num`
+fn fyfth_func_sin(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match val.as_num() {
+        Some(val) => Ok(Some(FyfthVariant::Num(val.sin()))),
+        _ => Err(FyfthError::type_mismatch("num", args)),
+    }
+}
+
+/// `val: num`
+fn fyfth_func_cos(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match val.as_num() {
+        Some(val) => Ok(Some(FyfthVariant::Num(val.cos()))),
+        _ => Err(FyfthError::type_mismatch("num", args)),
+    }
+}
+
+/// `val: num`
+fn fyfth_func_tan(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match val.as_num() {
+        Some(val) => Ok(Some(FyfthVariant::Num(val.tan()))),
+        _ => Err(FyfthError::type_mismatch("num", args)),
+    }
+}
+
+/// `val: num`
+fn fyfth_func_atan(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match val.as_num() {
+        Some(val) => Ok(Some(FyfthVariant::Num(val.atan()))),
+        _ => Err(FyfthError::type_mismatch("num", args)),
+    }
+}
+
+/// `lhs: num, rhs: num`
+fn fyfth_func_atan2(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (lhs.as_num(), rhs.as_num()) {
+        (Some(lhs), Some(rhs)) => Ok(Some(FyfthVariant::Num(lhs.atan2(rhs)))),
+        _ => Err(FyfthError::type_mismatch("num num", args)),
+    }
+}
+
+/// `val: num|vec2|vec3`
+fn fyfth_func_to_rad(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match int_to_num(val) {
+        FyfthVariant::Num(val) => Ok(Some(FyfthVariant::Num(val.to_radians()))),
+        FyfthVariant::Vec2(val) => Ok(Some(FyfthVariant::Vec2(Vec2::from_array(
+            val.to_array().map(f32::to_radians),
+        )))),
+        FyfthVariant::Vec3(val) => Ok(Some(FyfthVariant::Vec3(Vec3::from_array(
+            val.to_array().map(f32::to_radians),
+        )))),
+        _ => Err(FyfthError::type_mismatch("num, vec2 or vec3", args)),
+    }
+}
+
+/// `val: num|vec2|vec3`
+fn fyfth_func_to_deg(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match int_to_num(val) {
+        FyfthVariant::Num(val) => Ok(Some(FyfthVariant::Num(val.to_degrees()))),
+        FyfthVariant::Vec2(val) => Ok(Some(FyfthVariant::Vec2(Vec2::from_array(
+            val.to_array().map(f32::to_degrees),
+        )))),
+        FyfthVariant::Vec3(val) => Ok(Some(FyfthVariant::Vec3(Vec3::from_array(
+            val.to_array().map(f32::to_degrees),
+        )))),
+        _ => Err(FyfthError::type_mismatch("num, vec2 or vec3", args)),
+    }
+}
+
+/// `val: num`
+fn fyfth_func_sqrt(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match val.as_num() {
+        Some(val) => Ok(Some(FyfthVariant::Num(val.sqrt()))),
+        _ => Err(FyfthError::type_mismatch("num", args)),
+    }
+}
+
+/// `base: num, exp: num`
+fn fyfth_func_pow(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [base, exp] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (base, exp) {
+        (FyfthVariant::Int(0), &FyfthVariant::Int(exp)) if exp < 0 => Err(FyfthError::runtime(
+            "cannot raise an int 0 to a negative power",
+        )),
+        // an int to a non-negative int power stays an int
+        (&FyfthVariant::Int(base), &FyfthVariant::Int(exp)) if exp >= 0 => u32::try_from(exp)
+            .ok()
+            .and_then(|exp| base.checked_pow(exp))
+            .map(|val| Some(FyfthVariant::Int(val)))
+            .ok_or_else(|| {
+                FyfthError::runtime(format!(
+                    "{base} to the power of {exp} does not fit in an int"
+                ))
+            }),
+        _ => match (base.as_num(), exp.as_num()) {
+            (Some(base), Some(exp)) => Ok(Some(FyfthVariant::Num(base.powf(exp)))),
+            _ => Err(FyfthError::type_mismatch("num num", args)),
+        },
+    }
+}
+
+/// `val: num|vec2|vec3`
+fn fyfth_func_abs(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match *val {
+        FyfthVariant::Int(val) => val
+            .checked_abs()
+            .map(|val| Some(FyfthVariant::Int(val)))
+            .ok_or_else(|| FyfthError::runtime(format!("the abs of {val} does not fit in an int"))),
+        FyfthVariant::Num(val) => Ok(Some(FyfthVariant::Num(val.abs()))),
+        FyfthVariant::Vec2(val) => Ok(Some(FyfthVariant::Vec2(val.abs()))),
+        FyfthVariant::Vec3(val) => Ok(Some(FyfthVariant::Vec3(val.abs()))),
+        _ => Err(FyfthError::type_mismatch("num, vec2 or vec3", args)),
+    }
+}
+
+/// `lhs: num|vec2|vec3, rhs: num|vec2|vec3`
+fn fyfth_func_min(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (lhs, rhs) {
+        (
+            FyfthVariant::Int(_) | FyfthVariant::Num(_),
+            FyfthVariant::Int(_) | FyfthVariant::Num(_),
+        ) => combine_nums(lhs, rhs, |lhs, rhs| Some(lhs.min(rhs)), f32::min).map(Some),
+        (&FyfthVariant::Vec2(lhs), &FyfthVariant::Vec2(rhs)) => {
+            Ok(Some(FyfthVariant::Vec2(lhs.min(rhs))))
+        }
+        (&FyfthVariant::Vec3(lhs), &FyfthVariant::Vec3(rhs)) => {
+            Ok(Some(FyfthVariant::Vec3(lhs.min(rhs))))
+        }
+        _ => Err(FyfthError::type_mismatch(
+            "num num, vec2 vec2 or vec3 vec3",
+            args,
+        )),
+    }
+}
+
+/// `lhs: num|vec2|vec3, rhs: num|vec2|vec3`
+fn fyfth_func_max(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (lhs, rhs) {
+        (
+            FyfthVariant::Int(_) | FyfthVariant::Num(_),
+            FyfthVariant::Int(_) | FyfthVariant::Num(_),
+        ) => combine_nums(lhs, rhs, |lhs, rhs| Some(lhs.max(rhs)), f32::max).map(Some),
+        (&FyfthVariant::Vec2(lhs), &FyfthVariant::Vec2(rhs)) => {
+            Ok(Some(FyfthVariant::Vec2(lhs.max(rhs))))
+        }
+        (&FyfthVariant::Vec3(lhs), &FyfthVariant::Vec3(rhs)) => {
+            Ok(Some(FyfthVariant::Vec3(lhs.max(rhs))))
+        }
+        _ => Err(FyfthError::type_mismatch(
+            "num num, vec2 vec2 or vec3 vec3",
+            args,
+        )),
+    }
+}
+
+/// `val: num|vec2|vec3`
+fn fyfth_func_floor(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    round_components(args, f32::floor)
+}
+
+/// `val: num|vec2|vec3`
+fn fyfth_func_ceil(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    round_components(args, f32::ceil)
+}
+
+/// `val: num|vec2|vec3`
+fn fyfth_func_round(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    round_components(args, f32::round)
+}
+
+/// `val: num|vec2|vec3, min: num|vec2|vec3, max: num|vec2|vec3`
+fn fyfth_func_clamp(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val, min, max] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    // written as `max` then `min` rather than with `clamp`, which panics if `min > max`
+    match (val, min, max) {
+        (&FyfthVariant::Int(val), &FyfthVariant::Int(min), &FyfthVariant::Int(max)) => {
+            Ok(Some(FyfthVariant::Int(val.max(min).min(max))))
+        }
+        _ => match (&int_to_num(val), &int_to_num(min), &int_to_num(max)) {
+            (&FyfthVariant::Num(val), &FyfthVariant::Num(min), &FyfthVariant::Num(max)) => {
+                Ok(Some(FyfthVariant::Num(val.max(min).min(max))))
+            }
+            (&FyfthVariant::Vec2(val), &FyfthVariant::Num(min), &FyfthVariant::Num(max)) => {
+                Ok(Some(FyfthVariant::Vec2(
+                    val.max(Vec2::splat(min)).min(Vec2::splat(max)),
+                )))
+            }
+            (&FyfthVariant::Vec2(val), &FyfthVariant::Vec2(min), &FyfthVariant::Vec2(max)) => {
+                Ok(Some(FyfthVariant::Vec2(val.max(min).min(max))))
+            }
+            (&FyfthVariant::Vec3(val), &FyfthVariant::Num(min), &FyfthVariant::Num(max)) => {
+                Ok(Some(FyfthVariant::Vec3(
+                    val.max(Vec3::splat(min)).min(Vec3::splat(max)),
+                )))
+            }
+            (&FyfthVariant::Vec3(val), &FyfthVariant::Vec3(min), &FyfthVariant::Vec3(max)) => {
+                Ok(Some(FyfthVariant::Vec3(val.max(min).min(max))))
+            }
+            _ => Err(FyfthError::type_mismatch(
+                "num num num, vec2 num|vec2 num|vec2 or vec3 num|vec3 num|vec3",
+                args,
+            )),
+        },
     }
 }
 
-/// `cond: bool` `then: X`, `else: Y`
-fn fyfth_func_select(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
-    let [lhs, mhs, rhs] = args else {
+/// `from: num|vec2|vec3, to: num|vec2|vec3, t: num`
+fn fyfth_func_lerp(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [from, to, t] = args else {
         panic!("received the wrong number of arguments")
     };
-    match (lhs, mhs, rhs) {
-        (&FyfthVariant::Bool(cond), then_val, else_val) => {
-            if cond {
-                Ok(Some(then_val.clone()))
-            } else {
-                Ok(Some(else_val.clone()))
-            }
+    match (&int_to_num(from), &int_to_num(to), t.as_num()) {
+        (&FyfthVariant::Num(from), &FyfthVariant::Num(to), Some(t)) => {
+            Ok(Some(FyfthVariant::Num(from + (to - from) * t)))
         }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `select` needs to operate on `bool X Y`."
-            )
-            .unwrap();
-            Err(())
+        (&FyfthVariant::Vec2(from), &FyfthVariant::Vec2(to), Some(t)) => {
+            Ok(Some(FyfthVariant::Vec2(from.lerp(to, t))))
         }
+        (&FyfthVariant::Vec3(from), &FyfthVariant::Vec3(to), Some(t)) => {
+            Ok(Some(FyfthVariant::Vec3(from.lerp(to, t))))
+        }
+        _ => Err(FyfthError::type_mismatch(
+            "num num num, vec2 vec2 num or vec3 vec3 num",
+            args,
+        )),
     }
 }
 
-/// `lhs: num`, `rhs: num`
-fn fyfth_func_mod(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+/// `lhs: vec2|vec3|quat, rhs: vec2|vec3|quat`
+fn fyfth_func_dot(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [lhs, rhs] = args else {
         panic!("received the wrong number of arguments")
     };
     match (lhs, rhs) {
-        (&FyfthVariant::Num(lhs), &FyfthVariant::Num(rhs)) => Ok(Some(FyfthVariant::Num(
-            ((lhs as i32) % (rhs as i32)) as f32,
-        ))),
-        (_, FyfthVariant::Num(_)) => Ok(Some(FyfthVariant::Nil)),
-        _ => {
-            write!(
-                    ctx.output,
-                    "Syntax error: the operation `mod` needs to operate on `X num`. If X is not num it will return nil."
-                )
-                .unwrap();
-            Err(())
+        (&FyfthVariant::Vec2(lhs), &FyfthVariant::Vec2(rhs)) => {
+            Ok(Some(FyfthVariant::Num(lhs.dot(rhs))))
+        }
+        (&FyfthVariant::Vec3(lhs), &FyfthVariant::Vec3(rhs)) => {
+            Ok(Some(FyfthVariant::Num(lhs.dot(rhs))))
+        }
+        (&FyfthVariant::Quat(lhs), &FyfthVariant::Quat(rhs)) => {
+            Ok(Some(FyfthVariant::Num(lhs.dot(rhs))))
         }
+        _ => Err(FyfthError::type_mismatch(
+            "vec2 vec2, vec3 vec3 or quat quat",
+            args,
+        )),
     }
 }
 
-/// `lhs: num`, `rhs: num`
-fn fyfth_func_vec2(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+/// `lhs: vec3, rhs: vec3`
+fn fyfth_func_cross(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [lhs, rhs] = args else {
         panic!("received the wrong number of arguments")
     };
     match (lhs, rhs) {
-        (&FyfthVariant::Num(x), &FyfthVariant::Num(y)) => {
-            Ok(Some(FyfthVariant::Vec2(Vec2::new(x, y))))
-        }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `vec2` needs to operate on `num num`."
-            )
-            .unwrap();
-            Err(())
+        (&FyfthVariant::Vec3(lhs), &FyfthVariant::Vec3(rhs)) => {
+            Ok(Some(FyfthVariant::Vec3(lhs.cross(rhs))))
         }
+        _ => Err(FyfthError::type_mismatch("vec3 vec3", args)),
     }
 }
 
-/// `num num num`
-fn fyfth_func_vec3(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
-    let [lhs, mhs, rhs] = args else {
+/// `val: vec2|vec3`
+fn fyfth_func_length(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
         panic!("received the wrong number of arguments")
     };
-    match (lhs, mhs, rhs) {
-        (&FyfthVariant::Num(x), &FyfthVariant::Num(y), &FyfthVariant::Num(z)) => {
-            Ok(Some(FyfthVariant::Vec3(Vec3::new(x, y, z))))
-        }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `vec3` needs to operate on `num num num`."
-            )
-            .unwrap();
-            Err(())
-        }
+    match *val {
+        FyfthVariant::Vec2(val) => Ok(Some(FyfthVariant::Num(val.length()))),
+        FyfthVariant::Vec3(val) => Ok(Some(FyfthVariant::Num(val.length()))),
+        _ => Err(FyfthError::type_mismatch("vec2 or vec3", args)),
     }
 }
 
-/// `num num num num`
-fn fyfth_func_quat(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
-    let [x, y, z, w] = args else {
+/// `val: vec2|vec3`
+fn fyfth_func_normalize(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
         panic!("received the wrong number of arguments")
     };
-    match (x, y, z, w) {
-        (
-            &FyfthVariant::Num(x),
-            &FyfthVariant::Num(y),
-            &FyfthVariant::Num(z),
-            &FyfthVariant::Num(w),
-        ) => Ok(Some(FyfthVariant::Quat(
-            Quat::from_xyzw(x, y, z, w).normalize(),
-        ))),
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `quat` needs to operate on `num num num num`."
-            )
-            .unwrap();
-            Err(())
-        }
+    // the zero vector has no direction, so it stays the zero vector
+    match *val {
+        FyfthVariant::Vec2(val) => Ok(Some(FyfthVariant::Vec2(val.normalize_or_zero()))),
+        FyfthVariant::Vec3(val) => Ok(Some(FyfthVariant::Vec3(val.normalize_or_zero()))),
+        _ => Err(FyfthError::type_mismatch("vec2 or vec3", args)),
     }
 }
 
-/// `lhs: literal`, `rhs: literal`
-fn fyfth_func_fuzzy(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+/// `lhs: vec2|vec3, rhs: vec2|vec3`
+fn fyfth_func_distance(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [lhs, rhs] = args else {
         panic!("received the wrong number of arguments")
     };
     match (lhs, rhs) {
-        (FyfthVariant::Literal(lhs), FyfthVariant::Literal(rhs)) => {
-            Ok(Some(FyfthVariant::Bool(util::fuzzy_match(&lhs, &rhs))))
+        (&FyfthVariant::Vec2(lhs), &FyfthVariant::Vec2(rhs)) => {
+            Ok(Some(FyfthVariant::Num(lhs.distance(rhs))))
         }
-        (_, FyfthVariant::Literal(_)) => Ok(Some(FyfthVariant::Bool(false))),
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `fuzzy` needs to operate on `X literal`."
-            )
-            .unwrap();
-            Err(())
+        (&FyfthVariant::Vec3(lhs), &FyfthVariant::Vec3(rhs)) => {
+            Ok(Some(FyfthVariant::Num(lhs.distance(rhs))))
         }
+        _ => Err(FyfthError::type_mismatch("vec2 vec2 or vec3 vec3", args)),
     }
 }
 
-/// `lhs: literal`, `rhs: literal`
-#[cfg(feature = "regex")]
-fn fyfth_func_regex(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
-    let [lhs, rhs] = args else {
+/// `from: quat, to: quat, t: num`
+fn fyfth_func_slerp(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [from, to, t] = args else {
         panic!("received the wrong number of arguments")
     };
-    match (lhs, rhs) {
-        (FyfthVariant::Literal(lhs), FyfthVariant::Literal(rhs)) => {
-            match Regex::new(&rhs) {
-                Ok(re) => {
-                    if re.is_match(&lhs) {
-                        // add the captures as variables
-                        for capture_name in re.capture_names().filter_map(|n| n) {
-                            let captures: Vec<_> = re
-                                .captures_iter(&lhs)
-                                .filter_map(|c| c.name(capture_name))
-                                .map(|m| m.as_str())
-                                .collect();
-
-                            match captures.len() {
-                                // don't do anything
-                                0 => {}
-                                // put it in a var as a literal
-                                1 => {
-                                    ctx.vars.insert(
-                                        capture_name.to_string(),
-                                        FyfthVariant::Literal(captures[0].to_string()),
-                                    );
-                                }
-                                // put an iterator of all of them into the var
-                                _ => {
-                                    ctx.vars.insert(
-                                        capture_name.to_string(),
-                                        FyfthVariant::Iter(
-                                            captures
-                                                .iter()
-                                                .map(|&c| FyfthVariant::Literal(c.to_string()))
-                                                .collect(),
-                                        ),
-                                    );
-                                }
-                            }
-                        }
-                        Ok(Some(FyfthVariant::Bool(true)))
-                    } else {
-                        Ok(Some(FyfthVariant::Bool(false)))
-                    }
-                }
-                Err(err) => {
-                    write!(ctx.output, "Error: failed to parse regex: {err}").unwrap();
-                    Err(())
-                }
-            }
-            // Ok(Some(FyfthVariant::Bool(matcher.fuzzy_match(&lhs, &rhs).is_some())))
-        }
-        (_, FyfthVariant::Literal(_)) => Ok(Some(FyfthVariant::Bool(false))),
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `regex` needs to operate on `X literal`."
-            )
-            .unwrap();
-            Err(())
+    match (from, to, t.as_num()) {
+        (&FyfthVariant::Quat(from), &FyfthVariant::Quat(to), Some(t)) => {
+            Ok(Some(FyfthVariant::Quat(from.slerp(to, t))))
         }
+        _ => Err(FyfthError::type_mismatch("quat quat num", args)),
     }
 }
 
-/// `val: num`
-fn fyfth_func_sin(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+/// `val: quat`
+fn fyfth_func_inverse(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [val] = args else {
         panic!("received the wrong number of arguments")
     };
-    match val {
-        &FyfthVariant::Num(val) => Ok(Some(FyfthVariant::Num(val.sin()))),
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `sin` needs to operate on `num`."
-            )
-            .unwrap();
-            Err(())
-        }
+    match *val {
+        FyfthVariant::Quat(val) => Ok(Some(FyfthVariant::Quat(val.inverse()))),
+        _ => Err(FyfthError::type_mismatch("quat", args)),
     }
 }
 
-/// `val: num`
-fn fyfth_func_cos(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
-    let [val] = args else {
+/// `val: vec2|vec3, rot: num|quat`
+fn fyfth_func_rotate(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val, rot] = args else {
         panic!("received the wrong number of arguments")
     };
-    match val {
-        &FyfthVariant::Num(val) => Ok(Some(FyfthVariant::Num(val.cos()))),
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `cos` needs to operate on `num`."
-            )
-            .unwrap();
-            Err(())
+    match (val, &int_to_num(rot)) {
+        (&FyfthVariant::Vec2(val), &FyfthVariant::Num(angle)) => Ok(Some(FyfthVariant::Vec2(
+            Vec2::from_angle(angle).rotate(val),
+        ))),
+        (&FyfthVariant::Vec3(val), &FyfthVariant::Quat(rot)) => {
+            Ok(Some(FyfthVariant::Vec3(rot * val)))
         }
+        _ => Err(FyfthError::type_mismatch("vec2 num or vec3 quat", args)),
     }
 }
 
-/// `val: num`
-fn fyfth_func_tan(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
-    let [val] = args else {
+/// `angles: vec3, order: literal`
+fn fyfth_func_euler(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [angles, order] = args else {
         panic!("received the wrong number of arguments")
     };
-    match val {
-        &FyfthVariant::Num(val) => Ok(Some(FyfthVariant::Num(val.tan()))),
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `tan` needs to operate on `num`."
-            )
-            .unwrap();
-            Err(())
+    match (angles, order) {
+        (&FyfthVariant::Vec3(angles), FyfthVariant::Literal(order)) => {
+            let (rot, axes) = euler_order(order)?;
+            let [a, b, c] = axes.map(|axis| angles[axis]);
+            Ok(Some(FyfthVariant::Quat(Quat::from_euler(rot, a, b, c))))
         }
+        _ => Err(FyfthError::type_mismatch("vec3 literal", args)),
     }
 }
 
-/// `val: num`
-fn fyfth_func_atan(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
-    let [val] = args else {
+/// `rot: quat, order: literal`
+fn fyfth_func_to_euler(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [rot, order] = args else {
         panic!("received the wrong number of arguments")
     };
-    match val {
-        &FyfthVariant::Num(val) => Ok(Some(FyfthVariant::Num(val.atan()))),
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `atan` needs to operate on `num`."
-            )
-            .unwrap();
-            Err(())
+    match (rot, order) {
+        (&FyfthVariant::Quat(quat), FyfthVariant::Literal(order)) => {
+            let (rot, axes) = euler_order(order)?;
+            let (a, b, c) = quat.to_euler(rot);
+            let mut angles = Vec3::ZERO;
+            for (axis, angle) in axes.into_iter().zip([a, b, c]) {
+                angles[axis] = angle;
+            }
+            Ok(Some(FyfthVariant::Vec3(angles)))
         }
+        _ => Err(FyfthError::type_mismatch("quat literal", args)),
     }
 }
 
-/// `lhs: num, rhs: num`
-fn fyfth_func_atan2(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
-    let [lhs, rhs] = args else {
+/// `axis: vec3, angle: num`
+fn fyfth_func_axis_angle(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [axis, angle] = args else {
         panic!("received the wrong number of arguments")
     };
-    match (lhs, rhs) {
-        (&FyfthVariant::Num(lhs), &FyfthVariant::Num(rhs)) => {
-            Ok(Some(FyfthVariant::Num(lhs.atan2(rhs))))
-        }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `atan` needs to operate on `num num`."
-            )
-            .unwrap();
-            Err(())
+    match (axis, angle.as_num()) {
+        (&FyfthVariant::Vec3(axis), Some(angle)) => match axis.try_normalize() {
+            Some(axis) => Ok(Some(FyfthVariant::Quat(Quat::from_axis_angle(axis, angle)))),
+            None => Err(FyfthError::runtime(format!(
+                "cannot rotate around the axis {axis}, which has no direction"
+            ))),
+        },
+        _ => Err(FyfthError::type_mismatch("vec3 num", args)),
+    }
+}
+
+/// `dir: vec3, up: vec3`
+fn fyfth_func_look_to(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [dir, up] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (dir, up) {
+        (&FyfthVariant::Vec3(dir_vec), &FyfthVariant::Vec3(up_vec)) => {
+            let (Ok(dir), Ok(up)) = (Dir3::new(dir_vec), Dir3::new(up_vec)) else {
+                return Err(FyfthError::runtime(format!(
+                    "cannot look to {dir_vec} with {up_vec} as up, both need a direction"
+                )));
+            };
+            // if `up` is parallel to `dir`, some other up direction is picked
+            let rotation = Transform::IDENTITY.looking_to(dir, up).rotation;
+            Ok(Some(FyfthVariant::Quat(rotation)))
         }
+        _ => Err(FyfthError::type_mismatch("vec3 vec3", args)),
     }
 }
 
 /// `lhs: num, rhs: num`
-fn fyfth_func_mul(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_mul(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [lhs, rhs] = args else {
         panic!("received the wrong number of arguments")
     };
-    match (lhs, rhs) {
-        (&FyfthVariant::Num(lhs), &FyfthVariant::Num(rhs)) => {
-            Ok(Some(FyfthVariant::Num(lhs * rhs)))
-        }
+    if let (
+        FyfthVariant::Int(_) | FyfthVariant::Num(_),
+        FyfthVariant::Int(_) | FyfthVariant::Num(_),
+    ) = (lhs, rhs)
+    {
+        return combine_nums(lhs, rhs, i64::checked_mul, |lhs, rhs| lhs * rhs).map(Some);
+    }
+    // vectors are scaled by ints just like by nums
+    match (&int_to_num(lhs), &int_to_num(rhs)) {
         (&FyfthVariant::Vec2(lhs), &FyfthVariant::Num(rhs)) => {
             Ok(Some(FyfthVariant::Vec2(lhs * rhs)))
         }
@@ -1431,27 +2645,26 @@ fn fyfth_func_mul(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<Fyf
         (&FyfthVariant::Quat(lhs), &FyfthVariant::Quat(rhs)) => {
             Ok(Some(FyfthVariant::Quat(lhs * rhs)))
         }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `mul` cannot work on types `"
-            )
-            .unwrap();
-            lhs.pretty_print_type(ctx.output);
-            ctx.output.push_str(" ");
-            rhs.pretty_print_type(ctx.output);
-            ctx.output.push_str("`.");
-            Err(())
-        }
+        _ => Err(FyfthError::type_mismatch(
+            "num num, vec2|vec3 num, num vec2|vec3, vec2 vec2, vec3 vec3 or quat quat",
+            args,
+        )),
     }
 }
 
 /// `lhs: num, rhs: num`
-fn fyfth_func_div(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<FyfthVariant>, ()> {
+fn fyfth_func_div(
+    _ctx: FyfthContext,
+    args: &[FyfthVariant],
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [lhs, rhs] = args else {
         panic!("received the wrong number of arguments")
     };
-    match (lhs, rhs) {
+    if let (FyfthVariant::Int(_), FyfthVariant::Int(0)) = (lhs, rhs) {
+        return Err(FyfthError::runtime("cannot divide an int by 0"));
+    }
+    // dividing ints gives a num, just like dividing nums
+    match (&int_to_num(lhs), &int_to_num(rhs)) {
         (&FyfthVariant::Num(lhs), &FyfthVariant::Num(rhs)) => {
             Ok(Some(FyfthVariant::Num(lhs / rhs)))
         }
@@ -1467,18 +2680,10 @@ fn fyfth_func_div(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<Fyf
         (&FyfthVariant::Vec3(lhs), &FyfthVariant::Vec3(rhs)) => {
             Ok(Some(FyfthVariant::Vec3(lhs / rhs)))
         }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `div` cannot work on types `"
-            )
-            .unwrap();
-            lhs.pretty_print_type(ctx.output);
-            ctx.output.push_str(" ");
-            rhs.pretty_print_type(ctx.output);
-            ctx.output.push_str("`.");
-            Err(())
-        }
+        _ => Err(FyfthError::type_mismatch(
+            "num num, vec2|vec3 num, vec2 vec2 or vec3 vec3",
+            args,
+        )),
     }
 }
 
@@ -1486,7 +2691,7 @@ fn fyfth_func_div(ctx: FyfthContext, args: &[FyfthVariant]) -> Result<Option<Fyf
 fn fyfth_func_component(
     ctx: FyfthContext,
     args: &[FyfthVariant],
-) -> Result<Option<FyfthVariant>, ()> {
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [val] = args else {
         panic!("received the wrong number of arguments")
     };
@@ -1506,83 +2711,288 @@ fn fyfth_func_component(
                     let comp = handler.from_world(ctx.world);
                     Ok(Some(FyfthVariant::Component(comp)))
                 }
-                Err(error) => match error {
-                    BevyComponentRegistryError::NoMatchingComponent => {
-                        write!(
-                            ctx.output,
-                            "Error: no component type path matches '{component_name}'. Make sure the component is registered using `app.register_shell_component::<T>()`.",
-                        )
-                        .unwrap();
-                        Err(())
-                    }
-                    BevyComponentRegistryError::MultipleMatchingComponents(vec) => {
-                        let num = vec.len();
-                        write!(
-                            ctx.output,
-                            "Error: {num} component type paths match '{component_name}':",
-                        )
-                        .unwrap();
-
-                        for index in vec {
-                            writeln!(
-                                ctx.output,
-                                "    {}",
-                                &registry.registered_components[index].full_path,
-                            )
-                            .unwrap();
-                        }
+                Err(error) => Err(component_lookup_error(registry, component_name, error)),
+            }
+        }
+        _ => Err(FyfthError::type_mismatch("literal", args)),
+    }
+}
 
-                        Err(())
-                    }
-                },
+fn component_lookup_error(
+    registry: &BevyComponentRegistry,
+    component_name: &str,
+    error: BevyComponentRegistryError,
+) -> FyfthError {
+    match error {
+        BevyComponentRegistryError::NoMatchingComponent => {
+            FyfthComponentError::NoMatchingComponent(component_name.to_string()).into()
+        }
+        BevyComponentRegistryError::MultipleMatchingComponents(vec) => {
+            FyfthComponentError::MultipleMatchingComponents {
+                query: component_name.to_string(),
+                candidates: vec
+                    .into_iter()
+                    .map(|index| registry.registered_components[index].full_path.clone())
+                    .collect(),
+            }
+            .into()
+        }
+    }
+}
+
+/// Combines two numbers, which stays an `int` if both are ints and becomes a `num` otherwise.
+fn combine_nums(
+    lhs: &FyfthVariant,
+    rhs: &FyfthVariant,
+    int_op: fn(i64, i64) -> Option<i64>,
+    num_op: fn(f32, f32) -> f32,
+) -> Result<FyfthVariant, FyfthError> {
+    match (lhs, rhs) {
+        (&FyfthVariant::Int(lhs), &FyfthVariant::Int(rhs)) => {
+            int_op(lhs, rhs).map(FyfthVariant::Int).ok_or_else(|| {
+                FyfthError::runtime(format!(
+                    "the result of {lhs} and {rhs} does not fit in an int"
+                ))
+            })
+        }
+        _ => match (lhs.as_num(), rhs.as_num()) {
+            (Some(lhs), Some(rhs)) => Ok(FyfthVariant::Num(num_op(lhs, rhs))),
+            _ => Err(FyfthError::type_mismatch(
+                "num num",
+                &[lhs.clone(), rhs.clone()],
+            )),
+        },
+    }
+}
+
+fn int_to_num(val: &FyfthVariant) -> FyfthVariant {
+    match *val {
+        FyfthVariant::Int(val) => FyfthVariant::Num(val as f32),
+        _ => val.clone(),
+    }
+}
+
+/// Rounds a num or every component of a vector using `f`, where a rounded num becomes an `int`
+/// and an `int` stays as it is.
+fn round_components(
+    args: &[FyfthVariant],
+    f: fn(f32) -> f32,
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match *val {
+        FyfthVariant::Int(val) => Ok(Some(FyfthVariant::Int(val))),
+        FyfthVariant::Num(val) => {
+            let rounded = f(val);
+            // the bounds are powers of two, so they are exact as f32s
+            if rounded.is_finite() && (i64::MIN as f32..i64::MAX as f32).contains(&rounded) {
+                Ok(Some(FyfthVariant::Int(rounded as i64)))
+            } else {
+                Err(FyfthError::runtime(format!(
+                    "{val} rounded does not fit in an int"
+                )))
             }
         }
+        FyfthVariant::Vec2(val) => Ok(Some(FyfthVariant::Vec2(Vec2::from_array(
+            val.to_array().map(f),
+        )))),
+        FyfthVariant::Vec3(val) => Ok(Some(FyfthVariant::Vec3(Vec3::from_array(
+            val.to_array().map(f),
+        )))),
+        _ => Err(FyfthError::type_mismatch("num, vec2 or vec3", args)),
+    }
+}
+
+/// Parses an Euler rotation order like `"XYZ"`, along with the axis of each angle in the order.
+fn euler_order(order: &str) -> Result<(EulerRot, [usize; 3]), FyfthError> {
+    let rot = match order.to_ascii_uppercase().as_str() {
+        "XYZ" => EulerRot::XYZ,
+        "XZY" => EulerRot::XZY,
+        "YXZ" => EulerRot::YXZ,
+        "YZX" => EulerRot::YZX,
+        "ZXY" => EulerRot::ZXY,
+        "ZYX" => EulerRot::ZYX,
         _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `component` cannot work on type `"
-            )
-            .unwrap();
-            val.pretty_print_type(ctx.output);
-            ctx.output.push_str("`.");
-            Err(())
+            return Err(FyfthError::runtime(format!(
+                "unknown Euler rotation order \"{order}\", expected one of XYZ, XZY, YXZ, YZX, \
+                 ZXY or ZYX"
+            )))
+        }
+    };
+    let mut axes = order.bytes().map(|axis| match axis.to_ascii_uppercase() {
+        b'X' => 0,
+        b'Y' => 1,
+        _ => 2,
+    });
+    Ok((rot, std::array::from_fn(|_| axes.next().unwrap())))
+}
+
+/// Runs `f` on a single literal argument. `nil` stays `nil`, so that e.g. the names of unnamed
+/// entities do not stop a broadcast.
+fn map_literal(
+    args: &[FyfthVariant],
+    f: impl FnOnce(&str) -> FyfthVariant,
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match val {
+        FyfthVariant::Literal(val) => Ok(Some(f(val))),
+        FyfthVariant::Nil => Ok(Some(FyfthVariant::Nil)),
+        _ => Err(FyfthError::type_mismatch("literal", args)),
+    }
+}
+
+/// Checks a literal against a pattern using `f`, where anything but a literal never matches, just
+/// like with `fuzzy`.
+fn match_literals(
+    args: &[FyfthVariant],
+    f: impl FnOnce(&str, &str) -> bool,
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [val, pattern] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match (val, pattern) {
+        (FyfthVariant::Literal(val), FyfthVariant::Literal(pattern)) => {
+            Ok(Some(FyfthVariant::Bool(f(val, pattern))))
+        }
+        (_, FyfthVariant::Literal(_)) => Ok(Some(FyfthVariant::Bool(false))),
+        _ => Err(FyfthError::type_mismatch("X literal", args)),
+    }
+}
+
+/// Resolves an index into a list of length `len`, where negative indices count from the end.
+fn list_index(index: &FyfthVariant, len: usize) -> Result<usize, FyfthError> {
+    let Some(index) = index.as_index() else {
+        return Err(FyfthError::type_mismatch(
+            "int",
+            std::slice::from_ref(index),
+        ));
+    };
+    let resolved = if index >= 0 {
+        index
+    } else {
+        len as i64 + index
+    };
+
+    if 0 <= resolved && resolved < len as i64 {
+        Ok(resolved as usize)
+    } else {
+        Err(FyfthErrorKind::IndexOutOfRange {
+            index: resolved,
+            len,
+        }
+        .into())
+    }
+}
+
+/// Whether two values are `eq`, where ints and nums are equal if they are the same number.
+pub(crate) fn values_eq(lhs: &FyfthVariant, rhs: &FyfthVariant) -> bool {
+    match (lhs, rhs) {
+        (&FyfthVariant::Int(int), &FyfthVariant::Num(num))
+        | (&FyfthVariant::Num(num), &FyfthVariant::Int(int)) => int as f64 == num as f64,
+        _ => lhs == rhs,
+    }
+}
+
+/// The pairs of types `compare_values` orders, as shown in type mismatches.
+const COMPARABLE_TYPES: &str = "num num, literal literal, bool bool or Entity Entity";
+
+/// Orders two numbers, two literals, two bools or two entities, and gives `None` for anything
+/// else.
+pub(crate) fn compare_values(lhs: &FyfthVariant, rhs: &FyfthVariant) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (FyfthVariant::Int(lhs), FyfthVariant::Int(rhs)) => Some(lhs.cmp(rhs)),
+        (FyfthVariant::Literal(lhs), FyfthVariant::Literal(rhs)) => Some(lhs.cmp(rhs)),
+        (FyfthVariant::Bool(lhs), FyfthVariant::Bool(rhs)) => Some(lhs.cmp(rhs)),
+        (FyfthVariant::Entity(lhs), FyfthVariant::Entity(rhs)) => Some(lhs.cmp(rhs)),
+        _ => {
+            // compared as f64, which holds every num and far larger ints than an f32
+            let as_f64 = |val: &FyfthVariant| match *val {
+                FyfthVariant::Int(val) => Some(val as f64),
+                FyfthVariant::Num(val) => Some(val as f64),
+                _ => None,
+            };
+            as_f64(lhs)?.partial_cmp(&as_f64(rhs)?)
+        }
+    }
+}
+
+/// Compares two values with `compare_values` and checks the result with `f`. Numbers that are not
+/// ordered, like `NaN`, compare as `false`.
+fn compare_args(
+    args: &[FyfthVariant],
+    f: fn(Ordering) -> bool,
+) -> Result<Option<FyfthVariant>, FyfthError> {
+    let [lhs, rhs] = args else {
+        panic!("received the wrong number of arguments")
+    };
+    match compare_values(lhs, rhs) {
+        Some(ord) => Ok(Some(FyfthVariant::Bool(f(ord)))),
+        None if lhs.as_num().is_some() && rhs.as_num().is_some() => {
+            Ok(Some(FyfthVariant::Bool(false)))
+        }
+        None => Err(FyfthError::type_mismatch(COMPARABLE_TYPES, args)),
+    }
+}
+
+/// Sorts `vals` by the matching `keys`, keeping values with equal keys in their order.
+pub(crate) fn sort_by_keys(
+    vals: &[FyfthVariant],
+    keys: &[FyfthVariant],
+) -> Result<Vec<FyfthVariant>, FyfthError> {
+    // checked up front, as sorting by an order that is not total may panic
+    if let Some(first) = keys.first() {
+        let incomparable = keys.iter().find(|key| compare_values(first, key).is_none());
+        if let Some(key) = incomparable {
+            return Err(FyfthError::type_mismatch(
+                COMPARABLE_TYPES,
+                &[first.clone(), key.clone()],
+            ));
         }
     }
+
+    let mut order: Vec<_> = (0..vals.len()).collect();
+    order.sort_by(|&lhs, &rhs| compare_values(&keys[lhs], &keys[rhs]).unwrap());
+    Ok(order.into_iter().map(|index| vals[index].clone()).collect())
 }
 
 //--------------------------------------------------
 // Prefix Implementations
 //--------------------------------------------------
 
-fn fyfth_prefix_load(word: &str, lang: &FyfthLanguageExtension) -> Result<Vec<FyfthVariant>, ()> {
-    Ok(vec![
-        FyfthVariant::Literal(word.to_string()),
-        FyfthVariant::LangFunc(lang.get_command_id("load").ok_or(())?),
-    ])
+fn command_id(lang: &FyfthLanguageExtension, keyword: &str) -> Result<u32, FyfthError> {
+    lang.get_command_id(keyword)
+        .ok_or_else(|| FyfthErrorKind::UnknownKeyword(keyword.to_string()).into())
 }
 
-fn fyfth_prefix_queue_macro(
+fn fyfth_prefix_load(
     word: &str,
     lang: &FyfthLanguageExtension,
-) -> Result<Vec<FyfthVariant>, ()> {
+) -> Result<Vec<FyfthVariant>, FyfthError> {
     Ok(vec![
         FyfthVariant::Literal(word.to_string()),
-        FyfthVariant::LangFunc(lang.get_command_id("load").ok_or(())?),
-        FyfthVariant::FnQueue,
+        FyfthVariant::LangFunc(command_id(lang, "load")?),
     ])
 }
 
+fn fyfth_prefix_queue_macro(
+    word: &str,
+    _lang: &FyfthLanguageExtension,
+) -> Result<Vec<FyfthVariant>, FyfthError> {
+    Ok(vec![FyfthVariant::MacroCall(word.to_string())])
+}
+
 fn fyfth_prefix_fuzzy_entity(
     word: &str,
     lang: &FyfthLanguageExtension,
-) -> Result<Vec<FyfthVariant>, ()> {
+) -> Result<Vec<FyfthVariant>, FyfthError> {
     use FyfthVariant::*;
     Ok(vec![
         Literal(word.to_string()),
-        Literal("fuzzent".to_string()),
-        LangFunc(lang.get_command_id("load").ok_or(())?),
-        FnQueue,
-        Num(0.0),
-        LangFunc(lang.get_command_id("index").ok_or(())?),
+        MacroCall("fuzzent".to_string()),
+        Int(0),
+        LangFunc(command_id(lang, "index")?),
     ])
 }
@@ -0,0 +1,365 @@
+use std::{fmt::Write, sync::Arc};
+
+use bevy::prelude::*;
+
+use crate::{
+    error::FyfthError,
+    interpreter::{FyfthContext, FyfthVariant},
+    language::FyfthLanguageExtension,
+    FyfthIgnoreEntity,
+};
+
+/// The maximum number of values of a range.
+pub const RANGE_LIMIT: usize = 1_000_000;
+
+/// The maximum number of values lazy iterators may produce while running a single command, so
+/// that one step of the interpreter cannot run for arbitrarily long.
+pub const LAZY_STEP_LIMIT: usize = 10 * RANGE_LIMIT;
+
+/// An iterator whose values are only produced once it is consumed, e.g. by broadcasting a command
+/// over it, and that is collected into a list when a command needs all values at once.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FyfthLazyIter {
+    /// The numbers from `start` up to (but excluding) `end`, `step` apart.
+    Range { start: f32, end: f32, step: f32 },
+    /// The ints from `start` up to (but excluding) `end`, `step` apart.
+    IntRange { start: i64, end: i64, step: i64 },
+    /// All entities, queried when the iterator is consumed.
+    Entities,
+    /// The values of the iter or lazy iter `source` with `code` run on each of them.
+    Map {
+        source: FyfthVariant,
+        code: Arc<Vec<FyfthVariant>>,
+    },
+    /// The values of the iter or lazy iter `source` for which `code` leaves `true`.
+    Filter {
+        source: FyfthVariant,
+        code: Arc<Vec<FyfthVariant>>,
+    },
+}
+
+impl FyfthLazyIter {
+    pub fn range(start: f32, end: f32, step: f32) -> Result<Self, FyfthError> {
+        if step == 0.0 || !(start.is_finite() && end.is_finite() && step.is_finite()) {
+            return Err(FyfthError::runtime(format!(
+                "{start} {end} {step} is not a valid `range`"
+            )));
+        }
+        if range_len(start, end, step) > RANGE_LIMIT as f32 {
+            return Err(too_long(start, end, step));
+        }
+
+        Ok(Self::Range { start, end, step })
+    }
+
+    pub fn int_range(start: i64, end: i64, step: i64) -> Result<Self, FyfthError> {
+        if step == 0 {
+            return Err(FyfthError::runtime(format!(
+                "{start} {end} {step} is not a valid `range`"
+            )));
+        }
+        if int_range_len(start, end, step) > RANGE_LIMIT as i128 {
+            return Err(too_long(start, end, step));
+        }
+
+        Ok(Self::IntRange { start, end, step })
+    }
+
+    /// Creates a map or filter view, checking that `code` only contains values and commands.
+    pub(crate) fn view(
+        source: &FyfthVariant,
+        code: &Arc<Vec<FyfthVariant>>,
+        filter: bool,
+    ) -> Result<Self, FyfthError> {
+        if let Some(val) = code.iter().find(|val| !is_view_code(val)) {
+            return Err(FyfthError::runtime(format!(
+                "lazy views can only run values and commands, found a `{}`",
+                val.type_name()
+            )));
+        }
+
+        let (source, code) = (source.clone(), code.clone());
+        Ok(if filter {
+            Self::Filter { source, code }
+        } else {
+            Self::Map { source, code }
+        })
+    }
+
+    /// Produces all values of the iterator.
+    pub(crate) fn collect(&self, ctx: &mut FyfthContext) -> Result<Vec<FyfthVariant>, FyfthError> {
+        let mut cursor = FyfthLazyCursor::new_lazy(self, ctx.world);
+        // ranges built by hand rather than by `range` may still be arbitrarily long
+        let mut vals = Vec::with_capacity(cursor.len().unwrap_or(0).min(RANGE_LIMIT));
+        while let Some(val) = cursor.next(ctx)? {
+            vals.push(val);
+        }
+        Ok(vals)
+    }
+
+    pub(crate) fn pretty_print(
+        &self,
+        output: &mut String,
+        world: &World,
+        lang: &FyfthLanguageExtension,
+    ) {
+        match self {
+            FyfthLazyIter::Range { start, end, step } => {
+                write!(output, "range({start} {end} {step})").unwrap()
+            }
+            FyfthLazyIter::IntRange { start, end, step } => {
+                write!(output, "range({start} {end} {step})").unwrap()
+            }
+            FyfthLazyIter::Entities => write!(output, "entities()").unwrap(),
+            FyfthLazyIter::Map { source, code } | FyfthLazyIter::Filter { source, code } => {
+                let name = match self {
+                    FyfthLazyIter::Map { .. } => "map",
+                    _ => "filter",
+                };
+                write!(output, "{name}(").unwrap();
+                source.pretty_print(output, world, lang);
+                write!(output, ", ").unwrap();
+                FyfthVariant::Iter(code.clone()).pretty_print(output, world, lang);
+                write!(output, ")").unwrap();
+            }
+        }
+    }
+}
+
+/// The number of values of a float range, which may not be finite.
+fn range_len(start: f32, end: f32, step: f32) -> f32 {
+    ((end - start) / step).ceil().max(0.0)
+}
+
+/// The number of values of an int range, computed in i128 so that ranges over the whole `int`
+/// range cannot overflow.
+fn int_range_len(start: i64, end: i64, step: i64) -> i128 {
+    let (span, step) = (end as i128 - start as i128, step as i128);
+    if span != 0 && (span > 0) == (step > 0) {
+        (span.abs() + step.abs() - 1) / step.abs()
+    } else {
+        0
+    }
+}
+
+fn too_long(
+    start: impl std::fmt::Display,
+    end: impl std::fmt::Display,
+    step: impl std::fmt::Display,
+) -> FyfthError {
+    FyfthError::runtime(format!(
+        "{start} {end} {step} is not a valid `range`, ranges can have at most {RANGE_LIMIT} values"
+    ))
+}
+
+/// Whether `val` may be part of the code of a map or filter view, which is run without the
+/// interpreter and so cannot contain macros or control flow.
+fn is_view_code(val: &FyfthVariant) -> bool {
+    matches!(
+        val,
+        FyfthVariant::Nil
+            | FyfthVariant::Bool(_)
+            | FyfthVariant::Num(_)
+            | FyfthVariant::Int(_)
+            | FyfthVariant::Literal(_)
+            | FyfthVariant::Iter(_)
+            | FyfthVariant::Lazy(_)
+            | FyfthVariant::Map(_)
+            | FyfthVariant::Entity(_)
+            | FyfthVariant::Vec2(_)
+            | FyfthVariant::Vec3(_)
+            | FyfthVariant::Quat(_)
+            | FyfthVariant::Component(_)
+            | FyfthVariant::LangFunc(_)
+    )
+}
+
+/// Steps through the values of an iter or lazy iter one at a time.
+pub(crate) enum FyfthLazyCursor {
+    Values {
+        vals: Arc<Vec<FyfthVariant>>,
+        index: usize,
+    },
+    Range {
+        start: f32,
+        step: f32,
+        index: usize,
+        len: usize,
+    },
+    IntRange {
+        start: i64,
+        step: i64,
+        index: usize,
+        len: usize,
+    },
+    Map {
+        source: Box<FyfthLazyCursor>,
+        code: Arc<Vec<FyfthVariant>>,
+    },
+    Filter {
+        source: Box<FyfthLazyCursor>,
+        code: Arc<Vec<FyfthVariant>>,
+    },
+}
+
+impl FyfthLazyCursor {
+    /// Creates a cursor over `val` if it is an iter or a lazy iter.
+    pub(crate) fn new(val: &FyfthVariant, world: &mut World) -> Option<Self> {
+        match val {
+            FyfthVariant::Iter(vals) => Some(Self::Values {
+                vals: vals.clone(),
+                index: 0,
+            }),
+            FyfthVariant::Lazy(lazy) => Some(Self::new_lazy(lazy, world)),
+            _ => None,
+        }
+    }
+
+    fn new_lazy(lazy: &FyfthLazyIter, world: &mut World) -> Self {
+        match lazy {
+            &FyfthLazyIter::Range { start, end, step } => Self::Range {
+                start,
+                step,
+                index: 0,
+                len: range_len(start, end, step) as usize,
+            },
+            &FyfthLazyIter::IntRange { start, end, step } => Self::IntRange {
+                start,
+                step,
+                index: 0,
+                len: usize::try_from(int_range_len(start, end, step)).unwrap_or(usize::MAX),
+            },
+            FyfthLazyIter::Entities => {
+                let mut query = world.query_filtered::<Entity, Without<FyfthIgnoreEntity>>();
+                let vals = query.iter(world).map(FyfthVariant::Entity).collect();
+                Self::Values {
+                    vals: Arc::new(vals),
+                    index: 0,
+                }
+            }
+            FyfthLazyIter::Map { source, code } => Self::Map {
+                source: Box::new(Self::source(source, world)),
+                code: code.clone(),
+            },
+            FyfthLazyIter::Filter { source, code } => Self::Filter {
+                source: Box::new(Self::source(source, world)),
+                code: code.clone(),
+            },
+        }
+    }
+
+    fn source(source: &FyfthVariant, world: &mut World) -> Self {
+        Self::new(source, world).expect("the source of a lazy view is always an iter")
+    }
+
+    /// The number of values left, if it is known without producing them.
+    pub(crate) fn len(&self) -> Option<usize> {
+        match self {
+            Self::Values { vals, index } => Some(vals.len() - index),
+            Self::Range { index, len, .. } | Self::IntRange { index, len, .. } => Some(len - index),
+            Self::Map { source, .. } => source.len(),
+            Self::Filter { .. } => None,
+        }
+    }
+
+    pub(crate) fn next(
+        &mut self,
+        ctx: &mut FyfthContext,
+    ) -> Result<Option<FyfthVariant>, FyfthError> {
+        // plain lists are by far the most common, so they are kept out of the recursion below
+        match self {
+            Self::Values { vals, index } => {
+                let val = vals.get(*index).cloned();
+                *index += 1;
+                Ok(val)
+            }
+            _ => self.next_lazy(ctx),
+        }
+    }
+
+    fn next_lazy(&mut self, ctx: &mut FyfthContext) -> Result<Option<FyfthVariant>, FyfthError> {
+        if !matches!(self, Self::Values { .. }) {
+            ctx.consume_lazy_value()?;
+        }
+
+        match self {
+            Self::Values { .. } => self.next(ctx),
+            Self::Range {
+                start,
+                step,
+                index,
+                len,
+            } => {
+                if *index >= *len {
+                    return Ok(None);
+                }
+                let val = *start + *index as f32 * *step;
+                *index += 1;
+                Ok(Some(FyfthVariant::Num(val)))
+            }
+            Self::IntRange {
+                start,
+                step,
+                index,
+                len,
+            } => {
+                if *index >= *len {
+                    return Ok(None);
+                }
+                // the value lies between start and end, so wrapping around gives the right result
+                // even where `index * step` alone would not fit in an int
+                let val = start.wrapping_add((*index as i64).wrapping_mul(*step));
+                *index += 1;
+                Ok(Some(FyfthVariant::Int(val)))
+            }
+            Self::Map { source, code } => match source.next(ctx)? {
+                Some(val) => run_view_code(ctx, code, val).map(Some),
+                None => Ok(None),
+            },
+            Self::Filter { source, code } => {
+                while let Some(val) = source.next(ctx)? {
+                    match run_view_code(ctx, code, val.clone())? {
+                        FyfthVariant::Bool(true) => return Ok(Some(val)),
+                        FyfthVariant::Bool(false) => {}
+                        res => {
+                            return Err(FyfthError::type_mismatch("bool", &[res])
+                                .with_keyword("lazy_filter"))
+                        }
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Runs the code of a map or filter view on `val`, returning the single value it leaves.
+fn run_view_code(
+    ctx: &mut FyfthContext,
+    code: &[FyfthVariant],
+    val: FyfthVariant,
+) -> Result<FyfthVariant, FyfthError> {
+    let mut stack = vec![val];
+    for val in code {
+        match val {
+            &FyfthVariant::LangFunc(index) => {
+                let lang = ctx.lang;
+                FyfthVariant::try_call_func(
+                    ctx.reborrow(),
+                    &lang.functions[index as usize],
+                    &mut stack,
+                )?
+            }
+            val => stack.push(val.clone()),
+        }
+    }
+
+    match <[_; 1]>::try_from(stack) {
+        Ok([val]) => Ok(val),
+        Err(stack) => Err(FyfthError::runtime(format!(
+            "the code of a lazy view needs to leave exactly one value, but left {}",
+            stack.len()
+        ))
+        .with_stack(&stack)),
+    }
+}
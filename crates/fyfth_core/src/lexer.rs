@@ -1,21 +1,35 @@
-use std::{str::Lines, sync::Arc};
+use std::{fmt, str::Lines, sync::Arc};
 
-use crate::language::FyfthLanguageExtension;
+use crate::{error::FyfthError, language::FyfthLanguageExtension};
+
+/// The file name used for code that does not come from a file, e.g. terminal input.
+pub const FYFTH_INPUT_FILE_NAME: &str = "<input>";
 
 #[derive(Debug)]
 pub struct FyfthLexer<'a> {
     current_line: Option<&'a str>,
+    current_line_text: Arc<str>,
+    line_number: usize,
     line_byte_index: usize,
     lines: Lines<'a>,
+    file_name: Arc<str>,
     lang: Arc<FyfthLanguageExtension>,
 }
 
 impl<'a> FyfthLexer<'a> {
     pub fn iter(code: &'a str, lang: Arc<FyfthLanguageExtension>) -> Self {
+        Self::iter_file(code, FYFTH_INPUT_FILE_NAME, lang)
+    }
+
+    /// Like [`FyfthLexer::iter`], but the spans of the lexed words refer to `file_name`.
+    pub fn iter_file(code: &'a str, file_name: &str, lang: Arc<FyfthLanguageExtension>) -> Self {
         Self {
             current_line: None,
+            current_line_text: Arc::from(""),
+            line_number: 0,
             line_byte_index: 0,
             lines: code.lines(),
+            file_name: Arc::from(file_name),
             lang,
         }
     }
@@ -29,6 +43,59 @@ impl<'a> FyfthLexer<'a> {
 
         None
     }
+
+    /// Builds the span of the bytes `start..end` of the current line.
+    fn span(&self, start: usize, end: usize) -> FyfthSpan {
+        let line = &self.current_line_text;
+        FyfthSpan {
+            file_name: self.file_name.clone(),
+            line: self.line_number,
+            column: line[..start].chars().count() + 1,
+            len: line[start..end].chars().count(),
+            source_line: line.clone(),
+        }
+    }
+}
+
+/// The location of a word in the source code it was lexed from.
+///
+/// Lines and columns are 1-based and columns as well as the length are counted in characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FyfthSpan {
+    pub file_name: Arc<str>,
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+    /// The full line of source code the span points into.
+    pub source_line: Arc<str>,
+}
+
+impl FyfthSpan {
+    /// Writes the source line of this span with the spanned word underlined by carets.
+    pub fn write_excerpt(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        let line_number = self.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+
+        // keep tabs so that the carets line up with the source line
+        let padding: String = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        let carets = "^".repeat(self.len.max(1));
+
+        writeln!(f, "{gutter}--> {self}")?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{line_number} | {}", self.source_line.trim_end())?;
+        write!(f, "{gutter} | {padding}{carets}")
+    }
+}
+
+impl fmt::Display for FyfthSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file_name, self.line, self.column)
+    }
 }
 
 #[derive(Debug)]
@@ -36,6 +103,14 @@ pub struct FyfthWord {
     pub(crate) word: String,
     pub(crate) maybe_prefix: Option<u32>,
     pub(crate) in_quotes: bool,
+    pub(crate) span: FyfthSpan,
+}
+
+impl FyfthWord {
+    /// Whether this word is exactly `word` without quotes or a prefix.
+    pub(crate) fn is_bare(&self, word: &str) -> bool {
+        !self.in_quotes && self.maybe_prefix.is_none() && self.word == word
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -50,7 +125,7 @@ enum LexingState {
 }
 
 impl<'a> Iterator for FyfthLexer<'a> {
-    type Item = Result<FyfthWord, ()>;
+    type Item = Result<FyfthWord, FyfthError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         while self.current_line.is_none()
@@ -59,6 +134,8 @@ impl<'a> Iterator for FyfthLexer<'a> {
                 .is_empty()
         {
             self.current_line = Some(self.lines.next()?);
+            self.current_line_text = Arc::from(self.current_line.unwrap());
+            self.line_number += 1;
             self.line_byte_index = 0;
 
             // if the line is empty (after removing comments), try to get the next line
@@ -80,18 +157,45 @@ impl<'a> Iterator for FyfthLexer<'a> {
 
         let mut state = LexingState::Base;
 
+        // the start of the word including its prefix and opening quote
+        let mut word_start_index = 0;
         let mut start_index = 0;
         let mut end_index = 0;
         let mut escaped = false;
 
         let mut maybe_prefix = None;
 
+        // the length of the word starting at `index` if it is a bracket or brace, which are words
+        // of their own even without surrounding whitespace
+        let delimiter_len = |index: usize| {
+            let rest = &current_line[index..];
+            if rest.starts_with("{:") || rest.starts_with(":}") {
+                // local variable declarations are not split up like braces are
+                2
+            } else if rest.starts_with(['[', ']', '{', '}']) {
+                1
+            } else {
+                0
+            }
+        };
+
         use LexingState::*;
         for (index, ch) in current_line.char_indices() {
             match (state, ch) {
-                (Base, '"') => state = QuoteStart,
+                (Base, '"') => {
+                    word_start_index = index;
+                    state = QuoteStart;
+                }
                 (Base, _) if ch.is_whitespace() => {}
+                (Base, _) if delimiter_len(index) > 0 => {
+                    word_start_index = index;
+                    start_index = index;
+                    end_index = index + delimiter_len(index);
+                    state = FinishedWord;
+                    break;
+                }
                 (Base, _) => {
+                    word_start_index = index;
                     if let Some(prefix) = self.get_prefix(ch) {
                         state = Prefixed;
                         maybe_prefix = Some(prefix);
@@ -102,20 +206,24 @@ impl<'a> Iterator for FyfthLexer<'a> {
                 }
                 (Prefixed, '"') => state = QuoteStart,
                 (Prefixed, _) if ch.is_whitespace() => {}
+                (Prefixed, _) if delimiter_len(index) > 0 => {
+                    end_index = index;
+                    break;
+                }
                 (Prefixed, _) => {
                     start_index = index;
                     state = Word;
                 }
-                (Word, _) if ch.is_whitespace() => {
+                (Word, _) if ch.is_whitespace() || delimiter_len(index) > 0 => {
                     end_index = index;
                     state = FinishedWord;
                     break;
                 }
                 (Word, _) => {}
-                // empty string
+                // empty string, the closing quote is skipped below
                 (QuoteStart, '"') => {
                     start_index = index;
-                    end_index = index + 1;
+                    end_index = index;
                     state = FinishedQuotedWord;
                     break;
                 }
@@ -136,15 +244,34 @@ impl<'a> Iterator for FyfthLexer<'a> {
             }
         }
 
+        let line_offset = self.line_byte_index;
+
         // Finish off unfinished words
         match state {
             Base => panic!("It should not be possible to end up in the base state"),
-            Prefixed => return Some(Err(())),
+            Prefixed => {
+                // skip the dangling prefix so the lexer can continue after the error
+                if end_index == 0 {
+                    end_index = current_line.len();
+                }
+                self.line_byte_index += end_index;
+                let span = self.span(line_offset + word_start_index, self.line_byte_index);
+                return Some(Err(FyfthError::syntax(
+                    "a prefix needs to be followed by a word",
+                )
+                .with_span(&span)));
+            }
             Word => {
                 end_index = current_line.len();
                 state = FinishedWord;
             }
-            QuoteStart | QuotedWord => {
+            QuoteStart => {
+                // a lone quote at the end of the line is an empty word
+                start_index = current_line.len();
+                end_index = current_line.len();
+                state = FinishedQuotedWord;
+            }
+            QuotedWord => {
                 end_index = current_line.len();
                 state = FinishedQuotedWord;
             }
@@ -157,11 +284,14 @@ impl<'a> Iterator for FyfthLexer<'a> {
 
         self.line_byte_index += end_index;
 
+        let span = self.span(line_offset + word_start_index, self.line_byte_index);
+
         match state {
             FinishedWord => Some(Ok(FyfthWord {
                 word: current_line[start_index..end_index].to_string(),
                 maybe_prefix,
                 in_quotes: false,
+                span,
             })),
             FinishedQuotedWord => {
                 let mut processed_word = String::with_capacity(end_index - start_index);
@@ -179,10 +309,12 @@ impl<'a> Iterator for FyfthLexer<'a> {
                             't' => processed_word.push('\t'),
                             'r' => processed_word.push('\r'),
                             '\\' => processed_word.push('\\'),
-                            _ => eprintln!(
-                                "Illegal escape code `\\{ch}` in {}",
-                                self.current_line.unwrap()
-                            ),
+                            _ => {
+                                return Some(Err(FyfthError::syntax(format!(
+                                    "illegal escape code `\\{ch}`"
+                                ))
+                                .with_span(&span)))
+                            }
                         }
                         escaped = false;
                     }
@@ -191,6 +323,7 @@ impl<'a> Iterator for FyfthLexer<'a> {
                     word: processed_word,
                     maybe_prefix,
                     in_quotes: true,
+                    span,
                 }))
             }
             _ => unreachable!(),
@@ -202,7 +335,7 @@ impl<'a> Iterator for FyfthLexer<'a> {
 mod tests {
     use std::sync::Arc;
 
-    use crate::{interpreter::FyfthVariant, language::FyfthLanguageExtension};
+    use crate::{error::FyfthError, interpreter::FyfthVariant, language::FyfthLanguageExtension};
 
     use super::FyfthLexer;
 
@@ -291,18 +424,88 @@ bar                                                  # and this is the second
         assert_eq!(&commands, expected);
     }
 
+    #[test]
+    fn test_lexer_spans() {
+        let input = "foo  \"bar baz\"\n\n  *qux # comment";
+        let lang = FyfthLanguageExtension::base_fyfth();
+        let lexer = FyfthLexer::iter_file(input, "test.fy", Arc::new(lang));
+        let spans: Vec<_> = lexer
+            .map(|fw| {
+                let span = fw.unwrap().span;
+                (span.line, span.column, span.len)
+            })
+            .collect();
+        let expected = &[(1, 1, 3), (1, 6, 9), (3, 3, 4)];
+
+        assert_eq!(&spans, expected);
+    }
+
+    #[test]
+    fn test_lexer_brackets() {
+        let input = r#"[1 [foo] "[bar]"]x"#;
+        let lang = FyfthLanguageExtension::base_fyfth();
+        let lexer = FyfthLexer::iter(input, Arc::new(lang));
+        let commands: Vec<_> = lexer.map(|fw| fw.unwrap().word).collect();
+        let expected = &["[", "1", "[", "foo", "]", "[bar]", "]", "x"];
+
+        assert_eq!(&commands, expected);
+    }
+
+    #[test]
+    fn test_lexer_empty_quotes() {
+        let input = "\"\" 1 \"\"\nfoo \"\"\n\"";
+        let lang = FyfthLanguageExtension::base_fyfth();
+        let words: Vec<_> = FyfthLexer::iter(input, Arc::new(lang))
+            .map(|fw| fw.unwrap())
+            .collect();
+        let commands: Vec<_> = words.iter().map(|fw| fw.word.as_str()).collect();
+        assert_eq!(&commands, &["", "1", "", "foo", "", ""]);
+        assert!(words
+            .iter()
+            .all(|fw| fw.in_quotes != (fw.word == "1" || fw.word == "foo")));
+
+        let spans: Vec<_> = words
+            .iter()
+            .map(|fw| (fw.span.line, fw.span.column, fw.span.len))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (1, 1, 2),
+                (1, 4, 1),
+                (1, 6, 2),
+                (2, 1, 3),
+                (2, 5, 2),
+                (3, 1, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_braces() {
+        let input = r#"{a 1 b{c "{d}"}}x {:e f:} g:h"#;
+        let lang = FyfthLanguageExtension::base_fyfth();
+        let lexer = FyfthLexer::iter(input, Arc::new(lang));
+        let commands: Vec<_> = lexer.map(|fw| fw.unwrap().word).collect();
+        let expected = &[
+            "{", "a", "1", "b", "{", "c", "{d}", "}", "}", "x", "{:", "e", "f", ":}", "g:h",
+        ];
+
+        assert_eq!(&commands, expected);
+    }
+
     fn debug_prefix_parser_fn(
         _word: &str,
         _lang: &FyfthLanguageExtension,
-    ) -> Result<Vec<FyfthVariant>, ()> {
+    ) -> Result<Vec<FyfthVariant>, FyfthError> {
         Ok(vec![])
     }
 
     #[test]
     fn test_lexer_prefix() {
-        let input = r#"@foo bar ^"foo bar" baz"#;
+        let input = r#"%foo bar ^"foo bar" baz"#;
         let mut lang = FyfthLanguageExtension::base_fyfth();
-        lang.with_prefix('@', debug_prefix_parser_fn);
+        lang.with_prefix('%', debug_prefix_parser_fn);
         lang.with_prefix('^', debug_prefix_parser_fn);
         let lexer: Vec<_> = FyfthLexer::iter(input, Arc::new(lang)).collect();
         let command_words: Vec<_> = lexer
@@ -314,7 +517,7 @@ bar                                                  # and this is the second
             .map(|fw| fw.as_ref().unwrap().maybe_prefix)
             .collect();
         let expected_words = &["foo", "bar", "foo bar", "baz"];
-        let expected_prefixes = &[Some(0), None, Some(1), None];
+        let expected_prefixes = &[Some(3), None, Some(4), None];
 
         assert_eq!(&command_words, expected_words);
         assert_eq!(&command_prefixes, expected_prefixes);
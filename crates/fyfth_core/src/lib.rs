@@ -1,11 +1,20 @@
+use asset::{FyfthPreludes, FyfthScript, FyfthScriptLoader};
 use bevy::prelude::*;
 use bevy_component::{BevyComponentRegistry, FyfthRegisterBevyComponent};
 use interpreter::FyfthInterpreter;
 
+pub mod asset;
 pub mod bevy_component;
+mod code;
+pub mod error;
+mod format;
+pub mod higher_order;
 pub mod interpreter;
 pub mod language;
+pub mod lazy;
 pub mod lexer;
+pub mod stack_words;
+pub mod trace;
 pub mod util;
 
 #[derive(Component)]
@@ -14,6 +23,17 @@ pub struct FyfthIgnoreEntity;
 #[derive(Debug, Default)]
 pub struct FyfthPlugin {
     preludes: Vec<String>,
+    prelude_error_behavior: FyfthPreludeErrorBehavior,
+}
+
+/// What [`FyfthPlugin`] does when one of its preludes fails to load.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FyfthPreludeErrorBehavior {
+    /// Panic with the errors of the prelude.
+    #[default]
+    Panic,
+    /// Log the errors of the prelude and keep running without the rest of it.
+    Log,
 }
 
 impl FyfthPlugin {
@@ -21,9 +41,11 @@ impl FyfthPlugin {
         Self::default()
     }
 
+    /// Creates the plugin with the preludes at the given asset paths, e.g. `fyfth/prelude.fy`.
     pub fn new_from_prelude_paths(paths: &[&str]) -> Self {
         Self {
             preludes: paths.iter().map(|&p| p.to_string()).collect(),
+            ..default()
         }
     }
 
@@ -31,23 +53,30 @@ impl FyfthPlugin {
         self.preludes.push(path.to_string());
         self
     }
+
+    pub fn with_prelude_error_behavior(
+        &mut self,
+        behavior: FyfthPreludeErrorBehavior,
+    ) -> &mut Self {
+        self.prelude_error_behavior = behavior;
+        self
+    }
 }
 
 impl Plugin for FyfthPlugin {
     fn build(&self, app: &mut App) {
-        let world = app.world_mut();
-        let mut interpreter = FyfthInterpreter::new();
-
-        // Parse all provided preludes into the interpreter
-        for path in self.preludes.iter() {
-            // TODO: Don't panic when we can't find the file?
-            let prelude = std::fs::read_to_string(path).unwrap();
-            interpreter.parse_code(&prelude);
-            let (_, res) = interpreter.run(world);
-            res.unwrap();
-        }
+        // Preludes are loaded as assets and run once all of them have been loaded
+        app.init_asset::<FyfthScript>()
+            .init_asset_loader::<FyfthScriptLoader>()
+            .insert_resource(FyfthPreludes::new(
+                self.preludes.clone(),
+                self.prelude_error_behavior,
+            ))
+            .add_systems(Startup, asset::load_preludes)
+            .add_systems(Update, asset::update_scripts);
 
-        world.insert_resource(interpreter);
+        let world = app.world_mut();
+        world.insert_resource(FyfthInterpreter::new());
 
         // Create the registry
         world.init_resource::<BevyComponentRegistry>();
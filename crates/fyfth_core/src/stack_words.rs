@@ -0,0 +1,174 @@
+use bevy::utils::HashMap;
+
+use crate::{error::FyfthError, interpreter::FyfthVariant};
+
+/// A built-in word from Forth that rearranges the values on the stack, or moves them between the
+/// stack and the return stack or the saved stacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FyfthStackWord {
+    /// `a b over` -> `a b a`
+    Over,
+    /// `a b nip` -> `b`
+    Nip,
+    /// `a b tuck` -> `b a b`
+    Tuck,
+    /// `.. n pick` copies the value `n` places below the top onto the top, so `0 pick` is `dup`.
+    Pick,
+    /// `.. n roll` moves the value `n` places below the top onto the top, so `1 roll` is `swap`.
+    Roll,
+    /// `a b 2dup` -> `a b a b`
+    TwoDup,
+    /// `a b c d 2swap` -> `c d a b`
+    TwoSwap,
+    /// `a b 2drop` -> ` `
+    TwoDrop,
+    /// Pushes the number of values on the stack.
+    Depth,
+    /// Removes all values from the stack.
+    Clear,
+    /// `a >r` moves `a` onto the return stack.
+    ToReturn,
+    /// `r>` moves the top value of the return stack back onto the stack.
+    FromReturn,
+    /// `r@` copies the top value of the return stack onto the stack.
+    CopyReturn,
+    /// `.. name save_stack` moves all values below `name` into the saved stack `name`, replacing
+    /// the values saved under that name before.
+    SaveStack,
+    /// `name restore_stack` moves the values of the saved stack `name` back onto the stack.
+    RestoreStack,
+}
+
+/// The stacks besides the data stack that [`FyfthStackWord`]s move values to and from.
+///
+/// Both keep their values from one run to the next, just like the data stack. The return stack
+/// is cleared when a module fails to run, since nothing is left to take the values back off it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FyfthSideStacks {
+    /// The return stack of `>r`, `r>` and `r@`.
+    pub(crate) ret: Vec<FyfthVariant>,
+    /// The stacks saved with `save_stack` by their names.
+    pub(crate) saved: HashMap<String, Vec<FyfthVariant>>,
+}
+
+impl FyfthStackWord {
+    pub fn keyword(self) -> &'static str {
+        match self {
+            FyfthStackWord::Over => "over",
+            FyfthStackWord::Nip => "nip",
+            FyfthStackWord::Tuck => "tuck",
+            FyfthStackWord::Pick => "pick",
+            FyfthStackWord::Roll => "roll",
+            FyfthStackWord::TwoDup => "2dup",
+            FyfthStackWord::TwoSwap => "2swap",
+            FyfthStackWord::TwoDrop => "2drop",
+            FyfthStackWord::Depth => "depth",
+            FyfthStackWord::Clear => "clear",
+            FyfthStackWord::ToReturn => ">r",
+            FyfthStackWord::FromReturn => "r>",
+            FyfthStackWord::CopyReturn => "r@",
+            FyfthStackWord::SaveStack => "save_stack",
+            FyfthStackWord::RestoreStack => "restore_stack",
+        }
+    }
+
+    /// Runs the word on `stack`, which is left untouched if the word fails.
+    pub(crate) fn run(
+        self,
+        stack: &mut Vec<FyfthVariant>,
+        side: &mut FyfthSideStacks,
+    ) -> Result<(), FyfthError> {
+        let len = stack.len();
+        let arity = match self {
+            FyfthStackWord::Over | FyfthStackWord::Nip | FyfthStackWord::Tuck => 2,
+            FyfthStackWord::TwoDup | FyfthStackWord::TwoDrop => 2,
+            FyfthStackWord::TwoSwap => 4,
+            // the index on top and the value it points to
+            FyfthStackWord::Pick | FyfthStackWord::Roll => {
+                match stack.last().map(FyfthVariant::as_index) {
+                    Some(Some(index)) if index >= 0 => (index as usize).saturating_add(2),
+                    Some(Some(index)) => {
+                        return Err(self.error(FyfthError::runtime(format!(
+                            "cannot {} the value {index} places below the top of the stack",
+                            self.keyword()
+                        ))))
+                    }
+                    Some(None) => {
+                        return Err(self.error(FyfthError::type_mismatch("int", &stack[len - 1..])))
+                    }
+                    None => 1,
+                }
+            }
+            FyfthStackWord::ToReturn => 1,
+            FyfthStackWord::SaveStack | FyfthStackWord::RestoreStack => match stack.last() {
+                Some(FyfthVariant::Literal(_)) | None => 1,
+                Some(_) => {
+                    return Err(self.error(FyfthError::type_mismatch("literal", &stack[len - 1..])))
+                }
+            },
+            FyfthStackWord::Depth
+            | FyfthStackWord::Clear
+            | FyfthStackWord::FromReturn
+            | FyfthStackWord::CopyReturn => 0,
+        };
+        if len < arity {
+            return Err(self.error(FyfthError::stack_underflow(arity, len)));
+        }
+
+        match self {
+            FyfthStackWord::Over => stack.push(stack[len - 2].clone()),
+            FyfthStackWord::Nip => {
+                stack.remove(len - 2);
+            }
+            FyfthStackWord::Tuck => stack.insert(len - 2, stack[len - 1].clone()),
+            FyfthStackWord::Pick => {
+                stack.pop();
+                stack.push(stack[len - arity].clone());
+            }
+            FyfthStackWord::Roll => {
+                stack.pop();
+                let val = stack.remove(len - arity);
+                stack.push(val);
+            }
+            FyfthStackWord::TwoDup => stack.extend_from_within(len - 2..),
+            FyfthStackWord::TwoSwap => stack[len - 4..].rotate_left(2),
+            FyfthStackWord::TwoDrop => stack.truncate(len - 2),
+            FyfthStackWord::Depth => stack.push(FyfthVariant::Int(len as i64)),
+            FyfthStackWord::Clear => stack.clear(),
+            FyfthStackWord::ToReturn => side.ret.extend(stack.pop()),
+            FyfthStackWord::FromReturn | FyfthStackWord::CopyReturn => {
+                let Some(val) = side.ret.last() else {
+                    return Err(self.error(FyfthError::runtime("the return stack is empty")));
+                };
+                if self == FyfthStackWord::FromReturn {
+                    stack.extend(side.ret.pop());
+                } else {
+                    stack.push(val.clone());
+                }
+            }
+            FyfthStackWord::SaveStack => {
+                let Some(FyfthVariant::Literal(name)) = stack.pop() else {
+                    unreachable!()
+                };
+                side.saved.insert(name, std::mem::take(stack));
+            }
+            FyfthStackWord::RestoreStack => {
+                let Some(FyfthVariant::Literal(name)) = stack.last() else {
+                    unreachable!()
+                };
+                let Some(vals) = side.saved.remove(name) else {
+                    return Err(self.error(FyfthError::runtime(format!(
+                        "there is no stack saved as \"{name}\""
+                    ))));
+                };
+                stack.pop();
+                stack.extend(vals);
+            }
+        }
+        Ok(())
+    }
+
+    fn error(self, err: FyfthError) -> FyfthError {
+        err.with_keyword(self.keyword())
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use bevy::utils::HashMap;
+
+use crate::{interpreter::FyfthVariant, lexer::FyfthSpan};
+
+/// A record of everything the interpreter ran while tracing was enabled using
+/// [`FyfthInterpreter::start_tracing`](crate::interpreter::FyfthInterpreter::start_tracing).
+#[derive(Debug, Clone, Default)]
+pub struct FyfthTrace {
+    /// Every value that was run, in order.
+    pub steps: Vec<FyfthTraceStep>,
+    /// The totals of every keyword, including built-ins like `dup` and `if`.
+    pub keywords: HashMap<String, FyfthProfileEntry>,
+    /// The totals of every macro, including the time spent in everything the macro ran.
+    pub macros: HashMap<String, FyfthProfileEntry>,
+    /// The time spent running all steps so far.
+    pub total_time: Duration,
+    /// The macros that are currently running, innermost last, with the total time at the start.
+    running_macros: Vec<(String, Duration)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FyfthTraceStep {
+    pub value: FyfthVariant,
+    pub span: Option<FyfthSpan>,
+    pub stack_depth_before: usize,
+    pub stack_depth_after: usize,
+    pub time: Duration,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FyfthProfileEntry {
+    pub calls: usize,
+    pub total_time: Duration,
+}
+
+impl FyfthProfileEntry {
+    pub fn average_time(&self) -> Duration {
+        self.total_time / self.calls.max(1) as u32
+    }
+}
+
+/// A step that has started running but has not been recorded yet.
+pub(crate) struct FyfthPendingStep {
+    value: FyfthVariant,
+    span: Option<FyfthSpan>,
+    stack_depth_before: usize,
+    keyword: Option<String>,
+    start: Instant,
+}
+
+impl FyfthPendingStep {
+    pub(crate) fn start(
+        value: &FyfthVariant,
+        span: Option<&FyfthSpan>,
+        stack_depth: usize,
+        keyword: Option<&str>,
+    ) -> Self {
+        Self {
+            value: value.clone(),
+            span: span.cloned(),
+            stack_depth_before: stack_depth,
+            keyword: keyword.map(str::to_string),
+            start: Instant::now(),
+        }
+    }
+}
+
+impl FyfthTrace {
+    pub(crate) fn finish_step(&mut self, step: FyfthPendingStep, stack_depth: usize) {
+        let time = step.start.elapsed();
+        self.total_time += time;
+
+        if let Some(keyword) = step.keyword {
+            let entry = self.keywords.entry(keyword).or_default();
+            entry.calls += 1;
+            entry.total_time += time;
+        }
+
+        self.steps.push(FyfthTraceStep {
+            value: step.value,
+            span: step.span,
+            stack_depth_before: step.stack_depth_before,
+            stack_depth_after: stack_depth,
+            time,
+        });
+    }
+
+    pub(crate) fn enter_macro(&mut self, name: &str) {
+        self.macros.entry(name.to_string()).or_default().calls += 1;
+        self.running_macros
+            .push((name.to_string(), self.total_time));
+    }
+
+    pub(crate) fn exit_macro(&mut self) {
+        if let Some((name, start)) = self.running_macros.pop() {
+            self.macros.entry(name).or_default().total_time += self.total_time - start;
+        }
+    }
+
+    /// Exits all running macros after a run failed, since their frames are never finished.
+    pub(crate) fn abort_macros(&mut self) {
+        while !self.running_macros.is_empty() {
+            self.exit_macro();
+        }
+    }
+
+    /// Pretty prints the totals of all keywords and macros as a table, slowest first.
+    pub fn pretty_print_table(&self) -> String {
+        let mut rows: Vec<_> = self
+            .keywords
+            .iter()
+            .map(|(name, entry)| (name, "keyword", entry))
+            .chain(
+                self.macros
+                    .iter()
+                    .map(|(name, entry)| (name, "macro", entry)),
+            )
+            .collect();
+        rows.sort_by(|a, b| b.2.total_time.cmp(&a.2.total_time).then(a.0.cmp(b.0)));
+
+        let width = rows
+            .iter()
+            .map(|(name, _, _)| name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("name".len());
+
+        let mut buffer = String::new();
+        writeln!(
+            &mut buffer,
+            "{} steps in {:.3?}",
+            self.steps.len(),
+            self.total_time
+        )
+        .unwrap();
+        write!(
+            &mut buffer,
+            "{:<width$}  {:<7}  {:>8}  {:>12}  {:>12}",
+            "name", "kind", "calls", "total", "average"
+        )
+        .unwrap();
+        for (name, kind, entry) in rows {
+            write!(
+                &mut buffer,
+                "\n{:<width$}  {:<7}  {:>8}  {:>12}  {:>12}",
+                name,
+                kind,
+                entry.calls,
+                format!("{:.3?}", entry.total_time),
+                format!("{:.3?}", entry.average_time()),
+            )
+            .unwrap();
+        }
+
+        buffer
+    }
+}
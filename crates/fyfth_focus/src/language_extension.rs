@@ -1,12 +1,15 @@
-use std::fmt::Write;
+use std::sync::Arc;
 
-use fyfth_core::interpreter::{FyfthContext, FyfthVariant};
+use fyfth_core::{
+    error::{FyfthComponentError, FyfthError},
+    interpreter::{FyfthContext, FyfthVariant},
+};
 
 /// `val: Entity`
 pub(crate) fn fyfth_func_focus(
     ctx: FyfthContext,
     args: &[FyfthVariant],
-) -> Result<Option<FyfthVariant>, ()> {
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [val] = args else {
         panic!("received the wrong number of arguments")
     };
@@ -16,18 +19,10 @@ pub(crate) fn fyfth_func_focus(
                 entity.insert(crate::FyfthFocusObject);
                 Ok(None)
             } else {
-                write!(ctx.output, "Error: entity ({entity}) no longer exists.").unwrap();
-                Err(())
+                Err(FyfthComponentError::NoSuchEntity(entity).into())
             }
         }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `atan` needs to operate on `num`."
-            )
-            .unwrap();
-            Err(())
-        }
+        _ => Err(FyfthError::type_mismatch("Entity", args)),
     }
 }
 
@@ -35,7 +30,7 @@ pub(crate) fn fyfth_func_focus(
 pub(crate) fn fyfth_func_unfocus(
     ctx: FyfthContext,
     args: &[FyfthVariant],
-) -> Result<Option<FyfthVariant>, ()> {
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [val] = args else {
         panic!("received the wrong number of arguments")
     };
@@ -45,25 +40,17 @@ pub(crate) fn fyfth_func_unfocus(
                 entity.remove::<crate::FyfthFocusObject>();
                 Ok(None)
             } else {
-                write!(ctx.output, "Error: entity ({entity}) no longer exists.").unwrap();
-                Err(())
+                Err(FyfthComponentError::NoSuchEntity(entity).into())
             }
         }
-        _ => {
-            write!(
-                ctx.output,
-                "Syntax error: the operation `atan` needs to operate on `num`."
-            )
-            .unwrap();
-            Err(())
-        }
+        _ => Err(FyfthError::type_mismatch("Entity", args)),
     }
 }
 
 pub(crate) fn fyfth_func_focused(
     ctx: FyfthContext,
     args: &[FyfthVariant],
-) -> Result<Option<FyfthVariant>, ()> {
+) -> Result<Option<FyfthVariant>, FyfthError> {
     let [] = args else {
         panic!("received the wrong number of arguments")
     };
@@ -74,5 +61,5 @@ pub(crate) fn fyfth_func_focused(
         ent_vec.push(FyfthVariant::Entity(av.0));
     }
 
-    Ok(Some(FyfthVariant::Iter(ent_vec)))
+    Ok(Some(FyfthVariant::Iter(Arc::new(ent_vec))))
 }
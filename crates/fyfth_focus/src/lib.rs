@@ -55,7 +55,9 @@ impl Plugin for FyfthFocusCameraPlugin {
 
         let mut interpreter = app.world_mut().get_resource_mut::<FyfthInterpreter>()
             .expect("Make sure to register the `FyfthPlugin` before registering the `FyfthFocusMainCameraPlugin`.");
-        interpreter.add_language_extension(language_extension);
+        interpreter
+            .add_language_extension(language_extension)
+            .expect("The focus language extension collides with another language extension.");
     }
 }
 
@@ -1,6 +1,11 @@
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
-use fyfth_core::interpreter::FyfthInterpreter;
+use fyfth_core::interpreter::{FyfthInterpreter, FyfthRunStatus};
+
+/// The number of steps a command may run per frame before it is continued on the next frame.
+const STEPS_PER_FRAME: usize = 10_000;
+/// The number of queued values shown when a command is paused.
+const DEFAULT_QUEUE_COUNT: usize = 5;
 
 pub struct FyfthTerminalPlugin;
 
@@ -8,6 +13,7 @@ impl Plugin for FyfthTerminalPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<TerminalDisplayEvent>()
             .add_event::<TerminalInteractionEvent>()
+            .init_resource::<TerminalRun>()
             .configure_sets(Update, FyfthTerminalSystemSet)
             .add_systems(
                 Update,
@@ -25,11 +31,60 @@ pub struct FyfthTerminalSystemSet;
 pub enum TerminalDisplayEvent {
     Print(String),
     SetState(String),
+    SetRunState(TerminalRunState),
 }
 
 #[derive(Debug, Clone, Event)]
 pub enum TerminalInteractionEvent {
+    /// Runs fyfth code, or a debugger command if it starts with `:`.
     Submit(String),
+    Stop,
+    Step,
+    Continue,
+    /// Prints the next `n` queued values.
+    ShowQueue(usize),
+    ShowVars,
+    AddBreakpoint(String),
+    RemoveBreakpoint(String),
+    StartTracing,
+    /// Stops tracing and prints the profile of everything that ran since tracing started.
+    StopTracing,
+}
+
+/// Whether the terminal is currently running a command.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalRunState {
+    #[default]
+    Idle,
+    Running,
+    /// The command is paused at a breakpoint or after a single step.
+    Paused,
+}
+
+/// The state of the run controls shown below the stack.
+struct TerminalRunControls {
+    state: TerminalRunState,
+    /// The number of queued values to show when pressing "Show next".
+    queue_count: usize,
+}
+
+impl Default for TerminalRunControls {
+    fn default() -> Self {
+        Self {
+            state: default(),
+            queue_count: DEFAULT_QUEUE_COUNT,
+        }
+    }
+}
+
+/// The interpreter of the commands that are still running, whose changes are only applied to the
+/// [`FyfthInterpreter`] resource once they have finished.
+#[derive(Default, Resource)]
+struct TerminalRun {
+    interpreter: Option<FyfthInterpreter>,
+    /// The resource as it was when the commands started, to tell apart what they changed.
+    base: Option<FyfthInterpreter>,
+    paused: bool,
 }
 
 fn display_terminal(
@@ -37,6 +92,7 @@ fn display_terminal(
     mut current_string: Local<String>,
     mut history: Local<Vec<String>>,
     mut state: Local<String>,
+    mut controls: Local<TerminalRunControls>,
     mut display_event_reader: EventReader<TerminalDisplayEvent>,
     mut interaction_event_writer: EventWriter<TerminalInteractionEvent>,
 ) {
@@ -48,6 +104,9 @@ fn display_terminal(
             TerminalDisplayEvent::SetState(value) => {
                 *state = value.clone();
             }
+            TerminalDisplayEvent::SetRunState(value) => {
+                controls.state = *value;
+            }
         }
     }
 
@@ -68,6 +127,41 @@ fn display_terminal(
             // state label
             ui.label("Stack:");
             ui.label(&*state);
+            // run controls
+            match controls.state {
+                TerminalRunState::Idle => {}
+                TerminalRunState::Running => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Running...");
+                        if ui.button("Stop").clicked() {
+                            interaction_event_writer.send(TerminalInteractionEvent::Stop);
+                        }
+                    });
+                }
+                TerminalRunState::Paused => {
+                    ui.horizontal(|ui| {
+                        ui.label("Paused");
+                        if ui.button("Step").clicked() {
+                            interaction_event_writer.send(TerminalInteractionEvent::Step);
+                        }
+                        if ui.button("Continue").clicked() {
+                            interaction_event_writer.send(TerminalInteractionEvent::Continue);
+                        }
+                        if ui.button("Stop").clicked() {
+                            interaction_event_writer.send(TerminalInteractionEvent::Stop);
+                        }
+                        if ui.button("Show next").clicked() {
+                            interaction_event_writer
+                                .send(TerminalInteractionEvent::ShowQueue(controls.queue_count));
+                        }
+                        ui.add(egui::DragValue::new(&mut controls.queue_count).range(1..=100));
+                        if ui.button("Variables").clicked() {
+                            interaction_event_writer.send(TerminalInteractionEvent::ShowVars);
+                        }
+                    });
+                }
+            }
             // input field
             let response = ui.text_edit_singleline(&mut *current_string);
 
@@ -83,38 +177,212 @@ fn display_terminal(
 fn shell(
     mut commands: Commands,
     mut interaction_event_reader: EventReader<TerminalInteractionEvent>,
+    run: Res<TerminalRun>,
 ) {
-    let mut submitted_commands = vec![];
-    for interaction in interaction_event_reader.read() {
-        match interaction {
-            TerminalInteractionEvent::Submit(value) => {
-                submitted_commands.push(value.clone());
-            }
-        }
+    let interactions: Vec<_> = interaction_event_reader.read().cloned().collect();
+
+    // paused commands only continue when asked to
+    if interactions.is_empty() && (run.interpreter.is_none() || run.paused) {
+        return;
     }
 
-    if !submitted_commands.is_empty() {
-        commands.add(|world: &mut World| {
-            let mut interpreter = world.resource::<FyfthInterpreter>().clone();
+    commands.add(|world: &mut World| {
+        let mut run = std::mem::take(&mut *world.resource_mut::<TerminalRun>());
+        let mut step = false;
 
-            for command in submitted_commands {
-                interpreter.parse_code(&command);
-            }
+        for interaction in interactions {
+            let interaction = match interaction {
+                TerminalInteractionEvent::Submit(command) if command.starts_with(':') => {
+                    match parse_debugger_command(&command[1..]) {
+                        Ok(interaction) => interaction,
+                        Err(err) => {
+                            world.send_event(TerminalDisplayEvent::Print(format!("Error: {err}")));
+                            continue;
+                        }
+                    }
+                }
+                interaction => interaction,
+            };
 
-            let (output, res) = interpreter.run(world);
+            match interaction {
+                TerminalInteractionEvent::Submit(command) => {
+                    // new commands are queued after the ones that are still running
+                    if run.interpreter.is_none() {
+                        let base = world.resource::<FyfthInterpreter>().clone();
+                        run.interpreter = Some(base.clone());
+                        run.base = Some(base);
+                    }
+                    let interpreter = run.interpreter.as_mut().unwrap();
 
-            if !output.is_empty() {
-                world.send_event(TerminalDisplayEvent::Print(output));
+                    if let Err(errors) = interpreter.parse_code(&command) {
+                        for err in errors {
+                            world.send_event(TerminalDisplayEvent::Print(format!("Error: {err}")));
+                        }
+                    }
+                }
+                TerminalInteractionEvent::Stop => {
+                    if run.interpreter.take().is_some() {
+                        world.send_event(TerminalDisplayEvent::Print("Stopped".to_string()));
+                    }
+                    run.base = None;
+                    run.paused = false;
+                }
+                TerminalInteractionEvent::Step => step = run.paused,
+                TerminalInteractionEvent::Continue => run.paused = false,
+                TerminalInteractionEvent::ShowQueue(count) => {
+                    let output = match &run.interpreter {
+                        Some(interpreter) => format!(
+                            "Next: {}",
+                            interpreter.pretty_print_queue(world, count, " ")
+                        ),
+                        None => "Nothing is running".to_string(),
+                    };
+                    world.send_event(TerminalDisplayEvent::Print(output));
+                }
+                TerminalInteractionEvent::ShowVars => {
+                    let output = match &run.interpreter {
+                        Some(interpreter) => interpreter.pretty_print_vars(world),
+                        None => world
+                            .resource::<FyfthInterpreter>()
+                            .pretty_print_vars(world),
+                    };
+                    world.send_event(TerminalDisplayEvent::Print(output));
+                }
+                // breakpoints are kept in both interpreters so they outlive the current command
+                TerminalInteractionEvent::AddBreakpoint(name) => {
+                    world
+                        .resource_mut::<FyfthInterpreter>()
+                        .add_breakpoint(&name);
+                    if let Some(interpreter) = &mut run.interpreter {
+                        interpreter.add_breakpoint(&name);
+                    }
+                }
+                TerminalInteractionEvent::RemoveBreakpoint(name) => {
+                    world
+                        .resource_mut::<FyfthInterpreter>()
+                        .remove_breakpoint(&name);
+                    if let Some(interpreter) = &mut run.interpreter {
+                        interpreter.remove_breakpoint(&name);
+                    }
+                }
+                TerminalInteractionEvent::StartTracing => {
+                    world.resource_mut::<FyfthInterpreter>().start_tracing();
+                    if let Some(interpreter) = &mut run.interpreter {
+                        interpreter.start_tracing();
+                    }
+                }
+                TerminalInteractionEvent::StopTracing => {
+                    // the running command has the most recent trace
+                    let mut trace = world.resource_mut::<FyfthInterpreter>().stop_tracing();
+                    if let Some(interpreter) = &mut run.interpreter {
+                        trace = interpreter.stop_tracing();
+                    }
+
+                    let output = match trace {
+                        Some(trace) => trace.pretty_print_table(),
+                        None => "Tracing is not enabled".to_string(),
+                    };
+                    world.send_event(TerminalDisplayEvent::Print(output));
+                }
             }
+        }
+
+        let Some(mut interpreter) = run.interpreter.take() else {
+            world.send_event(TerminalDisplayEvent::SetRunState(TerminalRunState::Idle));
+            return;
+        };
 
+        if run.paused && !step {
+            run.interpreter = Some(interpreter);
+            world.send_event(TerminalDisplayEvent::SetRunState(TerminalRunState::Paused));
+            *world.resource_mut::<TerminalRun>() = run;
+            return;
+        }
+
+        let (output, res) = if step {
+            interpreter.step(world)
+        } else {
+            interpreter.run_with_fuel(world, STEPS_PER_FRAME)
+        };
+
+        if !output.is_empty() {
+            world.send_event(TerminalDisplayEvent::Print(output));
+        }
+
+        world.send_event(TerminalDisplayEvent::SetState(
+            interpreter.pretty_print_stack(world, " "),
+        ));
+
+        match res {
             // if we didn't encounter an error, update the interpreters value
-            if res.is_ok() {
-                world.send_event(TerminalDisplayEvent::SetState(
-                    interpreter.pretty_print_stack(&world, " "),
-                ));
+            Ok(FyfthRunStatus::Finished) => {
+                world.send_event(TerminalDisplayEvent::SetRunState(TerminalRunState::Idle));
 
-                *world.resource_mut::<FyfthInterpreter>() = interpreter;
+                // keep what changed in the resource meanwhile, e.g. reloaded scripts
+                let base = run.base.take().unwrap_or_else(FyfthInterpreter::new);
+                world
+                    .resource_mut::<FyfthInterpreter>()
+                    .apply_run(&base, interpreter);
+                run.paused = false;
             }
-        });
+            // keep running on the next frame
+            Ok(FyfthRunStatus::Suspended) if !run.paused => {
+                world.send_event(TerminalDisplayEvent::SetRunState(TerminalRunState::Running));
+
+                run.interpreter = Some(interpreter);
+            }
+            Ok(FyfthRunStatus::Suspended | FyfthRunStatus::Paused) => {
+                let mut output = format!(
+                    "Paused before: {}",
+                    interpreter.pretty_print_queue(world, DEFAULT_QUEUE_COUNT, " ")
+                );
+                if let Some(span) = interpreter.queue().find_map(|(_, span)| span) {
+                    output.push_str(&format!("\n --> {span}"));
+                }
+                world.send_event(TerminalDisplayEvent::Print(output));
+                world.send_event(TerminalDisplayEvent::SetRunState(TerminalRunState::Paused));
+
+                run.interpreter = Some(interpreter);
+                run.paused = true;
+            }
+            Err(err) => {
+                world.send_event(TerminalDisplayEvent::Print(format!("Error: {err}")));
+
+                // the failed command is usually the one worth profiling
+                if let Some(trace) = interpreter.stop_tracing() {
+                    world
+                        .resource_mut::<FyfthInterpreter>()
+                        .continue_tracing(trace);
+                }
+                world.send_event(TerminalDisplayEvent::SetRunState(TerminalRunState::Idle));
+                run.base = None;
+                run.paused = false;
+            }
+        }
+
+        *world.resource_mut::<TerminalRun>() = run;
+    });
+}
+
+/// Parses a debugger command such as `break fib` (without the leading `:`).
+fn parse_debugger_command(command: &str) -> Result<TerminalInteractionEvent, String> {
+    let words: Vec<_> = command.split_whitespace().collect();
+    match words.as_slice() {
+        ["step"] => Ok(TerminalInteractionEvent::Step),
+        ["continue"] => Ok(TerminalInteractionEvent::Continue),
+        ["stop"] => Ok(TerminalInteractionEvent::Stop),
+        ["next"] => Ok(TerminalInteractionEvent::ShowQueue(DEFAULT_QUEUE_COUNT)),
+        ["next", count] => count
+            .parse()
+            .map(TerminalInteractionEvent::ShowQueue)
+            .map_err(|_| format!("`{count}` is not a number")),
+        ["vars"] => Ok(TerminalInteractionEvent::ShowVars),
+        ["break", name] => Ok(TerminalInteractionEvent::AddBreakpoint(name.to_string())),
+        ["unbreak", name] => Ok(TerminalInteractionEvent::RemoveBreakpoint(name.to_string())),
+        ["trace", "start"] => Ok(TerminalInteractionEvent::StartTracing),
+        ["trace", "stop"] => Ok(TerminalInteractionEvent::StopTracing),
+        _ => Err(format!(
+            "unknown debugger command `:{command}`, expected one of `:step`, `:continue`, `:stop`, `:next [n]`, `:vars`, `:break <name>`, `:unbreak <name>`, `:trace start` or `:trace stop`"
+        )),
     }
 }
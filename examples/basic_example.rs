@@ -8,7 +8,7 @@ fn main() {
         .add_plugins(EguiPlugin)
         .add_plugins(PanOrbitCameraPlugin)
         .add_plugins((
-            fyfth::core::FyfthPlugin::new_from_prelude_paths(&["assets/fyfth/prelude.fy"]),
+            fyfth::core::FyfthPlugin::new_from_prelude_paths(&["fyfth/prelude.fy"]),
             fyfth::focus::FyfthFocusCameraPlugin,
             fyfth::terminal::FyfthTerminalPlugin,
         ))